@@ -0,0 +1,81 @@
+use crate::app::books::{BookQuery, EditedBook, NewBook};
+use crate::{data::ApiUser, error::Error::*, WebResult, DB};
+use serde::{Deserialize, Serialize};
+use warp::{http::StatusCode, reject, reply, Reply};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PatchedBook {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub pages: Option<i32>,
+}
+
+pub async fn list_books_handler(user: ApiUser, db: DB) -> WebResult<impl Reply> {
+    let books = db
+        .fetch_books(&user.user_id, &BookQuery::all())
+        .await
+        .map_err(reject::custom)?;
+    Ok(reply::json(&books))
+}
+
+pub async fn get_book_handler(user: ApiUser, id: String, db: DB) -> WebResult<impl Reply> {
+    let book = db
+        .fetch_book(&id, &user.user_id)
+        .await
+        .map_err(reject::custom)?;
+    Ok(reply::json(&book))
+}
+
+pub async fn create_book_handler(user: ApiUser, body: NewBook, db: DB) -> WebResult<impl Reply> {
+    let errors = body.validate();
+    if !errors.is_empty() {
+        return Err(reject::custom(InvalidInputError(errors.to_string())));
+    }
+    let id = db
+        .create_book(&body, &user.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let book = db
+        .fetch_book(&id, &user.user_id)
+        .await
+        .map_err(reject::custom)?;
+    Ok(reply::with_status(reply::json(&book), StatusCode::CREATED))
+}
+
+pub async fn update_book_handler(
+    user: ApiUser,
+    id: String,
+    body: PatchedBook,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let book = db
+        .fetch_book(&id, &user.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let edited = EditedBook {
+        name: body.name.unwrap_or(book.name),
+        author: body.author.unwrap_or(book.author),
+        language: body.language.unwrap_or(book.language),
+        pages: body.pages.unwrap_or(book.num_pages as i32),
+    };
+    let errors = edited.validate();
+    if !errors.is_empty() {
+        return Err(reject::custom(InvalidInputError(errors.to_string())));
+    }
+    db.edit_book(&id, &user.user_id, &edited)
+        .await
+        .map_err(reject::custom)?;
+    let book = db
+        .fetch_book(&id, &user.user_id)
+        .await
+        .map_err(reject::custom)?;
+    Ok(reply::json(&book))
+}
+
+pub async fn delete_book_handler(user: ApiUser, id: String, db: DB) -> WebResult<impl Reply> {
+    db.delete_book(&id, &user.user_id)
+        .await
+        .map_err(reject::custom)?;
+    Ok(reply::with_status(reply(), StatusCode::NO_CONTENT))
+}
@@ -0,0 +1,3 @@
+//! Versioned JSON API, sharing the db layer with the HTML pages
+
+pub mod books;
@@ -0,0 +1,164 @@
+use crate::app::auth::{login_page, validate_password, verify_password};
+use crate::app::backup::{self, RestoreMode};
+use crate::app::csrf;
+use crate::app::import::attachment;
+use crate::{data::Session, error::Error::*, WebResult, DB};
+use askama::Template;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use warp::{reject, reply, reply::html, Reply};
+
+#[derive(Template)]
+#[template(path = "account.html")]
+struct AccountTemplate<'a> {
+    email: &'a str,
+    message: &'a str,
+    csrf_token: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChangePassword {
+    pub old_password: String,
+    pub new_password: String,
+    pub new_password_confirmation: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteAccount {
+    pub password: String,
+}
+
+pub async fn account_handler(session: Session, db: DB) -> WebResult<impl Reply> {
+    render_account(&session, "", &db).await
+}
+
+pub async fn change_password_handler(
+    session: Session,
+    body: ChangePassword,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let user = db
+        .fetch_user_by_id(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    verify_password(&body.old_password, &user.password)?;
+    validate_password(&body.new_password, &body.new_password_confirmation)?;
+
+    let password_hash = bcrypt::hash(&body.new_password, bcrypt::DEFAULT_COST)
+        .map_err(|e| reject::custom(PasswordHashError(e)))?;
+    db.update_password(&user.id, &password_hash)
+        .await
+        .map_err(reject::custom)?;
+    // log out everywhere else and revoke the API tokens, since the old password might have been
+    // compromised
+    db.delete_user_sessions(&user.id, Some(&session.session_id))
+        .await
+        .map_err(reject::custom)?;
+    db.delete_user_tokens(&user.id)
+        .await
+        .map_err(reject::custom)?;
+    render_account(
+        &session,
+        "Password changed, other sessions were logged out and API tokens revoked.",
+        &db,
+    )
+    .await
+}
+
+pub async fn delete_account_handler(
+    session: Session,
+    body: DeleteAccount,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let user = db
+        .fetch_user_by_id(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    verify_password(&body.password, &user.password)?;
+
+    db.delete_user_books(&user.id)
+        .await
+        .map_err(reject::custom)?;
+    db.delete_user_restaurants(&user.id)
+        .await
+        .map_err(reject::custom)?;
+    db.delete_user_recipes(&user.id)
+        .await
+        .map_err(reject::custom)?;
+    db.delete_user_todos(&user.id)
+        .await
+        .map_err(reject::custom)?;
+    db.delete_user_tokens(&user.id)
+        .await
+        .map_err(reject::custom)?;
+    db.delete_user_tags(&user.id)
+        .await
+        .map_err(reject::custom)?;
+    db.delete_user_sessions(&user.id, None)
+        .await
+        .map_err(reject::custom)?;
+    db.delete_user(&user.id).await.map_err(reject::custom)?;
+
+    login_page(true)
+}
+
+/// Downloads a zip archive of all of the user's items
+pub async fn backup_handler(session: Session, db: DB) -> WebResult<impl Reply> {
+    let user = db
+        .fetch_user_by_id(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let archive = backup::create_backup(&user, &db)
+        .await
+        .map_err(reject::custom)?;
+    let res = reply::with_header(archive, "content-type", "application/zip");
+    let filename = format!("toodeloo-backup-{}.zip", Utc::now().format("%Y-%m-%d"));
+    Ok(attachment(res, &filename))
+}
+
+/// Restores an uploaded archive, merging it with the user's items or replacing them
+pub async fn restore_handler(
+    session: Session,
+    fields: HashMap<String, Vec<u8>>,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let mode = match fields.get("mode").map(|m| m.as_slice()) {
+        Some(b"replace") => RestoreMode::Replace,
+        _ => RestoreMode::Merge,
+    };
+    let archive = match fields.get("archive") {
+        Some(archive) if !archive.is_empty() => archive,
+        _ => {
+            return Err(reject::custom(InvalidInputError(
+                "no backup archive was uploaded".to_owned(),
+            )))
+        }
+    };
+    let backup = backup::read_backup(archive).map_err(reject::custom)?;
+    let restored = backup::restore_backup(&backup, &session.user_id, mode, &db)
+        .await
+        .map_err(reject::custom)?;
+    let message = format!(
+        "Restored {} from the backup of {}.",
+        backup::describe(&restored),
+        backup.manifest.created_at.format("%Y-%m-%d")
+    );
+    render_account(&session, &message, &db).await
+}
+
+async fn render_account(session: &Session, message: &str, db: &DB) -> WebResult<impl Reply> {
+    let user = db
+        .fetch_user_by_id(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let template = AccountTemplate {
+        email: &user.email,
+        message,
+        csrf_token: &csrf::session_token(session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
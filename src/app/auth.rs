@@ -1,83 +1,225 @@
+use crate::app::{cookie, csrf, validation::FieldErrors};
 use crate::data::Session;
-use crate::db::{
-    session::{create_session, delete_session},
-    user::fetch_user,
-};
-use crate::{error::Error::*, WebResult, DB};
+use crate::{error::Error::*, WebResult, CONFIG, DB};
 use askama::Template;
 use serde::{Deserialize, Serialize};
-use warp::{reject, reply::html, Reply};
+use warp::{
+    http::{header::HeaderValue, StatusCode},
+    reject,
+    reply::{self, html, Response},
+    Reply,
+};
 
 const SET_COOKIE: &str = "Set-Cookie";
 const COOKIE_NAME: &str = "toodeloo";
-const MAX_AGE: usize = 60 * 60 * 24 * 15; // 15 days
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_EMAIL_LENGTH: usize = 254;
 
 #[derive(Template)]
 #[template(path = "login.html")]
-struct LoginTemplate {}
+struct LoginTemplate<'a> {
+    email: &'a str,
+    errors: &'a FieldErrors,
+    csrf_token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "register.html")]
+struct RegisterTemplate<'a> {
+    csrf_token: &'a str,
+}
 
 #[derive(Template)]
 #[template(path = "loggedin.html")]
-struct LoggedInTemplate {
+struct LoggedInTemplate<'a> {
     pub email: String,
+    csrf_token: &'a str,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
 pub struct LoginUser {
     pub email: String,
     pub password: String,
 }
 
+impl LoginUser {
+    pub fn validate(&self) -> FieldErrors {
+        let mut errors = FieldErrors::default();
+        errors.check_required("email", &self.email, MAX_EMAIL_LENGTH);
+        if !self.email.contains('@') {
+            errors.add("email", "must be an email address".to_owned());
+        }
+        if self.password.is_empty() {
+            errors.add("password", "is required".to_owned());
+        }
+        errors
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RegisterUser {
+    pub email: String,
+    pub password: String,
+    pub password_confirmation: String,
+}
+
 pub async fn login_handler() -> WebResult<impl Reply> {
-    let template = LoginTemplate {};
+    login_page(false)
+}
+
+pub async fn do_login_handler(body: LoginUser, db: DB) -> WebResult<Response> {
+    let errors = body.validate();
+    if !errors.is_empty() {
+        return login_form(&body.email, &errors, StatusCode::BAD_REQUEST, false);
+    }
+    let email = normalize_email(&body.email);
+    let user = match db.fetch_user(&email).await {
+        Ok(user) if verify_password(&body.password, &user.password).is_ok() => user,
+        _ => {
+            let mut errors = FieldErrors::default();
+            errors.add("password", "does not match the email".to_owned());
+            return login_form(&body.email, &errors, StatusCode::UNAUTHORIZED, false);
+        }
+    };
+    let res = logged_in(&user.id, email, &db).await?;
+    Ok(res.into_response())
+}
+
+pub async fn register_handler() -> WebResult<impl Reply> {
+    let csrf_token = csrf::new_login_token();
+    let template = RegisterTemplate {
+        csrf_token: &csrf_token,
+    };
     let res = template
         .render()
         .map_err(|e| reject::custom(TemplateError(e)))?;
-    Ok(html(res))
+    with_cookies(html(res), &[csrf::create_login_cookie(&csrf_token)])
 }
 
-pub async fn do_login_handler(body: LoginUser, db: DB) -> WebResult<impl Reply> {
-    let user = fetch_user(&body.email, &db)
-        .await
-        .map_err(|_| reject::custom(InvalidCredentials))?;
+pub async fn do_register_handler(body: RegisterUser, db: DB) -> WebResult<impl Reply> {
+    let email = normalize_email(&body.email);
+    if email.is_empty() || !email.contains('@') {
+        return Err(reject::custom(InvalidInputError(format!(
+            "invalid email: {}",
+            email
+        ))));
+    }
+    validate_password(&body.password, &body.password_confirmation)?;
 
-    match bcrypt::verify(&body.password, &user.password) {
-        Ok(v) => {
-            if !v {
-                return Err(reject::custom(InvalidCredentials));
-            }
-        }
-        Err(_) => return Err(reject::custom(InvalidCredentials)),
+    match db.fetch_user(&email).await {
+        Ok(_) => return Err(reject::custom(EmailTakenError(email))),
+        Err(NoEntryFoundError(_)) => (),
+        Err(e) => return Err(reject::custom(e)),
     };
-    let session_id = create_session(&user.id, &db)
+
+    let password_hash = bcrypt::hash(&body.password, bcrypt::DEFAULT_COST)
+        .map_err(|e| reject::custom(PasswordHashError(e)))?;
+    let user_id = db
+        .create_user(&email, &password_hash)
+        .await
+        .map_err(reject::custom)?;
+    logged_in(&user_id, email, &db).await
+}
+
+async fn logged_in(user_id: &str, email: String, db: &DB) -> WebResult<impl Reply> {
+    let session = db
+        .create_session(user_id)
         .await
         .map_err(|_| reject::custom(CreateSessionError))?;
-    let template = LoggedInTemplate { email: body.email };
+    let template = LoggedInTemplate {
+        email,
+        csrf_token: &csrf::session_token(&session),
+    };
     let res = template
         .render()
         .map_err(|e| reject::custom(TemplateError(e)))?;
     let html = warp::reply::html(res);
-    // TODO: encrypt session_id in cookie
-    let response = warp::reply::with_header(html, SET_COOKIE, &create_cookie(&session_id));
+    let response = warp::reply::with_header(html, SET_COOKIE, &create_cookie(&session.session_id));
     Ok(response)
 }
 
 pub async fn logout_handler(session: Session, db: DB) -> WebResult<impl Reply> {
-    delete_session(&session.session_id, &db)
+    db.delete_session(&session.session_id)
         .await
         .map_err(|_| reject::custom(LogoutError))?;
-    let reply = login_handler().await?;
-    Ok(warp::reply::with_header(
-        reply,
-        SET_COOKIE,
-        &create_cookie(""),
-    ))
+    login_page(true)
+}
+
+/// Renders the login page with a fresh CSRF cookie, clearing the session cookie if `logged_out`
+pub fn login_page(logged_out: bool) -> WebResult<Response> {
+    login_form("", &FieldErrors::default(), StatusCode::OK, logged_out)
+}
+
+/// Renders the login form with the given email and messages for the invalid fields
+fn login_form(
+    email: &str,
+    errors: &FieldErrors,
+    code: StatusCode,
+    logged_out: bool,
+) -> WebResult<Response> {
+    let csrf_token = csrf::new_login_token();
+    let template = LoginTemplate {
+        email,
+        errors,
+        csrf_token: &csrf_token,
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    let mut cookies = vec![csrf::create_login_cookie(&csrf_token)];
+    if logged_out {
+        cookies.push(create_cookie(""));
+    }
+    with_cookies(reply::with_status(html(res), code), &cookies)
+}
+
+fn with_cookies(reply: impl Reply, cookies: &[String]) -> WebResult<Response> {
+    let mut response = reply.into_response();
+    for cookie in cookies {
+        let value = HeaderValue::from_str(cookie).map_err(|e| reject::custom(HeaderError(e)))?;
+        response.headers_mut().append(SET_COOKIE, value);
+    }
+    Ok(response)
+}
+
+/// Checks the given password against the stored bcrypt hash
+pub fn verify_password(password: &str, hash: &str) -> WebResult<()> {
+    match bcrypt::verify(password, hash) {
+        Ok(true) => Ok(()),
+        _ => Err(reject::custom(InvalidCredentials)),
+    }
+}
+
+/// Checks that a new password is long enough and matches its confirmation
+pub fn validate_password(password: &str, confirmation: &str) -> WebResult<()> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(reject::custom(InvalidInputError(format!(
+            "password must have at least {} characters",
+            MIN_PASSWORD_LENGTH
+        ))));
+    }
+    if password != confirmation {
+        return Err(reject::custom(InvalidInputError(
+            "passwords do not match".to_owned(),
+        )));
+    }
+    Ok(())
 }
 
-fn create_cookie(session_id: &str) -> String {
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+pub fn create_cookie(session_id: &str) -> String {
+    let value = if session_id.is_empty() {
+        String::default()
+    } else {
+        cookie::sign(session_id)
+    };
     let cookie = format!(
-        "{}={};Max-Age={};HTTPOnly;Secure",
-        COOKIE_NAME, session_id, MAX_AGE
+        "{}={};Max-Age={};HTTPOnly;Secure;SameSite=Lax",
+        COOKIE_NAME, value, CONFIG.session.max_lifetime_secs
     );
     cookie
 }
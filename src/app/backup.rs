@@ -0,0 +1,440 @@
+//! Per-user backups as a zip archive, holding a JSON manifest, the account settings and a JSON
+//! file per item type. Sessions and API tokens aren't backed up. Item types added later get their
+//! own file, which is treated as empty when restoring older archives.
+
+use crate::app::auth::normalize_email;
+use crate::app::books::BookQuery;
+use crate::app::tags::TagEntry;
+use crate::app::todos::TodoFilter;
+use crate::{
+    data::{Book, Recipe, Restaurant, Tag, Todo, User},
+    error::Error::*,
+    Result, DB,
+};
+use chrono::prelude::*;
+use log::info;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read, Write};
+use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+/// Version of the archive layout, raised on changes older versions can't restore
+const VERSION: u32 = 1;
+const MANIFEST: &str = "manifest.json";
+const SETTINGS: &str = "settings.json";
+const BOOKS: &str = "books.json";
+const TAGS: &str = "tags.json";
+const RESTAURANTS: &str = "restaurants.json";
+const RECIPES: &str = "recipes.json";
+const TODOS: &str = "todos.json";
+/// Limit for the uncompressed size of a file in the archive, since a small upload can unpack to
+/// far more than fits into memory
+const MAX_ENTRY_SIZE: u64 = 1024 * 1024 * 64;
+
+/// Describes the archive
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The account's settings, which are archived for reference. They aren't restored, since an
+/// archive can be restored into another account.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct AccountSettings {
+    pub email: String,
+}
+
+/// The content of an archive
+#[derive(Debug)]
+pub struct Backup {
+    pub manifest: Manifest,
+    pub settings: AccountSettings,
+    pub books: Vec<Book>,
+    pub tags: Vec<Tag>,
+    pub restaurants: Vec<Restaurant>,
+    pub recipes: Vec<Recipe>,
+    pub todos: Vec<Todo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RestoreMode {
+    /// adds the archived items the user doesn't have yet
+    Merge,
+    /// deletes all of the user's items before restoring the archived ones
+    Replace,
+}
+
+/// How many items of a type were restored, and how many were skipped as already existing
+#[derive(Debug)]
+pub struct Restored {
+    pub kind: &'static str,
+    pub restored: usize,
+    pub skipped: usize,
+}
+
+/// Describes what was restored, like "3 books (1 skipped), 2 recipes"
+pub fn describe(restored: &[Restored]) -> String {
+    let parts: Vec<String> = restored
+        .iter()
+        .map(|r| match r.skipped {
+            0 => format!("{} {}", r.restored, r.kind),
+            skipped => format!("{} {} ({} skipped)", r.restored, r.kind, skipped),
+        })
+        .collect();
+    parts.join(", ")
+}
+
+/// Creates the zip archive of all of the user's items
+pub async fn create_backup(user: &User, db: &DB) -> Result<Vec<u8>> {
+    let books = db.fetch_books(&user.id, &BookQuery::all()).await?;
+    let tags = db.fetch_tags(&user.id).await?;
+    let restaurants = db.fetch_restaurants(&user.id).await?;
+    let recipes = db.fetch_recipes(&user.id).await?;
+    let todos = fetch_all_todos(&user.id, db).await?;
+    let manifest = Manifest {
+        version: VERSION,
+        created_at: Utc::now(),
+    };
+    let settings = AccountSettings {
+        email: user.email.clone(),
+    };
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    write_json(&mut zip, MANIFEST, &manifest)?;
+    write_json(&mut zip, SETTINGS, &settings)?;
+    write_json(&mut zip, BOOKS, &books)?;
+    write_json(&mut zip, TAGS, &tags)?;
+    write_json(&mut zip, RESTAURANTS, &restaurants)?;
+    write_json(&mut zip, RECIPES, &recipes)?;
+    write_json(&mut zip, TODOS, &todos)?;
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Reads and validates the whole archive, so nothing is restored from broken archives
+pub fn read_backup(data: &[u8]) -> Result<Backup> {
+    let mut zip = ZipArchive::new(Cursor::new(data))?;
+    let manifest: Manifest = read_json(&mut zip, MANIFEST)?
+        .ok_or_else(|| BackupError(format!("{} is missing", MANIFEST)))?;
+    if manifest.version == 0 || manifest.version > VERSION {
+        return Err(BackupError(format!(
+            "archive version {} is not supported, up to version {} can be restored",
+            manifest.version, VERSION
+        )));
+    }
+    let backup = Backup {
+        manifest,
+        settings: read_json(&mut zip, SETTINGS)?.unwrap_or_default(),
+        books: read_json(&mut zip, BOOKS)?.unwrap_or_default(),
+        tags: read_json(&mut zip, TAGS)?.unwrap_or_default(),
+        restaurants: read_json(&mut zip, RESTAURANTS)?.unwrap_or_default(),
+        recipes: read_json(&mut zip, RECIPES)?.unwrap_or_default(),
+        todos: read_json(&mut zip, TODOS)?.unwrap_or_default(),
+    };
+    Ok(backup)
+}
+
+/// Restores the archived items for the user. Merging skips books with the name and author of an
+/// existing book and other items with the name and creation time of an existing one, tags are
+/// matched by name. Replacing deletes the existing items only after the archived ones were
+/// inserted, so the user's data is kept if restoring fails.
+pub async fn restore_backup(
+    backup: &Backup,
+    user_id: &str,
+    mode: RestoreMode,
+    db: &DB,
+) -> Result<Vec<Restored>> {
+    let old_books = db.fetch_books(user_id, &BookQuery::all()).await?;
+    let old_restaurants = db.fetch_restaurants(user_id).await?;
+    let old_recipes = db.fetch_recipes(user_id).await?;
+    let old_todos = fetch_all_todos(user_id, db).await?;
+    let old_tags = db.fetch_tags(user_id).await?;
+    // when replacing, archived items are only compared with each other
+    let merge = mode == RestoreMode::Merge;
+
+    let tag_ids = restore_tags(&backup.tags, &old_tags, user_id, mode, db).await?;
+
+    let books: Vec<Book> = new_items(&backup.books, if merge { &old_books } else { &[] }, |b| {
+        (b.name.to_lowercase(), b.author.to_lowercase())
+    })
+    .into_iter()
+    .map(|b| Book {
+        tags: b
+            .tags
+            .iter()
+            .filter_map(|id| tag_ids.get(id).cloned())
+            .collect(),
+        ..b
+    })
+    .collect();
+    let restored_books = db.import_books(&books, user_id).await?;
+
+    let restaurants = new_items(
+        &backup.restaurants,
+        if merge { &old_restaurants } else { &[] },
+        |r| (r.name.clone(), r.added_at),
+    );
+    let restored_restaurants = db.import_restaurants(&restaurants, user_id).await?;
+
+    let recipes = new_items(
+        &backup.recipes,
+        if merge { &old_recipes } else { &[] },
+        |r| (r.title.clone(), r.added_at),
+    );
+    let restored_recipes = db.import_recipes(&recipes, user_id).await?;
+
+    let todos = new_items(&backup.todos, if merge { &old_todos } else { &[] }, |t| {
+        (t.title.clone(), t.added_at)
+    });
+    let restored_todos = db.import_todos(&todos, user_id).await?;
+
+    if mode == RestoreMode::Replace {
+        for book in &old_books {
+            db.delete_book(&book.id, user_id).await?;
+        }
+        for restaurant in &old_restaurants {
+            db.delete_restaurant(&restaurant.id, user_id).await?;
+        }
+        for recipe in &old_recipes {
+            db.delete_recipe(&recipe.id, user_id).await?;
+        }
+        for todo in &old_todos {
+            db.delete_todo(&todo.id, user_id).await?;
+        }
+        // existing tags with the name of an archived tag were reused
+        let restored_tag_ids: HashSet<&String> = tag_ids.values().collect();
+        for tag in old_tags
+            .iter()
+            .filter(|t| !restored_tag_ids.contains(&t.id))
+        {
+            db.delete_tag(&tag.id, user_id).await?;
+        }
+    }
+
+    Ok(vec![
+        Restored {
+            kind: "books",
+            restored: restored_books,
+            skipped: backup.books.len() - restored_books,
+        },
+        Restored {
+            kind: "restaurants",
+            restored: restored_restaurants,
+            skipped: backup.restaurants.len() - restored_restaurants,
+        },
+        Restored {
+            kind: "recipes",
+            restored: restored_recipes,
+            skipped: backup.recipes.len() - restored_recipes,
+        },
+        Restored {
+            kind: "todos",
+            restored: restored_todos,
+            skipped: backup.todos.len() - restored_todos,
+        },
+    ])
+}
+
+/// Writes a backup of the user with the given email to `path`, for `--backup <email> <path>`
+pub async fn backup_command(args: &[String]) -> Result<()> {
+    let (email, path) = match args {
+        [email, path, ..] => (email, path),
+        _ => {
+            return Err(InvalidInputError(
+                "usage: --backup <email> <file>".to_owned(),
+            ))
+        }
+    };
+    let db = crate::db::init().await?;
+    let user = db.fetch_user(&normalize_email(email)).await?;
+    let archive = create_backup(&user, &db).await?;
+    std::fs::write(path, archive)?;
+    info!("Wrote backup of {} to {}", user.email, path);
+    Ok(())
+}
+
+/// Restores a backup from `path` for the user with the given email, for
+/// `--restore <email> <path> [--replace]`
+pub async fn restore_command(args: &[String]) -> Result<()> {
+    let (email, path) = match args {
+        [email, path, ..] => (email, path),
+        _ => {
+            return Err(InvalidInputError(
+                "usage: --restore <email> <file> [--replace]".to_owned(),
+            ))
+        }
+    };
+    let mode = if args.iter().any(|a| a == "--replace") {
+        RestoreMode::Replace
+    } else {
+        RestoreMode::Merge
+    };
+    let backup = read_backup(&std::fs::read(path)?)?;
+    info!(
+        "Restoring backup of {} created at {}",
+        backup.settings.email, backup.manifest.created_at
+    );
+    let db = crate::db::init().await?;
+    let user = db.fetch_user(&normalize_email(email)).await?;
+    let restored = restore_backup(&backup, &user.id, mode, &db).await?;
+    info!("Restored {} for {}", describe(&restored), user.email);
+    Ok(())
+}
+
+/// Creates the archived tags the user doesn't have yet, returning the user's tag ids by the
+/// archived ones. Existing tags with the name of an archived tag are reused, when replacing
+/// they get the archived color.
+async fn restore_tags(
+    tags: &[Tag],
+    existing: &[Tag],
+    user_id: &str,
+    mode: RestoreMode,
+    db: &DB,
+) -> Result<HashMap<String, String>> {
+    let mut ids: HashMap<String, String> = HashMap::new();
+    let mut by_name: HashMap<String, String> = existing
+        .iter()
+        .map(|t| (t.name.clone(), t.id.clone()))
+        .collect();
+    for tag in tags {
+        let entry = TagEntry {
+            name: tag.name.clone(),
+            color: tag.color.clone(),
+        };
+        let id = match by_name.get(&tag.name) {
+            Some(id) => {
+                let recolor = existing.iter().any(|t| &t.id == id && t.color != tag.color);
+                if mode == RestoreMode::Replace && recolor {
+                    db.edit_tag(id, user_id, &entry).await?;
+                }
+                id.clone()
+            }
+            None => {
+                let id = db.create_tag(&entry, user_id).await?;
+                by_name.insert(tag.name.clone(), id.clone());
+                id
+            }
+        };
+        ids.insert(tag.id.clone(), id);
+    }
+    Ok(ids)
+}
+
+async fn fetch_all_todos(user_id: &str, db: &DB) -> Result<Vec<Todo>> {
+    let mut todos = db.fetch_todos(user_id, TodoFilter::Open).await?;
+    todos.extend(db.fetch_todos(user_id, TodoFilter::Done).await?);
+    Ok(todos)
+}
+
+/// Returns the archived items whose key doesn't match an existing item or an earlier one
+fn new_items<T: Clone, K: Eq + std::hash::Hash>(
+    archived: &[T],
+    existing: &[T],
+    key: fn(&T) -> K,
+) -> Vec<T> {
+    let mut seen: HashSet<K> = existing.iter().map(key).collect();
+    archived
+        .iter()
+        .filter(|item| seen.insert(key(item)))
+        .cloned()
+        .collect()
+}
+
+fn write_json<T: Serialize>(
+    zip: &mut ZipWriter<Cursor<Vec<u8>>>,
+    name: &str,
+    value: &T,
+) -> Result<()> {
+    zip.start_file(name, FileOptions::default())?;
+    zip.write_all(&serde_json::to_vec_pretty(value)?)?;
+    Ok(())
+}
+
+/// Returns `None` if the archive has no file with the given name, fails for files larger than
+/// `MAX_ENTRY_SIZE`
+fn read_json<T: DeserializeOwned>(
+    zip: &mut ZipArchive<Cursor<&[u8]>>,
+    name: &str,
+) -> Result<Option<T>> {
+    let file = match zip.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut content = Vec::new();
+    file.take(MAX_ENTRY_SIZE + 1).read_to_end(&mut content)?;
+    if content.len() as u64 > MAX_ENTRY_SIZE {
+        return Err(BackupError(format!(
+            "{} is larger than {} MB",
+            name,
+            MAX_ENTRY_SIZE / 1024 / 1024
+        )));
+    }
+    let value = serde_json::from_slice(&content)
+        .map_err(|e| BackupError(format!("{} is invalid: {}", name, e)))?;
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(version: u32) -> Vec<u8> {
+        let manifest = Manifest {
+            version,
+            created_at: Utc::now(),
+        };
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        write_json(&mut zip, MANIFEST, &manifest).expect("manifest is written");
+        zip.finish().expect("archive is written").into_inner()
+    }
+
+    #[test]
+    fn reads_archives_of_supported_versions() {
+        let backup = read_backup(&archive(VERSION)).expect("archive is read");
+        assert_eq!(backup.manifest.version, VERSION);
+        assert_eq!(backup.settings.email, "");
+        assert!(backup.books.is_empty());
+        assert!(backup.todos.is_empty());
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        for version in &[0, VERSION + 1] {
+            match read_backup(&archive(*version)) {
+                Err(BackupError(message)) => assert_eq!(
+                    message,
+                    format!(
+                        "archive version {} is not supported, up to version {} can be restored",
+                        version, VERSION
+                    )
+                ),
+                other => panic!("version {} was not rejected: {:?}", version, other),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_archives_without_manifest() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let data = zip.finish().expect("archive is written").into_inner();
+        assert!(matches!(read_backup(&data), Err(BackupError(_))));
+    }
+
+    #[test]
+    fn rejects_oversized_files() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file(MANIFEST, FileOptions::default())
+            .expect("file is started");
+        let chunk = vec![b' '; 1024 * 1024];
+        for _ in 0..=MAX_ENTRY_SIZE / 1024 / 1024 {
+            zip.write_all(&chunk).expect("chunk is written");
+        }
+        let data = zip.finish().expect("archive is written").into_inner();
+        match read_backup(&data) {
+            Err(BackupError(message)) => assert_eq!(message, "manifest.json is larger than 64 MB"),
+            other => panic!("oversized file was not rejected: {:?}", other),
+        }
+    }
+}
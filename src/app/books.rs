@@ -1,27 +1,345 @@
-use crate::db::books::{create_book, delete_book, edit_book, fetch_book, fetch_books};
+use crate::app::{confirm_delete, csrf, markdown, tags, validation::FieldErrors};
 use crate::{
-    data::{Book, Session},
+    data::{start_of_day, Book, BookNote, NoteKind, ReadingStatus, Session, Tag},
     error::Error::*,
     WebResult, DB,
 };
 use askama::Template;
+use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
-use warp::{reject, reply::html, Reply};
+use std::cmp::Reverse;
+use warp::{
+    http::StatusCode,
+    reject,
+    reply::{self, html, Response},
+    Reply,
+};
+
+const DEFAULT_PER_PAGE: usize = 20;
+const MAX_PER_PAGE: usize = 100;
+const MAX_PAGE: usize = 100_000;
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const MIN_RATING: i32 = 1;
+const MAX_RATING: i32 = 5;
+const MAX_TEXT_LENGTH: usize = 200;
+const MAX_LANGUAGE_LENGTH: usize = 50;
+const MAX_PAGES: i32 = 100_000;
 
 #[derive(Template)]
 #[template(path = "book/list.html")]
 struct BooklistTemplate<'a> {
     books: &'a Vec<Book>,
+    query: &'a BookListQuery,
+    author: &'a str,
+    language: &'a str,
+    status: &'a str,
+    statuses: &'a [ReadingStatus],
+    tag: &'a str,
+    tags: &'a Vec<Tag>,
+    sort: &'a str,
+    dir: &'a str,
+    page: usize,
+    total_pages: usize,
+    total: i64,
+    csrf_token: &'a str,
+}
+
+impl<'a> BooklistTemplate<'a> {
+    fn page_url(&self, page: usize) -> String {
+        list_url(&BookListQuery {
+            page: Some(page.to_string()),
+            ..self.query.clone()
+        })
+    }
+
+    /// Links to the list sorted by `sort`, toggling the direction if it's already sorted by it
+    fn sort_url(&self, sort: &str) -> String {
+        let dir = if sort == self.sort && self.dir == "asc" {
+            SortDirection::Desc
+        } else {
+            SortDirection::Asc
+        };
+        list_url(&BookListQuery {
+            page: None,
+            sort: Some(sort.to_owned()),
+            dir: Some(dir.as_str().to_owned()),
+            ..self.query.clone()
+        })
+    }
+
+    fn author_url(&self, book: &Book) -> String {
+        list_url(&BookListQuery {
+            page: None,
+            author: Some(book.author.clone()),
+            ..self.query.clone()
+        })
+    }
+
+    fn language_url(&self, book: &Book) -> String {
+        list_url(&BookListQuery {
+            page: None,
+            language: Some(book.language.clone()),
+            ..self.query.clone()
+        })
+    }
+
+    fn tag_url(&self, tag: &Tag) -> String {
+        list_url(&BookListQuery {
+            page: None,
+            tag: Some(tag.id.clone()),
+            ..self.query.clone()
+        })
+    }
+
+    fn untagged_url(&self) -> String {
+        list_url(&BookListQuery {
+            page: None,
+            tag: None,
+            ..self.query.clone()
+        })
+    }
+
+    fn tag_name(&self) -> &str {
+        self.tags
+            .iter()
+            .find(|t| t.id == self.tag)
+            .map(|t| t.name.as_str())
+            .unwrap_or_default()
+    }
+
+    fn book_tags(&self, book: &Book) -> Vec<&Tag> {
+        book_tags(book, self.tags)
+    }
+
+    /// Links to the list showing only books with the given status, or all books for ""
+    fn status_url(&self, status: &str) -> String {
+        list_url(&BookListQuery {
+            page: None,
+            status: Some(status.to_owned()).filter(|s| !s.is_empty()),
+            ..self.query.clone()
+        })
+    }
+}
+
+#[derive(Template)]
+#[template(path = "book/search.html")]
+struct BookSearchTemplate<'a> {
+    q: &'a str,
+    hits: &'a Vec<BookHit<'a>>,
+    csrf_token: &'a str,
+}
+
+/// A search result, with the matches in name and author highlighted as HTML
+struct BookHit<'a> {
+    book: &'a Book,
+    name: String,
+    author: String,
 }
 
 #[derive(Template)]
 #[template(path = "book/new.html")]
-struct NewBookTemplate {}
+struct NewBookTemplate<'a> {
+    form: &'a BookForm,
+    errors: &'a FieldErrors,
+    csrf_token: &'a str,
+}
 
 #[derive(Template)]
 #[template(path = "book/edit.html")]
 struct EditBookTemplate<'a> {
+    id: &'a str,
+    form: &'a BookForm,
+    errors: &'a FieldErrors,
+    tag_names: &'a [String],
+    csrf_token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "book/view.html")]
+struct ViewBookTemplate<'a> {
     book: &'a Book,
+    review: &'a str,
+    notes: &'a Vec<RenderedNote<'a>>,
+    same_author: &'a Vec<Book>,
+    tags: &'a Vec<&'a Tag>,
+    note_kinds: &'a [NoteKind],
+    ratings: &'a [i32],
+    csrf_token: &'a str,
+}
+
+impl<'a> ViewBookTemplate<'a> {
+    fn is_rated(&self, rating: &i32) -> bool {
+        self.book.rating == Some(*rating as usize)
+    }
+
+    fn author_url(&self) -> String {
+        list_url(&BookListQuery {
+            author: Some(self.book.author.clone()),
+            ..BookListQuery::default()
+        })
+    }
+
+    fn language_url(&self) -> String {
+        list_url(&BookListQuery {
+            language: Some(self.book.language.clone()),
+            ..BookListQuery::default()
+        })
+    }
+}
+
+/// A note with its text rendered from Markdown to HTML
+struct RenderedNote<'a> {
+    note: &'a BookNote,
+    html: String,
+}
+
+#[derive(Template)]
+#[template(path = "book/progress.html")]
+struct ProgressTemplate<'a> {
+    book: &'a Book,
+    statuses: &'a [ReadingStatus],
+    today: &'a str,
+    csrf_token: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BookSort {
+    Name,
+    Author,
+    AddedAt,
+    Pages,
+}
+
+impl BookSort {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BookSort::Name => "name",
+            BookSort::Author => "author",
+            BookSort::AddedAt => "added_at",
+            BookSort::Pages => "pages",
+        }
+    }
+
+    fn parse(sort: &str) -> Option<Self> {
+        match sort {
+            "name" => Some(BookSort::Name),
+            "author" => Some(BookSort::Author),
+            "added_at" => Some(BookSort::AddedAt),
+            "pages" => Some(BookSort::Pages),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+impl SortDirection {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SortDirection::Asc => "asc",
+            SortDirection::Desc => "desc",
+        }
+    }
+
+    fn parse(dir: &str) -> Option<Self> {
+        match dir {
+            "asc" => Some(SortDirection::Asc),
+            "desc" => Some(SortDirection::Desc),
+            _ => None,
+        }
+    }
+}
+
+/// The parameters of the book list as given in the URL. They're kept as text, so invalid
+/// values fall back to the defaults instead of failing the whole request.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BookListQuery {
+    pub page: Option<String>,
+    pub per_page: Option<String>,
+    pub sort: Option<String>,
+    pub dir: Option<String>,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub status: Option<String>,
+    pub tag: Option<String>,
+}
+
+/// Filters, order and page of a book listing, as passed to the storage layer
+#[derive(Debug, Clone)]
+pub struct BookQuery {
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub status: Option<ReadingStatus>,
+    /// id of a tag the books must have
+    pub tag: Option<String>,
+    pub sort: BookSort,
+    pub dir: SortDirection,
+    pub page: usize,
+    /// books per page, all matching books are returned if not set
+    pub per_page: Option<usize>,
+}
+
+impl BookQuery {
+    /// All books of a user, in the order they were added
+    pub fn all() -> Self {
+        BookQuery {
+            author: None,
+            language: None,
+            status: None,
+            tag: None,
+            sort: BookSort::AddedAt,
+            dir: SortDirection::Asc,
+            page: 1,
+            per_page: None,
+        }
+    }
+
+    /// Number of books before the page, saturating instead of overflowing for huge pages
+    pub fn skip(&self) -> usize {
+        self.page
+            .saturating_sub(1)
+            .saturating_mul(self.per_page.unwrap_or(0))
+    }
+}
+
+impl From<&BookListQuery> for BookQuery {
+    fn from(query: &BookListQuery) -> Self {
+        let non_empty = |v: &Option<String>| v.clone().filter(|v| !v.trim().is_empty());
+        let number = |v: &Option<String>| v.as_deref().and_then(|v| v.trim().parse().ok());
+        BookQuery {
+            author: non_empty(&query.author),
+            language: non_empty(&query.language),
+            status: query.status.as_deref().and_then(ReadingStatus::parse),
+            tag: non_empty(&query.tag),
+            sort: query
+                .sort
+                .as_deref()
+                .and_then(BookSort::parse)
+                .unwrap_or(BookSort::AddedAt),
+            dir: query
+                .dir
+                .as_deref()
+                .and_then(SortDirection::parse)
+                .unwrap_or(SortDirection::Asc),
+            page: number(&query.page).unwrap_or(1).clamp(1, MAX_PAGE),
+            per_page: Some(
+                number(&query.per_page)
+                    .unwrap_or(DEFAULT_PER_PAGE)
+                    .clamp(1, MAX_PER_PAGE),
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchQuery {
+    pub q: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -32,6 +350,12 @@ pub struct NewBook {
     pub pages: i32,
 }
 
+impl NewBook {
+    pub fn validate(&self) -> FieldErrors {
+        validate_book(&self.name, &self.author, &self.language, self.pages)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EditedBook {
     pub name: String,
@@ -40,53 +364,787 @@ pub struct EditedBook {
     pub pages: i32,
 }
 
-pub async fn books_list_handler(session: Session, db: DB) -> WebResult<impl Reply> {
-    log::info!("session in handler: {:?}", session);
-    let books = fetch_books(&db).await.map_err(|e| reject::custom(e))?;
-    let template = BooklistTemplate { books: &books };
+impl EditedBook {
+    pub fn validate(&self) -> FieldErrors {
+        validate_book(&self.name, &self.author, &self.language, self.pages)
+    }
+}
+
+/// The new and edit forms, the edit form also assigns tags by their comma-separated names.
+/// Fields are kept as submitted, so invalid input can be shown again.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct BookForm {
+    pub name: String,
+    pub author: String,
+    pub language: String,
+    pub pages: String,
+    pub tags: String,
+}
+
+impl BookForm {
+    fn of(book: &Book, tags: String) -> BookForm {
+        BookForm {
+            name: book.name.clone(),
+            author: book.author.clone(),
+            language: book.language.clone(),
+            pages: book.num_pages.to_string(),
+            tags,
+        }
+    }
+
+    /// Returns the trimmed book, or the messages for the invalid fields
+    fn book(&self) -> Result<EditedBook, FieldErrors> {
+        let mut errors = FieldErrors::default();
+        let book = EditedBook {
+            name: self.name.trim().to_owned(),
+            author: self.author.trim().to_owned(),
+            language: self.language.trim().to_owned(),
+            pages: errors.parse_number("pages", &self.pages),
+        };
+        errors.extend(book.validate());
+        if errors.is_empty() {
+            Ok(book)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn new_book(&self) -> Result<NewBook, FieldErrors> {
+        self.book().map(|book| NewBook {
+            name: book.name,
+            author: book.author,
+            language: book.language,
+            pages: book.pages,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NewStatus {
+    pub status: ReadingStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NewProgress {
+    pub logged_at: String,
+    pub page: i32,
+}
+
+/// A rating of 0 means the book isn't rated
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReviewEntry {
+    pub rating: i32,
+    pub review: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NewNote {
+    pub kind: NoteKind,
+    pub text: String,
+    /// the page the note refers to, may be left empty
+    pub page: String,
+}
+
+/// Reading status, current page and reading dates of a book, as passed to the storage layer
+#[derive(Debug, Clone)]
+pub struct ReadingState {
+    pub status: ReadingStatus,
+    pub current_page: usize,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl ReadingState {
+    fn of(book: &Book) -> Self {
+        ReadingState {
+            status: book.status,
+            current_page: book.current_page,
+            started_at: book.started_at,
+            finished_at: book.finished_at,
+        }
+    }
+
+    /// Moves the book to `status`, keeping the reading dates and current page consistent with it
+    fn with_status(book: &Book, status: ReadingStatus, at: &DateTime<Utc>) -> Self {
+        let state = ReadingState::of(book);
+        match status {
+            ReadingStatus::WantToRead => ReadingState {
+                status,
+                current_page: 0,
+                started_at: None,
+                finished_at: None,
+            },
+            ReadingStatus::Reading => ReadingState {
+                status,
+                started_at: state.started_at.or(Some(*at)),
+                finished_at: None,
+                ..state
+            },
+            ReadingStatus::Finished => ReadingState {
+                status,
+                current_page: book.num_pages.max(state.current_page),
+                started_at: state.started_at.or(Some(*at)),
+                finished_at: Some(*at),
+            },
+            ReadingStatus::Abandoned => ReadingState {
+                status,
+                finished_at: None,
+                ..state
+            },
+        }
+    }
+
+    /// Sets the current page, starting or finishing the book if that page gets there
+    fn with_page(book: &Book, page: usize, at: &DateTime<Utc>) -> Self {
+        let state = match book.status {
+            ReadingStatus::WantToRead | ReadingStatus::Abandoned => {
+                ReadingState::with_status(book, ReadingStatus::Reading, at)
+            }
+            _ => ReadingState::of(book),
+        };
+        if book.num_pages > 0 && page >= book.num_pages {
+            return ReadingState {
+                current_page: page,
+                ..ReadingState::with_status(book, ReadingStatus::Finished, at)
+            };
+        }
+        ReadingState {
+            current_page: page,
+            ..state
+        }
+    }
+}
+
+pub async fn books_list_handler(
+    session: Session,
+    query: BookListQuery,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let book_query = BookQuery::from(&query);
+    let total = db
+        .count_books(&session.user_id, &book_query)
+        .await
+        .map_err(reject::custom)?;
+    let books = db
+        .fetch_books(&session.user_id, &book_query)
+        .await
+        .map_err(reject::custom)?;
+    let tags = db
+        .fetch_tags(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let per_page = book_query.per_page.unwrap_or(DEFAULT_PER_PAGE) as i64;
+    let template = BooklistTemplate {
+        books: &books,
+        query: &query,
+        author: book_query.author.as_deref().unwrap_or_default(),
+        language: book_query.language.as_deref().unwrap_or_default(),
+        status: book_query.status.map(|s| s.as_str()).unwrap_or_default(),
+        statuses: &ReadingStatus::ALL,
+        tag: book_query.tag.as_deref().unwrap_or_default(),
+        tags: &tags,
+        sort: book_query.sort.as_str(),
+        dir: book_query.dir.as_str(),
+        page: book_query.page,
+        total_pages: ((total + per_page - 1) / per_page).max(1) as usize,
+        total,
+        csrf_token: &csrf::session_token(&session),
+    };
     let res = template
         .render()
         .map_err(|e| reject::custom(TemplateError(e)))?;
     Ok(html(res))
 }
 
-pub async fn new_book_handler(_session: Session, _db: DB) -> WebResult<impl Reply> {
-    let template = NewBookTemplate {};
+pub async fn search_books_handler(
+    session: Session,
+    query: SearchQuery,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let q = query.q.unwrap_or_default();
+    let terms: Vec<&str> = q.split_whitespace().collect();
+    let books = if terms.is_empty() {
+        Vec::new()
+    } else {
+        db.search_books(&session.user_id, q.trim())
+            .await
+            .map_err(reject::custom)?
+    };
+    let hits = books
+        .iter()
+        .map(|book| BookHit {
+            book,
+            name: highlight(&book.name, &terms),
+            author: highlight(&book.author, &terms),
+        })
+        .collect();
+    let template = BookSearchTemplate {
+        q: &q,
+        hits: &hits,
+        csrf_token: &csrf::session_token(&session),
+    };
     let res = template
         .render()
         .map_err(|e| reject::custom(TemplateError(e)))?;
     Ok(html(res))
 }
 
-pub async fn create_book_handler(session: Session, body: NewBook, db: DB) -> WebResult<impl Reply> {
-    create_book(&body, &db)
+pub async fn new_book_handler(session: Session, _db: DB) -> WebResult<impl Reply> {
+    let form = BookForm {
+        language: "de".to_owned(),
+        pages: "300".to_owned(),
+        ..BookForm::default()
+    };
+    render_new_book(&session, &form, &FieldErrors::default(), StatusCode::OK)
+}
+
+pub async fn create_book_handler(session: Session, body: BookForm, db: DB) -> WebResult<Response> {
+    let book = match body.new_book() {
+        Ok(book) => book,
+        Err(errors) => return render_new_book(&session, &body, &errors, StatusCode::BAD_REQUEST),
+    };
+    db.create_book(&book, &session.user_id)
         .await
-        .map_err(|e| reject::custom(e))?;
-    books_list_handler(session, db).await
+        .map_err(reject::custom)?;
+    let res = books_list_handler(session, BookListQuery::default(), db).await?;
+    Ok(res.into_response())
 }
 
-pub async fn edit_book_handler(_session: Session, id: String, db: DB) -> WebResult<impl Reply> {
-    let book = fetch_book(&id, &db).await.map_err(|e| reject::custom(e))?;
-    let template = EditBookTemplate { book: &book };
+pub async fn edit_book_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
+    let book = db
+        .fetch_book(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let tags = db
+        .fetch_tags(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let assigned: Vec<&str> = book_tags(&book, &tags)
+        .iter()
+        .map(|t| t.name.as_str())
+        .collect();
+    let form = BookForm::of(&book, assigned.join(", "));
+    render_edit_book(
+        &session,
+        &id,
+        &form,
+        &FieldErrors::default(),
+        &tags,
+        StatusCode::OK,
+    )
+}
+
+pub async fn do_edit_book_handler(
+    session: Session,
+    id: String,
+    body: BookForm,
+    db: DB,
+) -> WebResult<Response> {
+    let book = match body.book() {
+        Ok(book) => book,
+        Err(errors) => {
+            // the book has to exist for its edit form to be shown again
+            db.fetch_book(&id, &session.user_id)
+                .await
+                .map_err(reject::custom)?;
+            let tags = db
+                .fetch_tags(&session.user_id)
+                .await
+                .map_err(reject::custom)?;
+            return render_edit_book(
+                &session,
+                &id,
+                &body,
+                &errors,
+                &tags,
+                StatusCode::BAD_REQUEST,
+            );
+        }
+    };
+    // tags are created while resolving them, so the book's ownership is checked first and
+    // invalid tag names are rejected before anything is saved
+    db.fetch_book(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let tag_ids = tags::resolve_tags(&body.tags, &session.user_id, &db).await?;
+    db.edit_book(&id, &session.user_id, &book)
+        .await
+        .map_err(reject::custom)?;
+    db.set_book_tags(&id, &session.user_id, &tag_ids)
+        .await
+        .map_err(reject::custom)?;
+    let res = books_list_handler(session, BookListQuery::default(), db).await?;
+    Ok(res.into_response())
+}
+
+fn render_new_book(
+    session: &Session,
+    form: &BookForm,
+    errors: &FieldErrors,
+    code: StatusCode,
+) -> WebResult<Response> {
+    let template = NewBookTemplate {
+        form,
+        errors,
+        csrf_token: &csrf::session_token(session),
+    };
     let res = template
         .render()
         .map_err(|e| reject::custom(TemplateError(e)))?;
-    Ok(html(res))
+    Ok(reply::with_status(html(res), code).into_response())
 }
 
-pub async fn do_edit_book_handler(
+fn render_edit_book(
+    session: &Session,
+    id: &str,
+    form: &BookForm,
+    errors: &FieldErrors,
+    tags: &[Tag],
+    code: StatusCode,
+) -> WebResult<Response> {
+    let tag_names: Vec<String> = tags.iter().map(|t| t.name.clone()).collect();
+    let template = EditBookTemplate {
+        id,
+        form,
+        errors,
+        tag_names: &tag_names,
+        csrf_token: &csrf::session_token(session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(reply::with_status(html(res), code).into_response())
+}
+
+pub async fn confirm_delete_book_handler(
     session: Session,
     id: String,
-    body: EditedBook,
     db: DB,
 ) -> WebResult<impl Reply> {
-    edit_book(&id, &body, &db)
+    let book = db
+        .fetch_book(&id, &session.user_id)
         .await
-        .map_err(|e| reject::custom(e))?;
-    books_list_handler(session, db).await
+        .map_err(reject::custom)?;
+    confirm_delete(
+        &session,
+        &format!("the book \"{}\"", book.name),
+        &format!("/books/delete/{}", book.id),
+        "/books/list",
+    )
 }
 
 pub async fn delete_book_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
-    delete_book(&id, &db).await.map_err(|e| reject::custom(e))?;
-    books_list_handler(session, db).await
+    db.delete_book(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    books_list_handler(session, BookListQuery::default(), db).await
+}
+
+pub async fn view_book_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
+    let mut book = db
+        .fetch_book(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    book.notes.sort_by_key(|n| Reverse(n.added_at));
+    book.progress.sort_by_key(|p| Reverse(p.logged_at));
+    let same_author_query = BookQuery {
+        author: Some(book.author.clone()),
+        sort: BookSort::Name,
+        ..BookQuery::all()
+    };
+    let same_author = db
+        .fetch_books(&session.user_id, &same_author_query)
+        .await
+        .map_err(reject::custom)?
+        .into_iter()
+        .filter(|b| b.id != book.id)
+        .collect();
+    let notes = book
+        .notes
+        .iter()
+        .map(|note| RenderedNote {
+            note,
+            html: markdown::to_html(&note.text),
+        })
+        .collect();
+    let all_tags = db
+        .fetch_tags(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let tags = book_tags(&book, &all_tags);
+    let ratings: Vec<i32> = (MIN_RATING..=MAX_RATING).collect();
+    let template = ViewBookTemplate {
+        book: &book,
+        review: &markdown::to_html(&book.review),
+        notes: &notes,
+        same_author: &same_author,
+        tags: &tags,
+        note_kinds: &NoteKind::ALL,
+        ratings: &ratings,
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn review_book_handler(
+    session: Session,
+    id: String,
+    body: ReviewEntry,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let rating = match body.rating {
+        0 => None,
+        r if (MIN_RATING..=MAX_RATING).contains(&r) => Some(r as usize),
+        _ => {
+            return Err(reject::custom(InvalidInputError(format!(
+                "rating must be between {} and {}",
+                MIN_RATING, MAX_RATING
+            ))))
+        }
+    };
+    db.set_review(&id, &session.user_id, rating, body.review.trim())
+        .await
+        .map_err(reject::custom)?;
+    view_book_handler(session, id, db).await
+}
+
+pub async fn add_note_handler(
+    session: Session,
+    id: String,
+    body: NewNote,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let text = body.text.trim();
+    if text.is_empty() {
+        return Err(reject::custom(InvalidInputError(
+            "note must not be empty".to_owned(),
+        )));
+    }
+    let page = match body.page.trim() {
+        "" => None,
+        page => Some(
+            page.parse::<usize>()
+                .map_err(|_| reject::custom(InvalidInputError(body.page.clone())))?,
+        ),
+    };
+    db.add_note(&id, &session.user_id, body.kind, text, page)
+        .await
+        .map_err(reject::custom)?;
+    view_book_handler(session, id, db).await
+}
+
+pub async fn confirm_delete_note_handler(
+    session: Session,
+    id: String,
+    note_id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let book = db
+        .fetch_book(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let note = book
+        .notes
+        .iter()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| reject::custom(NoEntryFoundError(note_id.clone())))?;
+    confirm_delete(
+        &session,
+        &format!(
+            "the {} on \"{}\" from {}",
+            note.kind.as_str(),
+            book.name,
+            note.added_at.format(DATE_FORMAT)
+        ),
+        &format!("/books/notes/{}/delete/{}", book.id, note.id),
+        &format!("/books/{}", book.id),
+    )
+}
+
+pub async fn delete_note_handler(
+    session: Session,
+    id: String,
+    note_id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    db.delete_note(&id, &session.user_id, &note_id)
+        .await
+        .map_err(reject::custom)?;
+    view_book_handler(session, id, db).await
+}
+
+pub async fn progress_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
+    let mut book = db
+        .fetch_book(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    book.progress.sort_by_key(|p| Reverse(p.logged_at));
+    let today = Utc::now().format(DATE_FORMAT).to_string();
+    let template = ProgressTemplate {
+        book: &book,
+        statuses: &ReadingStatus::ALL,
+        today: &today,
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn set_status_handler(
+    session: Session,
+    id: String,
+    body: NewStatus,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let book = db
+        .fetch_book(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let state = ReadingState::with_status(&book, body.status, &Utc::now());
+    db.set_reading_state(&id, &session.user_id, &state)
+        .await
+        .map_err(reject::custom)?;
+    progress_handler(session, id, db).await
+}
+
+pub async fn log_progress_handler(
+    session: Session,
+    id: String,
+    body: NewProgress,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let date = NaiveDate::parse_from_str(&body.logged_at, DATE_FORMAT)
+        .map_err(|_| reject::custom(InvalidInputError(body.logged_at.clone())))?;
+    let logged_at = start_of_day(date);
+    if body.page < 0 {
+        return Err(reject::custom(InvalidInputError(format!(
+            "page must not be negative, got {}",
+            body.page
+        ))));
+    }
+    let page = body.page as usize;
+    let book = db
+        .fetch_book(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    if book.num_pages > 0 && page > book.num_pages {
+        return Err(reject::custom(InvalidInputError(format!(
+            "page must be at most {}",
+            book.num_pages
+        ))));
+    }
+    db.add_progress(&id, &session.user_id, &logged_at, page)
+        .await
+        .map_err(reject::custom)?;
+    let state = ReadingState::with_page(&book, page, &logged_at);
+    db.set_reading_state(&id, &session.user_id, &state)
+        .await
+        .map_err(reject::custom)?;
+    progress_handler(session, id, db).await
+}
+
+pub async fn confirm_delete_progress_handler(
+    session: Session,
+    id: String,
+    progress_id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let book = db
+        .fetch_book(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let entry = book
+        .progress
+        .iter()
+        .find(|p| p.id == progress_id)
+        .ok_or_else(|| reject::custom(NoEntryFoundError(progress_id.clone())))?;
+    confirm_delete(
+        &session,
+        &format!(
+            "the progress of \"{}\" on {}",
+            book.name,
+            entry.logged_at.format(DATE_FORMAT)
+        ),
+        &format!("/books/progress/{}/delete/{}", book.id, entry.id),
+        &format!("/books/progress/{}", book.id),
+    )
+}
+
+pub async fn delete_progress_handler(
+    session: Session,
+    id: String,
+    progress_id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    db.delete_progress(&id, &session.user_id, &progress_id)
+        .await
+        .map_err(reject::custom)?;
+    progress_handler(session, id, db).await
+}
+
+/// Returns the tags assigned to the book, in the order of `tags`
+fn book_tags<'a>(book: &Book, tags: &'a [Tag]) -> Vec<&'a Tag> {
+    tags.iter().filter(|t| book.tags.contains(&t.id)).collect()
+}
+
+fn list_url(query: &BookListQuery) -> String {
+    match serde_urlencoded::to_string(query) {
+        Ok(params) if !params.is_empty() => format!("/books/list?{}", params),
+        _ => "/books/list".to_owned(),
+    }
+}
+
+/// Escapes `text` for HTML and wraps case-insensitive occurrences of the terms in `<mark>`
+fn highlight(text: &str, terms: &[&str]) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    'outer: while let Some(c) = rest.chars().next() {
+        for term in terms {
+            if let Some(len) = match_len(rest, term) {
+                result.push_str("<mark>");
+                result.push_str(&escape_html(&rest[..len]));
+                result.push_str("</mark>");
+                rest = &rest[len..];
+                continue 'outer;
+            }
+        }
+        result.push_str(&escape_html(&rest[..c.len_utf8()]));
+        rest = &rest[c.len_utf8()..];
+    }
+    result
+}
+
+/// Returns the length in bytes of `term` at the start of `text`, if it's there ignoring case
+fn match_len(text: &str, term: &str) -> Option<usize> {
+    let mut chars = text.chars();
+    let mut len = 0;
+    for t in term.chars() {
+        match chars.next() {
+            Some(c) if c.to_lowercase().eq(t.to_lowercase()) => len += c.len_utf8(),
+            _ => return None,
+        }
+    }
+    Some(len).filter(|len| *len > 0)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+/// Checks that name and author are given, that no text is too long and that the page count is
+/// in range, 0 meaning the page count isn't known
+fn validate_book(name: &str, author: &str, language: &str, pages: i32) -> FieldErrors {
+    let mut errors = FieldErrors::default();
+    errors.check_required("name", name, MAX_TEXT_LENGTH);
+    errors.check_required("author", author, MAX_TEXT_LENGTH);
+    errors.check_length("language", language, MAX_LANGUAGE_LENGTH);
+    errors.check_range("pages", pages, 0, MAX_PAGES);
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(params: &str) -> BookQuery {
+        let query: BookListQuery = serde_urlencoded::from_str(params).expect("query is parsed");
+        BookQuery::from(&query)
+    }
+
+    #[test]
+    fn reads_list_parameters() {
+        let query = query("page=3&per_page=10&sort=pages&dir=desc&status=reading&author=Herbert");
+        assert_eq!(query.page, 3);
+        assert_eq!(query.per_page, Some(10));
+        assert_eq!(query.sort, BookSort::Pages);
+        assert_eq!(query.dir, SortDirection::Desc);
+        assert_eq!(query.status, Some(ReadingStatus::Reading));
+        assert_eq!(query.author.as_deref(), Some("Herbert"));
+        assert_eq!(query.skip(), 20);
+    }
+
+    #[test]
+    fn falls_back_to_defaults_for_invalid_parameters() {
+        let query = query("page=abc&per_page=-1&sort=foo&dir=up&status=&author=+");
+        assert_eq!(query.page, 1);
+        assert_eq!(query.per_page, Some(DEFAULT_PER_PAGE));
+        assert_eq!(query.sort, BookSort::AddedAt);
+        assert_eq!(query.dir, SortDirection::Asc);
+        assert_eq!(query.status, None);
+        assert_eq!(query.author, None);
+    }
+
+    #[test]
+    fn clamps_page_and_page_size() {
+        let query = query("page=99999999999&per_page=1000");
+        assert_eq!(query.page, MAX_PAGE);
+        assert_eq!(query.per_page, Some(MAX_PER_PAGE));
+        assert_eq!(query.skip(), (MAX_PAGE - 1) * MAX_PER_PAGE);
+    }
+
+    fn book(num_pages: usize) -> Book {
+        Book::new(
+            "1",
+            "1",
+            "Dune",
+            "Frank Herbert",
+            "English",
+            num_pages,
+            &Utc::now(),
+        )
+    }
+
+    #[test]
+    fn starting_and_finishing_sets_dates() {
+        let started = Utc.with_ymd_and_hms(2020, 5, 1, 0, 0, 0).unwrap();
+        let finished = Utc.with_ymd_and_hms(2020, 5, 31, 0, 0, 0).unwrap();
+        let mut book = book(412);
+
+        let state = ReadingState::with_status(&book, ReadingStatus::Reading, &started);
+        assert_eq!(state.started_at, Some(started));
+        assert_eq!(state.finished_at, None);
+
+        book.status = state.status;
+        book.started_at = state.started_at;
+        let state = ReadingState::with_status(&book, ReadingStatus::Finished, &finished);
+        assert_eq!(state.started_at, Some(started));
+        assert_eq!(state.finished_at, Some(finished));
+        assert_eq!(state.current_page, 412);
+
+        let state = ReadingState::with_status(&book, ReadingStatus::WantToRead, &finished);
+        assert_eq!(state.started_at, None);
+        assert_eq!(state.current_page, 0);
+    }
+
+    #[test]
+    fn logging_pages_starts_and_finishes_books() {
+        let now = Utc::now();
+        let book = book(412);
+
+        let state = ReadingState::with_page(&book, 100, &now);
+        assert_eq!(state.status, ReadingStatus::Reading);
+        assert_eq!(state.current_page, 100);
+        assert_eq!(state.started_at, Some(now));
+
+        let state = ReadingState::with_page(&book, 412, &now);
+        assert_eq!(state.status, ReadingStatus::Finished);
+        assert_eq!(state.finished_at, Some(now));
+    }
+
+    #[test]
+    fn books_without_page_count_are_not_finished_by_pages() {
+        let state = ReadingState::with_page(&book(0), 100, &Utc::now());
+        assert_eq!(state.status, ReadingStatus::Reading);
+        assert_eq!(state.current_page, 100);
+    }
 }
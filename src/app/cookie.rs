@@ -0,0 +1,52 @@
+//! Signing and verification of cookie values using HMAC-SHA256
+
+use crate::CONFIG;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SEPARATOR: char = '.';
+
+/// Appends a signature to the given value, using the current secret
+pub fn sign(value: &str) -> String {
+    format!("{}{}{}", value, SEPARATOR, signature(value))
+}
+
+/// Returns the original value, if the signature was created with the current or a previous secret
+pub fn verify(signed: &str) -> Option<String> {
+    let idx = signed.rfind(SEPARATOR)?;
+    let (value, signature) = (&signed[..idx], &signed[idx + 1..]);
+    if verify_signature(value, signature) {
+        Some(value.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Creates a hex encoded signature of the given value, using the current secret
+pub fn signature(value: &str) -> String {
+    let mut mac = new_mac(&CONFIG.cookie.secret);
+    mac.update(value.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Checks in constant time, whether the signature was created with the current or a previous secret
+pub fn verify_signature(value: &str, signature: &str) -> bool {
+    let signature = match hex::decode(signature) {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+
+    std::iter::once(&CONFIG.cookie.secret)
+        .chain(CONFIG.cookie.previous_secrets.iter())
+        .any(|secret| {
+            let mut mac = new_mac(secret);
+            mac.update(value.as_bytes());
+            mac.verify(&signature).is_ok()
+        })
+}
+
+fn new_mac(secret: &str) -> HmacSha256 {
+    HmacSha256::new_varkey(secret.as_bytes()).expect("HMAC can take key of any size")
+}
@@ -0,0 +1,52 @@
+//! CSRF protection for forms
+//!
+//! Within a session, the token is a signature of the session id, so it's tied to the session and
+//! can be checked without a database lookup. Before login, a random token is stored in a signed
+//! cookie and has to be submitted with the form as well.
+
+use crate::app::cookie;
+use crate::data::Session;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const CSRF_COOKIE_NAME: &str = "toodeloo_csrf";
+const CSRF_COOKIE_MAX_AGE: usize = 60 * 60; // 1 hour
+const SESSION_TOKEN_PREFIX: &str = "csrf:";
+
+/// The token every protected form has to submit in its `csrf_token` field
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CsrfToken {
+    pub csrf_token: String,
+}
+
+/// Returns the CSRF token for forms within the given session
+pub fn session_token(session: &Session) -> String {
+    cookie::signature(&format!("{}{}", SESSION_TOKEN_PREFIX, session.session_id))
+}
+
+/// Checks the submitted token against the session id
+pub fn verify_session_token(session_id: &str, token: &str) -> bool {
+    cookie::verify_signature(&format!("{}{}", SESSION_TOKEN_PREFIX, session_id), token)
+}
+
+/// Creates a new random token for forms outside of a session, e.g. login
+pub fn new_login_token() -> String {
+    Uuid::new_v4().to_simple().to_string()
+}
+
+/// Checks the submitted token against the value of the signed CSRF cookie
+pub fn verify_login_token(cookie_value: &str, token: &str) -> bool {
+    match cookie::verify(cookie_value) {
+        Some(expected) => !token.is_empty() && expected == token,
+        None => false,
+    }
+}
+
+pub fn create_login_cookie(token: &str) -> String {
+    format!(
+        "{}={};Max-Age={};HTTPOnly;Secure;SameSite=Strict",
+        CSRF_COOKIE_NAME,
+        cookie::sign(token),
+        CSRF_COOKIE_MAX_AGE
+    )
+}
@@ -0,0 +1,111 @@
+//! Reads the CSV export of Goodreads, as downloaded from "My Books" > "Import and export"
+
+use super::{parse_date, parse_number, parse_rating, ImportRow, Layout, Table};
+use crate::data::{Book, ReadingStatus};
+use chrono::prelude::*;
+
+const TITLE: &str = "Title";
+const AUTHOR: &str = "Author";
+const PAGES: &str = "Number of Pages";
+const RATING: &str = "My Rating";
+const DATE_READ: &str = "Date Read";
+const DATE_ADDED: &str = "Date Added";
+const SHELVES: &str = "Bookshelves";
+const EXCLUSIVE_SHELF: &str = "Exclusive Shelf";
+const REVIEW: &str = "My Review";
+
+const READ: &str = "read";
+const CURRENTLY_READING: &str = "currently-reading";
+const TO_READ: &str = "to-read";
+/// names commonly given to a custom exclusive shelf for books the reader gave up on
+const ABANDONED_SHELVES: [&str; 4] = ["abandoned", "did-not-finish", "dnf", "gave-up"];
+
+pub fn read_rows(table: &Table, now: &DateTime<Utc>) -> Result<Vec<ImportRow>, String> {
+    let layout = Layout::new(
+        &table.headers,
+        &[TITLE, AUTHOR, EXCLUSIVE_SHELF],
+        "Goodreads export",
+    )?;
+    let rows = table
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| read_row(i + 1, row, &layout, now))
+        .collect();
+    Ok(rows)
+}
+
+/// Shelves other than the default ones become tags. Goodreads only records when a book was
+/// finished, so books are imported without a start date.
+fn read_row(number: usize, row: &[String], layout: &Layout, now: &DateTime<Utc>) -> ImportRow {
+    let cell = |column: &str| layout.cell(row, column);
+    let mut errors = Vec::new();
+
+    let name = cell(TITLE);
+    if name.is_empty() {
+        errors.push("title is missing".to_owned());
+    }
+    let author = cell(AUTHOR);
+    if author.is_empty() {
+        errors.push("author is missing".to_owned());
+    }
+    let num_pages = parse_number(cell(PAGES), PAGES, &mut errors).unwrap_or(0);
+    let rating = parse_rating(cell(RATING), RATING, &mut errors);
+    let date_read = parse_date(cell(DATE_READ), DATE_READ, &mut errors);
+    let added_at = parse_date(cell(DATE_ADDED), DATE_ADDED, &mut errors).unwrap_or(*now);
+
+    let status = shelf_status(cell(EXCLUSIVE_SHELF));
+    let finished = status == ReadingStatus::Finished;
+    let book = Book {
+        status,
+        current_page: if finished { num_pages } else { 0 },
+        finished_at: date_read.filter(|_| finished),
+        rating,
+        // reviews are HTML with line breaks, which are kept as Markdown paragraphs
+        review: cell(REVIEW).replace("<br/>", "\n"),
+        ..Book::new("", "", name, author, "", num_pages, &added_at)
+    };
+    let tag_names: Vec<&str> = cell(SHELVES)
+        .split(',')
+        .map(|shelf| shelf.trim())
+        .filter(|shelf| !shelf.is_empty() && ![READ, CURRENTLY_READING, TO_READ].contains(shelf))
+        .collect();
+    ImportRow {
+        number,
+        book,
+        tag_names: tag_names.join(", "),
+        errors,
+        duplicate: false,
+    }
+}
+
+/// Books on custom exclusive shelves are abandoned if the shelf's name says so, and not read
+/// yet otherwise
+fn shelf_status(shelf: &str) -> ReadingStatus {
+    match shelf {
+        READ => ReadingStatus::Finished,
+        CURRENTLY_READING => ReadingStatus::Reading,
+        TO_READ => ReadingStatus::WantToRead,
+        shelf if ABANDONED_SHELVES.contains(&shelf) => ReadingStatus::Abandoned,
+        _ => ReadingStatus::WantToRead,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shelf_status_maps_default_shelves() {
+        assert_eq!(shelf_status("read"), ReadingStatus::Finished);
+        assert_eq!(shelf_status("currently-reading"), ReadingStatus::Reading);
+        assert_eq!(shelf_status("to-read"), ReadingStatus::WantToRead);
+    }
+
+    #[test]
+    fn shelf_status_maps_custom_shelves() {
+        assert_eq!(shelf_status("dnf"), ReadingStatus::Abandoned);
+        assert_eq!(shelf_status("gave-up"), ReadingStatus::Abandoned);
+        assert_eq!(shelf_status("wishlist"), ReadingStatus::WantToRead);
+    }
+}
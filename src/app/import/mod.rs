@@ -0,0 +1,821 @@
+//! Import and export of books as CSV or JSON, e.g. from and to spreadsheets, and import of the
+//! exports of reading sites
+
+use crate::app::{books::BookQuery, csrf, tags};
+use crate::{
+    data::{start_of_day, Book, ReadingStatus, Session, Tag},
+    error::Error::*,
+    WebResult, DB,
+};
+use askama::Template;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use warp::{reject, reply, reply::html, Reply};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+/// the date format of the reading sites' exports
+const SLASHED_DATE_FORMAT: &str = "%Y/%m/%d";
+const MAX_ROWS: usize = 5000;
+const MIN_RATING: usize = 1;
+const MAX_RATING: usize = 5;
+
+mod goodreads;
+mod storygraph;
+
+#[derive(Template)]
+#[template(path = "book/import.html")]
+struct ImportTemplate<'a> {
+    format: &'a str,
+    data: &'a str,
+    /// whether the columns of the format can be chosen, instead of being a known layout
+    mappable: bool,
+    fields: &'a [Field],
+    headers: &'a [String],
+    mapping: &'a Mapping,
+    rows: &'a [ImportRow],
+    importable: usize,
+    /// whether the rows were imported, so they're shown as a report
+    done: bool,
+    message: &'a str,
+    csrf_token: &'a str,
+}
+
+impl<'a> ImportTemplate<'a> {
+    fn is_mapped(&self, field: &Field, column: &usize) -> bool {
+        self.mapping.column(*field) == Some(*column)
+    }
+}
+
+/// A book field which can be read from an uploaded column
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Name,
+    Author,
+    Language,
+    Pages,
+    Status,
+    CurrentPage,
+    Rating,
+    StartedAt,
+    FinishedAt,
+    AddedAt,
+    Tags,
+    Review,
+}
+
+impl Field {
+    const ALL: [Field; 12] = [
+        Field::Name,
+        Field::Author,
+        Field::Language,
+        Field::Pages,
+        Field::Status,
+        Field::CurrentPage,
+        Field::Rating,
+        Field::StartedAt,
+        Field::FinishedAt,
+        Field::AddedAt,
+        Field::Tags,
+        Field::Review,
+    ];
+
+    /// The field's column header in exports
+    fn as_str(&self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::Author => "author",
+            Field::Language => "language",
+            Field::Pages => "pages",
+            Field::Status => "status",
+            Field::CurrentPage => "current_page",
+            Field::Rating => "rating",
+            Field::StartedAt => "started_at",
+            Field::FinishedAt => "finished_at",
+            Field::AddedAt => "added_at",
+            Field::Tags => "tags",
+            Field::Review => "review",
+        }
+    }
+
+    /// Lowercase column headers the field is read from, unless another column is chosen
+    fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            Field::Name => &["name", "title"],
+            Field::Author => &["author", "authors"],
+            Field::Language => &["language", "lang"],
+            Field::Pages => &["pages", "num_pages", "number of pages"],
+            Field::Status => &["status", "reading status"],
+            Field::CurrentPage => &["current_page", "current page"],
+            Field::Rating => &["rating", "my rating"],
+            Field::StartedAt => &["started_at", "started", "date started"],
+            Field::FinishedAt => &["finished_at", "finished", "date finished", "date read"],
+            Field::AddedAt => &["added_at", "added", "date added"],
+            Field::Tags => &["tags"],
+            Field::Review => &["review", "my review"],
+        }
+    }
+}
+
+/// A book as exported, with dates like `2020-05-31` and tags by their comma-separated names
+#[derive(Serialize, Debug)]
+struct BookRecord {
+    name: String,
+    author: String,
+    language: String,
+    pages: usize,
+    status: &'static str,
+    current_page: usize,
+    rating: Option<usize>,
+    started_at: String,
+    finished_at: String,
+    added_at: String,
+    tags: String,
+    review: String,
+}
+
+impl BookRecord {
+    fn of(book: &Book, tags: &[Tag]) -> Self {
+        let tag_names: Vec<&str> = tags
+            .iter()
+            .filter(|t| book.tags.contains(&t.id))
+            .map(|t| t.name.as_str())
+            .collect();
+        BookRecord {
+            name: book.name.clone(),
+            author: book.author.clone(),
+            language: book.language.clone(),
+            pages: book.num_pages,
+            status: book.status.as_str(),
+            current_page: book.current_page,
+            rating: book.rating,
+            started_at: book.started_date(),
+            finished_at: book.finished_date(),
+            added_at: book.added_at.format(DATE_FORMAT).to_string(),
+            tags: tag_names.join(", "),
+            review: book.review.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Csv,
+    Json,
+    Goodreads,
+    StoryGraph,
+}
+
+impl ImportFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ImportFormat::Csv => "csv",
+            ImportFormat::Json => "json",
+            ImportFormat::Goodreads => "goodreads",
+            ImportFormat::StoryGraph => "storygraph",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            ImportFormat::Csv => "CSV",
+            ImportFormat::Json => "JSON",
+            ImportFormat::Goodreads => "Goodreads export",
+            ImportFormat::StoryGraph => "StoryGraph export",
+        }
+    }
+
+    fn is_mappable(&self) -> bool {
+        match self {
+            ImportFormat::Csv | ImportFormat::Json => true,
+            ImportFormat::Goodreads | ImportFormat::StoryGraph => false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStep {
+    Preview,
+    Import,
+}
+
+/// The uploaded data and the columns chosen for the book fields by their header, where an empty
+/// column skips the field. Columns which weren't chosen yet are guessed from the headers.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportForm {
+    pub format: ImportFormat,
+    pub data: String,
+    pub step: ImportStep,
+    pub name_column: Option<String>,
+    pub author_column: Option<String>,
+    pub language_column: Option<String>,
+    pub pages_column: Option<String>,
+    pub status_column: Option<String>,
+    pub current_page_column: Option<String>,
+    pub rating_column: Option<String>,
+    pub started_at_column: Option<String>,
+    pub finished_at_column: Option<String>,
+    pub added_at_column: Option<String>,
+    pub tags_column: Option<String>,
+    pub review_column: Option<String>,
+}
+
+impl ImportForm {
+    fn column(&self, field: Field) -> Option<&str> {
+        let column = match field {
+            Field::Name => &self.name_column,
+            Field::Author => &self.author_column,
+            Field::Language => &self.language_column,
+            Field::Pages => &self.pages_column,
+            Field::Status => &self.status_column,
+            Field::CurrentPage => &self.current_page_column,
+            Field::Rating => &self.rating_column,
+            Field::StartedAt => &self.started_at_column,
+            Field::FinishedAt => &self.finished_at_column,
+            Field::AddedAt => &self.added_at_column,
+            Field::Tags => &self.tags_column,
+            Field::Review => &self.review_column,
+        };
+        column.as_deref()
+    }
+}
+
+/// Uploaded rows as text cells, under the column headers
+#[derive(Debug, Default)]
+struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn parse(format: ImportFormat, data: &str) -> Result<Self, String> {
+        // spreadsheets like to start their CSV files with a byte order mark
+        let data = data.trim_start_matches('\u{feff}');
+        match format {
+            ImportFormat::Json => parse_json(data),
+            _ => parse_csv(data),
+        }
+    }
+}
+
+/// The column each book field is read from, by its index
+#[derive(Debug, Default)]
+struct Mapping(Vec<(Field, Option<usize>)>);
+
+impl Mapping {
+    fn new(form: &ImportForm, headers: &[String]) -> Self {
+        let columns = Field::ALL
+            .iter()
+            .map(|field| {
+                let column = match form.column(*field) {
+                    Some(header) => headers.iter().position(|h| h == header),
+                    None => headers
+                        .iter()
+                        .position(|h| field.aliases().contains(&h.trim().to_lowercase().as_str())),
+                };
+                (*field, column)
+            })
+            .collect();
+        Mapping(columns)
+    }
+
+    fn column(&self, field: Field) -> Option<usize> {
+        self.0
+            .iter()
+            .find(|(f, _)| *f == field)
+            .and_then(|(_, column)| *column)
+    }
+
+    fn cell<'r>(&self, row: &'r [String], field: Field) -> &'r str {
+        self.column(field)
+            .and_then(|column| row.get(column))
+            .map(|cell| cell.trim())
+            .unwrap_or_default()
+    }
+}
+
+/// Looks up cells by the column headers of a known layout, like the export of a reading site
+struct Layout<'t> {
+    headers: &'t [String],
+}
+
+impl<'t> Layout<'t> {
+    /// Fails unless all of the given columns are there
+    fn new(headers: &'t [String], required: &[&str], name: &str) -> Result<Self, String> {
+        for column in required {
+            if !headers.iter().any(|h| h == column) {
+                return Err(format!(
+                    "this is not a {}, the column \"{}\" is missing",
+                    name, column
+                ));
+            }
+        }
+        Ok(Layout { headers })
+    }
+
+    fn cell<'r>(&self, row: &'r [String], column: &str) -> &'r str {
+        self.headers
+            .iter()
+            .position(|h| h == column)
+            .and_then(|i| row.get(i))
+            .map(|cell| cell.trim())
+            .unwrap_or_default()
+    }
+}
+
+/// An uploaded row with the book read from it, which is only imported if the row has no errors
+/// and the book isn't a duplicate
+#[derive(Debug)]
+struct ImportRow {
+    /// position of the row in the upload, starting at 1
+    number: usize,
+    book: Book,
+    /// comma-separated names of the book's tags, which are created on import if missing
+    tag_names: String,
+    errors: Vec<String>,
+    duplicate: bool,
+}
+
+impl ImportRow {
+    fn is_importable(&self) -> bool {
+        self.errors.is_empty() && !self.duplicate
+    }
+
+    fn rating(&self) -> String {
+        self.book.rating.map(|r| r.to_string()).unwrap_or_default()
+    }
+
+    fn result(&self, done: bool) -> String {
+        if !self.errors.is_empty() {
+            return self.errors.join("; ");
+        }
+        if self.duplicate {
+            return "duplicate, skipped".to_owned();
+        }
+        if done {
+            "imported".to_owned()
+        } else {
+            "ok".to_owned()
+        }
+    }
+}
+
+pub async fn export_csv_handler(session: Session, db: DB) -> WebResult<impl Reply> {
+    let records = export_records(&session.user_id, &db).await?;
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for record in &records {
+        writer
+            .serialize(record)
+            .map_err(|e| reject::custom(CsvError(e)))?;
+    }
+    let body = writer
+        .into_inner()
+        .map_err(|e| reject::custom(CsvError(e.into_error().into())))?;
+    let res = reply::with_header(body, "content-type", "text/csv; charset=utf-8");
+    Ok(attachment(res, "books.csv"))
+}
+
+pub async fn export_json_handler(session: Session, db: DB) -> WebResult<impl Reply> {
+    let records = export_records(&session.user_id, &db).await?;
+    Ok(attachment(reply::json(&records), "books.json"))
+}
+
+pub async fn import_handler(session: Session, _db: DB) -> WebResult<impl Reply> {
+    let template = ImportTemplate {
+        format: ImportFormat::Csv.as_str(),
+        data: "",
+        mappable: true,
+        fields: &Field::ALL,
+        headers: &[],
+        mapping: &Mapping::default(),
+        rows: &[],
+        importable: 0,
+        done: false,
+        message: "",
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+/// Previews the upload with the chosen columns, or imports its valid rows once confirmed
+pub async fn do_import_handler(
+    session: Session,
+    body: ImportForm,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let table = match Table::parse(body.format, &body.data) {
+        Ok(table) => table,
+        Err(e) => {
+            let message = format!("Could not read the {}: {}", body.format.label(), e);
+            return render_import(&session, &body, &Table::default(), &[], false, &message);
+        }
+    };
+    if table.rows.len() > MAX_ROWS {
+        return Err(reject::custom(InvalidInputError(format!(
+            "at most {} rows can be imported at once",
+            MAX_ROWS
+        ))));
+    }
+
+    let mut rows = match read_rows(&body, &table) {
+        Ok(rows) => rows,
+        Err(e) => {
+            let message = format!("Could not read the {}: {}", body.format.label(), e);
+            return render_import(&session, &body, &Table::default(), &[], false, &message);
+        }
+    };
+    let existing = db
+        .fetch_books(&session.user_id, &BookQuery::all())
+        .await
+        .map_err(reject::custom)?;
+    mark_duplicates(&mut rows, &existing);
+    if body.step == ImportStep::Preview {
+        return render_import(&session, &body, &table, &rows, false, "");
+    }
+
+    let mut books = Vec::new();
+    for row in rows.iter().filter(|r| r.is_importable()) {
+        let tags = tags::resolve_tags(&row.tag_names, &session.user_id, &db).await?;
+        books.push(Book {
+            tags,
+            ..row.book.clone()
+        });
+    }
+    let imported = db
+        .import_books(&books, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let invalid = rows.iter().filter(|r| !r.errors.is_empty()).count();
+    let duplicates = rows.iter().filter(|r| r.duplicate).count();
+    let message = format!(
+        "Imported {} books, skipped {} rows with errors and {} duplicates.",
+        imported, invalid, duplicates
+    );
+    render_import(&session, &body, &table, &rows, true, &message)
+}
+
+fn render_import(
+    session: &Session,
+    form: &ImportForm,
+    table: &Table,
+    rows: &[ImportRow],
+    done: bool,
+    message: &str,
+) -> WebResult<warp::reply::Html<String>> {
+    let template = ImportTemplate {
+        format: form.format.as_str(),
+        data: &form.data,
+        mappable: form.format.is_mappable(),
+        fields: &Field::ALL,
+        headers: &table.headers,
+        mapping: &Mapping::new(form, &table.headers),
+        rows,
+        importable: rows.iter().filter(|r| r.is_importable()).count(),
+        done,
+        message,
+        csrf_token: &csrf::session_token(session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+async fn export_records(user_id: &str, db: &DB) -> WebResult<Vec<BookRecord>> {
+    let books = db
+        .fetch_books(user_id, &BookQuery::all())
+        .await
+        .map_err(reject::custom)?;
+    let tags = db.fetch_tags(user_id).await.map_err(reject::custom)?;
+    Ok(books.iter().map(|b| BookRecord::of(b, &tags)).collect())
+}
+
+/// Makes browsers download the reply as a file named `filename`
+pub fn attachment(reply: impl Reply, filename: &str) -> impl Reply {
+    reply::with_header(
+        reply,
+        "content-disposition",
+        format!("attachment; filename=\"{}\"", filename),
+    )
+}
+
+fn parse_csv(data: &str) -> Result<Table, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(data.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|h| h.trim().to_owned())
+        .collect();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        rows.push(record.iter().map(|c| c.to_owned()).collect());
+    }
+    Ok(Table { headers, rows })
+}
+
+/// Reads a list of objects, with the objects' keys as headers
+fn parse_json(data: &str) -> Result<Table, String> {
+    let entries: Vec<serde_json::Map<String, Value>> =
+        serde_json::from_str(data).map_err(|e| format!("expected a list of objects, {}", e))?;
+    let mut headers: Vec<String> = Vec::new();
+    for key in entries.iter().flat_map(|entry| entry.keys()) {
+        if !headers.contains(key) {
+            headers.push(key.clone());
+        }
+    }
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            headers
+                .iter()
+                .map(|h| entry.get(h).map(json_cell).unwrap_or_default())
+                .collect()
+        })
+        .collect();
+    Ok(Table { headers, rows })
+}
+
+/// Lists become comma-separated, like the tags of an export
+fn json_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values
+            .iter()
+            .map(json_cell)
+            .collect::<Vec<String>>()
+            .join(", "),
+        other => other.to_string(),
+    }
+}
+
+/// Reads the rows with the chosen columns, or by the layout of the format
+fn read_rows(form: &ImportForm, table: &Table) -> Result<Vec<ImportRow>, String> {
+    let now = Utc::now();
+    match form.format {
+        ImportFormat::Goodreads => goodreads::read_rows(table, &now),
+        ImportFormat::StoryGraph => storygraph::read_rows(table, &now),
+        ImportFormat::Csv | ImportFormat::Json => {
+            let mapping = Mapping::new(form, &table.headers);
+            let rows = table
+                .rows
+                .iter()
+                .enumerate()
+                .map(|(i, row)| read_row(i + 1, row, &mapping, &now))
+                .collect();
+            Ok(rows)
+        }
+    }
+}
+
+/// Reads the book from the row's mapped cells, collecting an error for every invalid cell
+fn read_row(number: usize, row: &[String], mapping: &Mapping, now: &DateTime<Utc>) -> ImportRow {
+    let cell = |field: Field| mapping.cell(row, field);
+    let mut errors = Vec::new();
+
+    let name = cell(Field::Name);
+    if name.is_empty() {
+        errors.push("name is missing".to_owned());
+    }
+    let author = cell(Field::Author);
+    if author.is_empty() {
+        errors.push("author is missing".to_owned());
+    }
+    let num_pages =
+        parse_number(cell(Field::Pages), Field::Pages.as_str(), &mut errors).unwrap_or(0);
+    let started_at = parse_date(
+        cell(Field::StartedAt),
+        Field::StartedAt.as_str(),
+        &mut errors,
+    );
+    let finished_at = parse_date(
+        cell(Field::FinishedAt),
+        Field::FinishedAt.as_str(),
+        &mut errors,
+    );
+    let added_at =
+        parse_date(cell(Field::AddedAt), Field::AddedAt.as_str(), &mut errors).unwrap_or(*now);
+
+    let status = match cell(Field::Status) {
+        "" if finished_at.is_some() => ReadingStatus::Finished,
+        "" if started_at.is_some() => ReadingStatus::Reading,
+        "" => ReadingStatus::WantToRead,
+        status => parse_status(status).unwrap_or_else(|| {
+            errors.push(format!("unknown status: {}", status));
+            ReadingStatus::WantToRead
+        }),
+    };
+    let current_page = parse_number(
+        cell(Field::CurrentPage),
+        Field::CurrentPage.as_str(),
+        &mut errors,
+    )
+    .unwrap_or(if status == ReadingStatus::Finished {
+        num_pages
+    } else {
+        0
+    });
+    let rating = parse_rating(cell(Field::Rating), Field::Rating.as_str(), &mut errors);
+
+    let book = Book {
+        status,
+        current_page,
+        started_at,
+        finished_at,
+        rating,
+        review: cell(Field::Review).to_owned(),
+        ..Book::new(
+            "",
+            "",
+            name,
+            author,
+            cell(Field::Language),
+            num_pages,
+            &added_at,
+        )
+    };
+    ImportRow {
+        number,
+        book,
+        tag_names: cell(Field::Tags).to_owned(),
+        errors,
+        duplicate: false,
+    }
+}
+
+fn parse_number(value: &str, name: &str, errors: &mut Vec<String>) -> Option<usize> {
+    if value.is_empty() {
+        return None;
+    }
+    match value.parse() {
+        Ok(number) => Some(number),
+        Err(_) => {
+            errors.push(format!("{} is not a number: {}", name, value));
+            None
+        }
+    }
+}
+
+/// Rounds ratings with fractions of stars, spreadsheets and reading sites often use 0 for books
+/// without a rating
+fn parse_rating(value: &str, name: &str, errors: &mut Vec<String>) -> Option<usize> {
+    if value.is_empty() {
+        return None;
+    }
+    let rating = match value.parse::<f64>() {
+        Ok(rating) => rating.round(),
+        Err(_) => {
+            errors.push(format!("{} is not a number: {}", name, value));
+            return None;
+        }
+    };
+    if rating == 0.0 {
+        return None;
+    }
+    if rating < MIN_RATING as f64 || rating > MAX_RATING as f64 {
+        errors.push(format!(
+            "{} must be between {} and {}",
+            name, MIN_RATING, MAX_RATING
+        ));
+        return None;
+    }
+    Some(rating as usize)
+}
+
+/// Reads dates like `2020-05-31` or `2020/05/31`, or with a time as in RFC 3339
+fn parse_date(value: &str, name: &str, errors: &mut Vec<String>) -> Option<DateTime<Utc>> {
+    if value.is_empty() {
+        return None;
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(value, DATE_FORMAT)
+        .or_else(|_| NaiveDate::parse_from_str(value, SLASHED_DATE_FORMAT));
+    match date {
+        Ok(date) => Some(start_of_day(date)),
+        Err(_) => {
+            errors.push(format!("{} is not a date like 2020-05-31: {}", name, value));
+            None
+        }
+    }
+}
+
+/// Accepts the exported statuses as well as their labels, like "want to read"
+fn parse_status(status: &str) -> Option<ReadingStatus> {
+    ReadingStatus::parse(&status.to_lowercase().replace([' ', '-'], "_"))
+}
+
+/// Marks the rows matching an existing book or an earlier row by name and author, ignoring case
+fn mark_duplicates(rows: &mut [ImportRow], existing: &[Book]) {
+    let mut seen: HashSet<(String, String)> = existing.iter().map(duplicate_key).collect();
+    for row in rows.iter_mut().filter(|r| r.errors.is_empty()) {
+        row.duplicate = !seen.insert(duplicate_key(&row.book));
+    }
+}
+
+fn duplicate_key(book: &Book) -> (String, String) {
+    (
+        book.name.trim().to_lowercase(),
+        book.author.trim().to_lowercase(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_number_reads_whole_numbers() {
+        let mut errors = Vec::new();
+        assert_eq!(parse_number("412", "pages", &mut errors), Some(412));
+        assert_eq!(parse_number("", "pages", &mut errors), None);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn parse_number_reports_invalid_numbers() {
+        let mut errors = Vec::new();
+        assert_eq!(parse_number("many", "pages", &mut errors), None);
+        assert_eq!(parse_number("-1", "pages", &mut errors), None);
+        assert_eq!(
+            errors,
+            vec!["pages is not a number: many", "pages is not a number: -1"]
+        );
+    }
+
+    #[test]
+    fn parse_rating_rounds_fractions() {
+        let mut errors = Vec::new();
+        assert_eq!(parse_rating("4", "rating", &mut errors), Some(4));
+        assert_eq!(parse_rating("3.5", "rating", &mut errors), Some(4));
+        assert_eq!(parse_rating("1.2", "rating", &mut errors), Some(1));
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn parse_rating_treats_zero_as_unrated() {
+        let mut errors = Vec::new();
+        assert_eq!(parse_rating("0", "rating", &mut errors), None);
+        assert_eq!(parse_rating("", "rating", &mut errors), None);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn parse_rating_reports_invalid_ratings() {
+        let mut errors = Vec::new();
+        assert_eq!(parse_rating("6", "rating", &mut errors), None);
+        assert_eq!(parse_rating("great", "rating", &mut errors), None);
+        assert_eq!(
+            errors,
+            vec![
+                "rating must be between 1 and 5",
+                "rating is not a number: great"
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_date_reads_dates_and_timestamps() {
+        let mut errors = Vec::new();
+        let expected = Utc.with_ymd_and_hms(2020, 5, 31, 0, 0, 0).unwrap();
+        assert_eq!(
+            parse_date("2020-05-31", "date", &mut errors),
+            Some(expected)
+        );
+        assert_eq!(
+            parse_date("2020/05/31", "date", &mut errors),
+            Some(expected)
+        );
+        assert_eq!(
+            parse_date("2020-05-31T14:30:00+02:00", "date", &mut errors),
+            Some(Utc.with_ymd_and_hms(2020, 5, 31, 12, 30, 0).unwrap())
+        );
+        assert_eq!(parse_date("", "date", &mut errors), None);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn parse_date_reports_invalid_dates() {
+        let mut errors = Vec::new();
+        assert_eq!(parse_date("31.05.2020", "date", &mut errors), None);
+        assert_eq!(parse_date("2020-02-30", "date", &mut errors), None);
+        assert_eq!(
+            errors,
+            vec![
+                "date is not a date like 2020-05-31: 31.05.2020",
+                "date is not a date like 2020-05-31: 2020-02-30"
+            ]
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! Reads the CSV export of The StoryGraph, as downloaded from "Manage Account" > "Export
+//! StoryGraph Library". The export has no page counts, so books are imported without them.
+
+use super::{parse_date, parse_rating, ImportRow, Layout, Table};
+use crate::data::{Book, ReadingStatus};
+use chrono::prelude::*;
+
+const TITLE: &str = "Title";
+const AUTHORS: &str = "Authors";
+const READ_STATUS: &str = "Read Status";
+const RATING: &str = "Star Rating";
+const REVIEW: &str = "Review";
+const DATE_ADDED: &str = "Date Added";
+const LAST_DATE_READ: &str = "Last Date Read";
+const DATES_READ: &str = "Dates Read";
+const TAGS: &str = "Tags";
+
+pub fn read_rows(table: &Table, now: &DateTime<Utc>) -> Result<Vec<ImportRow>, String> {
+    let layout = Layout::new(
+        &table.headers,
+        &[TITLE, AUTHORS, READ_STATUS],
+        "StoryGraph export",
+    )?;
+    let rows = table
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| read_row(i + 1, row, &layout, now))
+        .collect();
+    Ok(rows)
+}
+
+fn read_row(number: usize, row: &[String], layout: &Layout, now: &DateTime<Utc>) -> ImportRow {
+    let cell = |column: &str| layout.cell(row, column);
+    let mut errors = Vec::new();
+
+    let name = cell(TITLE);
+    if name.is_empty() {
+        errors.push("title is missing".to_owned());
+    }
+    let author = cell(AUTHORS);
+    if author.is_empty() {
+        errors.push("authors are missing".to_owned());
+    }
+    let rating = parse_rating(cell(RATING), RATING, &mut errors);
+    let added_at = parse_date(cell(DATE_ADDED), DATE_ADDED, &mut errors).unwrap_or(*now);
+
+    // the last of the comma-separated reads, like `2021/01/02-2021/03/14`
+    let last_read = cell(DATES_READ)
+        .rsplit(',')
+        .next()
+        .unwrap_or_default()
+        .trim();
+    let mut read_dates = last_read.splitn(2, '-').map(|date| date.trim());
+    let started_at = parse_date(
+        read_dates.next().unwrap_or_default(),
+        DATES_READ,
+        &mut errors,
+    );
+    let finished_at = match cell(LAST_DATE_READ) {
+        "" => parse_date(
+            read_dates.next().unwrap_or_default(),
+            DATES_READ,
+            &mut errors,
+        ),
+        date => parse_date(date, LAST_DATE_READ, &mut errors),
+    };
+
+    let status = match cell(READ_STATUS) {
+        "read" => ReadingStatus::Finished,
+        "currently-reading" | "paused" => ReadingStatus::Reading,
+        "to-read" => ReadingStatus::WantToRead,
+        "did-not-finish" => ReadingStatus::Abandoned,
+        status => {
+            errors.push(format!("unknown read status: {}", status));
+            ReadingStatus::WantToRead
+        }
+    };
+    let book = Book {
+        status,
+        started_at: started_at.filter(|_| status != ReadingStatus::WantToRead),
+        finished_at: finished_at.filter(|_| status == ReadingStatus::Finished),
+        rating,
+        review: cell(REVIEW).to_owned(),
+        ..Book::new("", "", name, author, "", 0, &added_at)
+    };
+    ImportRow {
+        number,
+        book,
+        tag_names: cell(TAGS).to_owned(),
+        errors,
+        duplicate: false,
+    }
+}
@@ -0,0 +1,15 @@
+//! Rendering of user-written Markdown, like book reviews and notes
+
+use pulldown_cmark::{html, Options, Parser};
+
+/// Renders Markdown to HTML, which is sanitized so it's safe to embed in pages unescaped
+pub fn to_html(text: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TABLES);
+    let parser = Parser::new_ext(text, options);
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser);
+    ammonia::clean(&unsafe_html)
+}
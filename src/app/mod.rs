@@ -8,15 +8,57 @@ use warp::{reject, reply::html, Reply};
 struct WelcomeTemplate<'a> {
     title: &'a str,
     body: &'a str,
+    csrf_token: &'a str,
 }
 
+#[derive(Template)]
+#[template(path = "confirm_delete.html")]
+struct ConfirmDeleteTemplate<'a> {
+    what: &'a str,
+    action: &'a str,
+    back: &'a str,
+    csrf_token: &'a str,
+}
+
+pub mod account;
 pub mod auth;
+pub mod backup;
 pub mod books;
+pub mod cookie;
+pub mod csrf;
+pub mod import;
+pub mod markdown;
+pub mod recipes;
+pub mod restaurants;
+pub mod tags;
+pub mod todos;
+pub mod tokens;
+pub mod validation;
 
-pub async fn welcome_handler(_session: Session) -> WebResult<impl Reply> {
+pub async fn welcome_handler(session: Session) -> WebResult<impl Reply> {
     let template = WelcomeTemplate {
         title: "Welcome",
         body: "To Toodeloo!",
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+/// Renders a page asking to confirm the deletion of `what`, which is done by posting to `action`
+pub fn confirm_delete(
+    session: &Session,
+    what: &str,
+    action: &str,
+    back: &str,
+) -> WebResult<impl Reply> {
+    let template = ConfirmDeleteTemplate {
+        what,
+        action,
+        back,
+        csrf_token: &csrf::session_token(session),
     };
     let res = template
         .render()
@@ -0,0 +1,332 @@
+use crate::app::{confirm_delete, csrf};
+use crate::{
+    data::{Ingredient, Recipe, Session},
+    error::Error::*,
+    WebResult, DB,
+};
+use askama::Template;
+use serde::{Deserialize, Serialize};
+use warp::{reject, reply::html, Reply};
+
+const NO_UNIT: &str = "-";
+
+#[derive(Template)]
+#[template(path = "recipe/list.html")]
+struct RecipelistTemplate<'a> {
+    recipes: &'a Vec<Recipe>,
+    csrf_token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "recipe/new.html")]
+struct NewRecipeTemplate<'a> {
+    csrf_token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "recipe/edit.html")]
+struct EditRecipeTemplate<'a> {
+    recipe: &'a Recipe,
+    ingredients: &'a str,
+    steps: &'a str,
+    csrf_token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "recipe/view.html")]
+struct ViewRecipeTemplate<'a> {
+    recipe: &'a Recipe,
+    servings: usize,
+    ingredients: &'a Vec<Ingredient>,
+    csrf_token: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NewRecipe {
+    pub title: String,
+    pub servings: i32,
+    pub ingredients: String,
+    pub steps: String,
+    pub prep_time: i32,
+    pub cook_time: i32,
+    pub source: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EditedRecipe {
+    pub title: String,
+    pub servings: i32,
+    pub ingredients: String,
+    pub steps: String,
+    pub prep_time: i32,
+    pub cook_time: i32,
+    pub source: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ScaleQuery {
+    pub servings: Option<usize>,
+}
+
+/// A recipe as submitted by a form, with parsed ingredients and steps
+#[derive(Debug)]
+pub struct RecipeEntry {
+    pub title: String,
+    pub servings: usize,
+    pub ingredients: Vec<Ingredient>,
+    pub steps: Vec<String>,
+    pub prep_time: usize,
+    pub cook_time: usize,
+    pub source: String,
+}
+
+impl RecipeEntry {
+    fn parse(
+        title: &str,
+        servings: i32,
+        ingredients: &str,
+        steps: &str,
+        prep_time: i32,
+        cook_time: i32,
+        source: &str,
+    ) -> WebResult<Self> {
+        if servings < 1 {
+            return Err(reject::custom(InvalidInputError(
+                "servings must be at least 1".to_owned(),
+            )));
+        }
+        if prep_time < 0 || cook_time < 0 {
+            return Err(reject::custom(InvalidInputError(
+                "times must not be negative".to_owned(),
+            )));
+        }
+        Ok(RecipeEntry {
+            title: title.to_owned(),
+            servings: servings as usize,
+            ingredients: parse_ingredients(ingredients)?,
+            steps: parse_steps(steps),
+            prep_time: prep_time as usize,
+            cook_time: cook_time as usize,
+            source: source.to_owned(),
+        })
+    }
+}
+
+impl NewRecipe {
+    fn to_entry(&self) -> WebResult<RecipeEntry> {
+        RecipeEntry::parse(
+            &self.title,
+            self.servings,
+            &self.ingredients,
+            &self.steps,
+            self.prep_time,
+            self.cook_time,
+            &self.source,
+        )
+    }
+}
+
+impl EditedRecipe {
+    fn to_entry(&self) -> WebResult<RecipeEntry> {
+        RecipeEntry::parse(
+            &self.title,
+            self.servings,
+            &self.ingredients,
+            &self.steps,
+            self.prep_time,
+            self.cook_time,
+            &self.source,
+        )
+    }
+}
+
+pub async fn recipes_list_handler(session: Session, db: DB) -> WebResult<impl Reply> {
+    let recipes = db
+        .fetch_recipes(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let template = RecipelistTemplate {
+        recipes: &recipes,
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn new_recipe_handler(session: Session, _db: DB) -> WebResult<impl Reply> {
+    let template = NewRecipeTemplate {
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn create_recipe_handler(
+    session: Session,
+    body: NewRecipe,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let entry = body.to_entry()?;
+    db.create_recipe(&entry, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    recipes_list_handler(session, db).await
+}
+
+pub async fn edit_recipe_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
+    let recipe = db
+        .fetch_recipe(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let ingredients = format_ingredients(&recipe.ingredients);
+    let steps = recipe.steps.join("\n");
+    let template = EditRecipeTemplate {
+        recipe: &recipe,
+        ingredients: &ingredients,
+        steps: &steps,
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn do_edit_recipe_handler(
+    session: Session,
+    id: String,
+    body: EditedRecipe,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let entry = body.to_entry()?;
+    db.edit_recipe(&id, &session.user_id, &entry)
+        .await
+        .map_err(reject::custom)?;
+    recipes_list_handler(session, db).await
+}
+
+pub async fn confirm_delete_recipe_handler(
+    session: Session,
+    id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let recipe = db
+        .fetch_recipe(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    confirm_delete(
+        &session,
+        &format!("the recipe \"{}\"", recipe.title),
+        &format!("/recipes/delete/{}", recipe.id),
+        "/recipes/list",
+    )
+}
+
+pub async fn delete_recipe_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
+    db.delete_recipe(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    recipes_list_handler(session, db).await
+}
+
+pub async fn view_recipe_handler(
+    session: Session,
+    id: String,
+    query: ScaleQuery,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let recipe = db
+        .fetch_recipe(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let servings = match query.servings {
+        Some(s) if s > 0 => s,
+        _ => recipe.servings,
+    };
+    let ingredients = recipe.scaled_ingredients(servings);
+    let template = ViewRecipeTemplate {
+        recipe: &recipe,
+        servings,
+        ingredients: &ingredients,
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+/// Parses one ingredient per line in the form `quantity unit name`, where a unit of `-` or a
+/// line with only `quantity name` means the ingredient has no unit
+fn parse_ingredients(input: &str) -> WebResult<Vec<Ingredient>> {
+    input
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 2 {
+                return Err(reject::custom(InvalidInputError(format!(
+                    "invalid ingredient: {}",
+                    line
+                ))));
+            }
+            let quantity = parse_quantity(parts[0]).ok_or_else(|| {
+                reject::custom(InvalidInputError(format!("invalid quantity: {}", parts[0])))
+            })?;
+            let (unit, name) = match parts.len() {
+                2 => ("", parts[1..].join(" ")),
+                _ if parts[1] == NO_UNIT => ("", parts[2..].join(" ")),
+                _ => (parts[1], parts[2..].join(" ")),
+            };
+            Ok(Ingredient {
+                quantity,
+                unit: unit.to_owned(),
+                name,
+            })
+        })
+        .collect()
+}
+
+/// Parses decimal quantities like `1.5` as well as fractions like `1/2`
+fn parse_quantity(input: &str) -> Option<f64> {
+    let quantity = match input.find('/') {
+        Some(idx) => {
+            let numerator: f64 = input[..idx].parse().ok()?;
+            let denominator: f64 = input[idx + 1..].parse().ok()?;
+            if denominator == 0.0 {
+                return None;
+            }
+            numerator / denominator
+        }
+        None => input.replace(',', ".").parse().ok()?,
+    };
+    if quantity < 0.0 {
+        return None;
+    }
+    Some(quantity)
+}
+
+fn parse_steps(input: &str) -> Vec<String> {
+    input
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_owned())
+        .collect()
+}
+
+fn format_ingredients(ingredients: &[Ingredient]) -> String {
+    ingredients
+        .iter()
+        .map(|i| {
+            let unit = if i.unit.is_empty() { NO_UNIT } else { &i.unit };
+            format!("{} {} {}", i.display_quantity(), unit, i.name)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
@@ -0,0 +1,254 @@
+use crate::app::{confirm_delete, csrf};
+use crate::{
+    data::{start_of_day, Restaurant, Session},
+    error::Error::*,
+    WebResult, DB,
+};
+use askama::Template;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use warp::{reject, reply::html, Reply};
+
+const MIN_RATING: i32 = 1;
+const MAX_RATING: i32 = 5;
+const MIN_PRICE_LEVEL: i32 = 1;
+const MAX_PRICE_LEVEL: i32 = 4;
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Template)]
+#[template(path = "restaurant/list.html")]
+struct RestaurantlistTemplate<'a> {
+    restaurants: &'a Vec<Restaurant>,
+    csrf_token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "restaurant/new.html")]
+struct NewRestaurantTemplate<'a> {
+    csrf_token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "restaurant/edit.html")]
+struct EditRestaurantTemplate<'a> {
+    restaurant: &'a Restaurant,
+    csrf_token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "restaurant/visits.html")]
+struct VisitsTemplate<'a> {
+    restaurant: &'a Restaurant,
+    today: &'a str,
+    csrf_token: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NewRestaurant {
+    pub name: String,
+    pub cuisine: String,
+    pub address: String,
+    pub price_level: i32,
+    pub rating: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EditedRestaurant {
+    pub name: String,
+    pub cuisine: String,
+    pub address: String,
+    pub price_level: i32,
+    pub rating: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NewVisit {
+    pub visited_at: String,
+    pub notes: String,
+}
+
+pub async fn restaurants_list_handler(session: Session, db: DB) -> WebResult<impl Reply> {
+    let restaurants = db
+        .fetch_restaurants(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let template = RestaurantlistTemplate {
+        restaurants: &restaurants,
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn new_restaurant_handler(session: Session, _db: DB) -> WebResult<impl Reply> {
+    let template = NewRestaurantTemplate {
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn create_restaurant_handler(
+    session: Session,
+    body: NewRestaurant,
+    db: DB,
+) -> WebResult<impl Reply> {
+    validate_levels(body.price_level, body.rating)?;
+    db.create_restaurant(&body, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    restaurants_list_handler(session, db).await
+}
+
+pub async fn edit_restaurant_handler(
+    session: Session,
+    id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let restaurant = db
+        .fetch_restaurant(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let template = EditRestaurantTemplate {
+        restaurant: &restaurant,
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn do_edit_restaurant_handler(
+    session: Session,
+    id: String,
+    body: EditedRestaurant,
+    db: DB,
+) -> WebResult<impl Reply> {
+    validate_levels(body.price_level, body.rating)?;
+    db.edit_restaurant(&id, &session.user_id, &body)
+        .await
+        .map_err(reject::custom)?;
+    restaurants_list_handler(session, db).await
+}
+
+pub async fn confirm_delete_restaurant_handler(
+    session: Session,
+    id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let restaurant = db
+        .fetch_restaurant(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    confirm_delete(
+        &session,
+        &format!("the restaurant \"{}\"", restaurant.name),
+        &format!("/restaurants/delete/{}", restaurant.id),
+        "/restaurants/list",
+    )
+}
+
+pub async fn delete_restaurant_handler(
+    session: Session,
+    id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    db.delete_restaurant(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    restaurants_list_handler(session, db).await
+}
+
+pub async fn visits_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
+    let mut restaurant = db
+        .fetch_restaurant(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    restaurant.visits.sort_by_key(|v| Reverse(v.visited_at));
+    let today = Utc::now().format(DATE_FORMAT).to_string();
+    let template = VisitsTemplate {
+        restaurant: &restaurant,
+        today: &today,
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn add_visit_handler(
+    session: Session,
+    id: String,
+    body: NewVisit,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let date = NaiveDate::parse_from_str(&body.visited_at, DATE_FORMAT)
+        .map_err(|_| reject::custom(InvalidInputError(body.visited_at.clone())))?;
+    let visited_at = start_of_day(date);
+    db.add_visit(&id, &session.user_id, &visited_at, &body.notes)
+        .await
+        .map_err(reject::custom)?;
+    visits_handler(session, id, db).await
+}
+
+pub async fn confirm_delete_visit_handler(
+    session: Session,
+    id: String,
+    visit_id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let restaurant = db
+        .fetch_restaurant(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let visit = restaurant
+        .visits
+        .iter()
+        .find(|v| v.id == visit_id)
+        .ok_or_else(|| reject::custom(NoEntryFoundError(visit_id.clone())))?;
+    confirm_delete(
+        &session,
+        &format!(
+            "the visit at \"{}\" on {}",
+            restaurant.name,
+            visit.visited_at.format(DATE_FORMAT)
+        ),
+        &format!("/restaurants/visits/{}/delete/{}", restaurant.id, visit.id),
+        &format!("/restaurants/visits/{}", restaurant.id),
+    )
+}
+
+pub async fn delete_visit_handler(
+    session: Session,
+    id: String,
+    visit_id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    db.delete_visit(&id, &session.user_id, &visit_id)
+        .await
+        .map_err(reject::custom)?;
+    visits_handler(session, id, db).await
+}
+
+fn validate_levels(price_level: i32, rating: i32) -> WebResult<()> {
+    if !(MIN_PRICE_LEVEL..=MAX_PRICE_LEVEL).contains(&price_level) {
+        return Err(reject::custom(InvalidInputError(format!(
+            "price level must be between {} and {}",
+            MIN_PRICE_LEVEL, MAX_PRICE_LEVEL
+        ))));
+    }
+    if !(MIN_RATING..=MAX_RATING).contains(&rating) {
+        return Err(reject::custom(InvalidInputError(format!(
+            "rating must be between {} and {}",
+            MIN_RATING, MAX_RATING
+        ))));
+    }
+    Ok(())
+}
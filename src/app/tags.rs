@@ -0,0 +1,247 @@
+use crate::app::{confirm_delete, csrf};
+use crate::{
+    data::{Session, Tag},
+    error::Error::*,
+    WebResult, DB,
+};
+use askama::Template;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use warp::{reject, reply, reply::html, Reply};
+
+const DEFAULT_COLOR: &str = "#888888";
+const MAX_NAME_LENGTH: usize = 40;
+const MAX_SUGGESTIONS: usize = 10;
+
+#[derive(Template)]
+#[template(path = "tag/list.html")]
+struct TaglistTemplate<'a> {
+    tags: &'a Vec<Tag>,
+    default_color: &'a str,
+    csrf_token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "tag/edit.html")]
+struct EditTagTemplate<'a> {
+    tag: &'a Tag,
+    others: &'a Vec<Tag>,
+    csrf_token: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagEntry {
+    pub name: String,
+    pub color: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MergeTag {
+    pub into: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AutocompleteQuery {
+    pub q: Option<String>,
+}
+
+pub async fn tags_list_handler(session: Session, db: DB) -> WebResult<impl Reply> {
+    let tags = db
+        .fetch_tags(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let template = TaglistTemplate {
+        tags: &tags,
+        default_color: DEFAULT_COLOR,
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn create_tag_handler(session: Session, body: TagEntry, db: DB) -> WebResult<impl Reply> {
+    let entry = validate_entry(&body)?;
+    db.create_tag(&entry, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    tags_list_handler(session, db).await
+}
+
+pub async fn edit_tag_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
+    let tag = db
+        .fetch_tag(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let others = db
+        .fetch_tags(&session.user_id)
+        .await
+        .map_err(reject::custom)?
+        .into_iter()
+        .filter(|t| t.id != tag.id)
+        .collect();
+    let template = EditTagTemplate {
+        tag: &tag,
+        others: &others,
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn do_edit_tag_handler(
+    session: Session,
+    id: String,
+    body: TagEntry,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let entry = validate_entry(&body)?;
+    db.edit_tag(&id, &session.user_id, &entry)
+        .await
+        .map_err(reject::custom)?;
+    tags_list_handler(session, db).await
+}
+
+pub async fn merge_tag_handler(
+    session: Session,
+    id: String,
+    body: MergeTag,
+    db: DB,
+) -> WebResult<impl Reply> {
+    if body.into == id {
+        return Err(reject::custom(InvalidInputError(
+            "a tag can't be merged into itself".to_owned(),
+        )));
+    }
+    // makes sure the target exists and belongs to the user, before any item is changed
+    db.fetch_tag(&body.into, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    db.merge_tag(&id, &session.user_id, &body.into)
+        .await
+        .map_err(reject::custom)?;
+    tags_list_handler(session, db).await
+}
+
+pub async fn confirm_delete_tag_handler(
+    session: Session,
+    id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let tag = db
+        .fetch_tag(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    confirm_delete(
+        &session,
+        &format!(
+            "the tag \"{}\", it will be removed from all items",
+            tag.name
+        ),
+        &format!("/tags/delete/{}", tag.id),
+        "/tags/list",
+    )
+}
+
+pub async fn delete_tag_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
+    db.delete_tag(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    tags_list_handler(session, db).await
+}
+
+/// Returns the names of the user's tags starting with `q` as JSON, followed by the ones
+/// containing it
+pub async fn autocomplete_handler(
+    session: Session,
+    query: AutocompleteQuery,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let q = query.q.unwrap_or_default().trim().to_lowercase();
+    let tags = db
+        .fetch_tags(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let (mut names, contained): (Vec<String>, Vec<String>) = tags
+        .into_iter()
+        .map(|t| t.name)
+        .filter(|name| name.to_lowercase().contains(&q))
+        .partition(|name| name.to_lowercase().starts_with(&q));
+    names.extend(contained);
+    names.truncate(MAX_SUGGESTIONS);
+    Ok(reply::json(&names))
+}
+
+/// Turns comma-separated tag names into the ids of the user's tags, creating missing tags. All
+/// names are validated before any tag is created.
+pub async fn resolve_tags(names: &str, user_id: &str, db: &DB) -> WebResult<Vec<String>> {
+    let entries = names
+        .split(',')
+        .filter(|n| !n.trim().is_empty())
+        .map(|name| {
+            validate_entry(&TagEntry {
+                name: name.to_owned(),
+                color: DEFAULT_COLOR.to_owned(),
+            })
+        })
+        .collect::<WebResult<Vec<TagEntry>>>()?;
+    let mut tags = db.fetch_tags(user_id).await.map_err(reject::custom)?;
+    let mut ids: Vec<String> = Vec::new();
+    for entry in entries {
+        let id = match tags.iter().find(|t| same_name(&t.name, &entry.name)) {
+            Some(tag) => tag.id.clone(),
+            None => {
+                let id = db
+                    .create_tag(&entry, user_id)
+                    .await
+                    .map_err(reject::custom)?;
+                tags.push(Tag {
+                    id: id.clone(),
+                    user_id: user_id.to_owned(),
+                    name: entry.name,
+                    color: entry.color,
+                    added_at: Utc::now(),
+                });
+                id
+            }
+        };
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+/// Tag names are unique per user ignoring case, so "SciFi" and "scifi" are the same tag
+pub fn same_name(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+/// Returns the trimmed entry, if the name is usable in comma-separated lists and the color is
+/// a hex color like `#3366cc`
+fn validate_entry(entry: &TagEntry) -> WebResult<TagEntry> {
+    let name = entry.name.trim();
+    if name.is_empty() || name.contains(',') || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(reject::custom(InvalidInputError(format!(
+            "tag names must not be empty, contain commas or be longer than {} characters",
+            MAX_NAME_LENGTH
+        ))));
+    }
+    let color = entry.color.trim().to_lowercase();
+    let is_hex_color = color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if !is_hex_color {
+        return Err(reject::custom(InvalidInputError(format!(
+            "invalid color: {}",
+            entry.color
+        ))));
+    }
+    Ok(TagEntry {
+        name: name.to_owned(),
+        color,
+    })
+}
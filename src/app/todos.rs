@@ -0,0 +1,226 @@
+use crate::app::{confirm_delete, csrf};
+use crate::{
+    data::{start_of_day, Priority, Session, Todo},
+    error::Error::*,
+    WebResult, DB,
+};
+use askama::Template;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use warp::{reject, reply::html, Reply};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Template)]
+#[template(path = "todo/list.html")]
+struct TodolistTemplate<'a> {
+    todos: &'a Vec<Todo>,
+    filter: &'a str,
+    csrf_token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "todo/new.html")]
+struct NewTodoTemplate<'a> {
+    csrf_token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "todo/edit.html")]
+struct EditTodoTemplate<'a> {
+    todo: &'a Todo,
+    csrf_token: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TodoFilter {
+    Open,
+    Overdue,
+    Done,
+}
+
+impl TodoFilter {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TodoFilter::Open => "open",
+            TodoFilter::Overdue => "overdue",
+            TodoFilter::Done => "done",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TodoListQuery {
+    pub filter: Option<TodoFilter>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NewTodo {
+    pub title: String,
+    pub description: String,
+    pub due_at: String,
+    pub priority: Priority,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EditedTodo {
+    pub title: String,
+    pub description: String,
+    pub due_at: String,
+    pub priority: Priority,
+}
+
+/// A todo as submitted by a form, with a parsed due date
+#[derive(Debug)]
+pub struct TodoEntry {
+    pub title: String,
+    pub description: String,
+    pub due_at: Option<DateTime<Utc>>,
+    pub priority: Priority,
+}
+
+impl TodoEntry {
+    fn parse(title: &str, description: &str, due_at: &str, priority: Priority) -> WebResult<Self> {
+        let due_at = match due_at.trim() {
+            "" => None,
+            date => {
+                let date = NaiveDate::parse_from_str(date, DATE_FORMAT)
+                    .map_err(|_| reject::custom(InvalidInputError(date.to_owned())))?;
+                Some(start_of_day(date))
+            }
+        };
+        Ok(TodoEntry {
+            title: title.to_owned(),
+            description: description.to_owned(),
+            due_at,
+            priority,
+        })
+    }
+}
+
+pub async fn todos_list_handler(
+    session: Session,
+    query: TodoListQuery,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let filter = query.filter.unwrap_or(TodoFilter::Open);
+    let mut todos = db
+        .fetch_todos(&session.user_id, filter)
+        .await
+        .map_err(reject::custom)?;
+    if filter != TodoFilter::Done {
+        // todos without a due date go last, ties are broken by priority
+        todos.sort_by_key(|t| (t.due_at.is_none(), t.due_at, priority_rank(t.priority)));
+    }
+    let template = TodolistTemplate {
+        todos: &todos,
+        filter: filter.as_str(),
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn new_todo_handler(session: Session, _db: DB) -> WebResult<impl Reply> {
+    let template = NewTodoTemplate {
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn create_todo_handler(session: Session, body: NewTodo, db: DB) -> WebResult<impl Reply> {
+    let entry = TodoEntry::parse(&body.title, &body.description, &body.due_at, body.priority)?;
+    db.create_todo(&entry, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    todos_list_handler(session, TodoListQuery { filter: None }, db).await
+}
+
+pub async fn edit_todo_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
+    let todo = db
+        .fetch_todo(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let template = EditTodoTemplate {
+        todo: &todo,
+        csrf_token: &csrf::session_token(&session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
+
+pub async fn do_edit_todo_handler(
+    session: Session,
+    id: String,
+    body: EditedTodo,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let entry = TodoEntry::parse(&body.title, &body.description, &body.due_at, body.priority)?;
+    db.edit_todo(&id, &session.user_id, &entry)
+        .await
+        .map_err(reject::custom)?;
+    todos_list_handler(session, TodoListQuery { filter: None }, db).await
+}
+
+pub async fn toggle_todo_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
+    let todo = db
+        .fetch_todo(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    db.set_todo_done(&id, &session.user_id, !todo.done)
+        .await
+        .map_err(reject::custom)?;
+    let filter = if todo.done {
+        TodoFilter::Done
+    } else {
+        TodoFilter::Open
+    };
+    todos_list_handler(
+        session,
+        TodoListQuery {
+            filter: Some(filter),
+        },
+        db,
+    )
+    .await
+}
+
+pub async fn confirm_delete_todo_handler(
+    session: Session,
+    id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let todo = db
+        .fetch_todo(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    confirm_delete(
+        &session,
+        &format!("the todo \"{}\"", todo.title),
+        &format!("/todos/delete/{}", todo.id),
+        "/todos/list",
+    )
+}
+
+pub async fn delete_todo_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
+    db.delete_todo(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    todos_list_handler(session, TodoListQuery { filter: None }, db).await
+}
+
+fn priority_rank(priority: Priority) -> u8 {
+    match priority {
+        Priority::High => 0,
+        Priority::Medium => 1,
+        Priority::Low => 2,
+    }
+}
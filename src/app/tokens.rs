@@ -0,0 +1,108 @@
+use crate::app::{confirm_delete, csrf};
+use crate::{
+    data::{ApiToken, Session},
+    error::Error::*,
+    WebResult, DB,
+};
+use askama::Template;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use warp::{reject, reply::html, Reply};
+
+const TOKEN_PREFIX: &str = "tdl_";
+const MAX_NAME_LENGTH: usize = 100;
+
+#[derive(Template)]
+#[template(path = "tokens.html")]
+struct TokensTemplate<'a> {
+    tokens: &'a Vec<ApiToken>,
+    new_token: &'a str,
+    csrf_token: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NewToken {
+    pub name: String,
+}
+
+pub async fn tokens_handler(session: Session, db: DB) -> WebResult<impl Reply> {
+    render_tokens(&session, "", &db).await
+}
+
+pub async fn create_token_handler(
+    session: Session,
+    body: NewToken,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(reject::custom(InvalidInputError(format!(
+            "token name must have between 1 and {} characters",
+            MAX_NAME_LENGTH
+        ))));
+    }
+    let token = generate_token();
+    db.create_token(&session.user_id, name, &hash_token(&token))
+        .await
+        .map_err(reject::custom)?;
+    render_tokens(&session, &token, &db).await
+}
+
+pub async fn confirm_revoke_token_handler(
+    session: Session,
+    id: String,
+    db: DB,
+) -> WebResult<impl Reply> {
+    let tokens = db
+        .fetch_tokens(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let token = tokens
+        .iter()
+        .find(|t| t.id == id)
+        .ok_or_else(|| reject::custom(NoEntryFoundError(id.clone())))?;
+    confirm_delete(
+        &session,
+        &format!("the API token \"{}\"", token.name),
+        &format!("/account/tokens/revoke/{}", token.id),
+        "/account/tokens",
+    )
+}
+
+pub async fn revoke_token_handler(session: Session, id: String, db: DB) -> WebResult<impl Reply> {
+    db.delete_token(&id, &session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    render_tokens(&session, "", &db).await
+}
+
+/// Hashes a token for storage and lookup, a fast hash is sufficient, since tokens are random
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn generate_token() -> String {
+    format!(
+        "{}{}{}",
+        TOKEN_PREFIX,
+        Uuid::new_v4().to_simple(),
+        Uuid::new_v4().to_simple()
+    )
+}
+
+async fn render_tokens(session: &Session, new_token: &str, db: &DB) -> WebResult<impl Reply> {
+    let tokens = db
+        .fetch_tokens(&session.user_id)
+        .await
+        .map_err(reject::custom)?;
+    let template = TokensTemplate {
+        tokens: &tokens,
+        new_token,
+        csrf_token: &csrf::session_token(session),
+    };
+    let res = template
+        .render()
+        .map_err(|e| reject::custom(TemplateError(e)))?;
+    Ok(html(res))
+}
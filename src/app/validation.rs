@@ -0,0 +1,137 @@
+//! Validation of submitted input, collecting a message per invalid field so forms can be
+//! rendered again with the submitted values and the messages next to the fields.
+
+use std::fmt;
+
+/// Messages for the invalid fields of a form, by field name
+#[derive(Debug, Default)]
+pub struct FieldErrors {
+    errors: Vec<(&'static str, String)>,
+}
+
+impl FieldErrors {
+    /// Adds a message for the field, keeping only the first message per field
+    pub fn add(&mut self, field: &'static str, message: String) {
+        if self.get(field).is_empty() {
+            self.errors.push((field, message));
+        }
+    }
+
+    pub fn extend(&mut self, other: FieldErrors) {
+        for (field, message) in other.errors {
+            self.add(field, message);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Returns the message for the field, which is empty if the field is valid
+    pub fn get(&self, field: &str) -> &str {
+        self.errors
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map_or("", |(_, message)| message.as_str())
+    }
+
+    /// Checks that the text isn't blank and has at most `max_length` characters
+    pub fn check_required(&mut self, field: &'static str, value: &str, max_length: usize) {
+        if value.trim().is_empty() {
+            self.add(field, "is required".to_owned());
+        } else {
+            self.check_length(field, value, max_length);
+        }
+    }
+
+    /// Checks that the text has at most `max_length` characters
+    pub fn check_length(&mut self, field: &'static str, value: &str, max_length: usize) {
+        if value.chars().count() > max_length {
+            self.add(
+                field,
+                format!("must not be longer than {} characters", max_length),
+            );
+        }
+    }
+
+    /// Checks that the number is between `min` and `max`, both inclusive
+    pub fn check_range(&mut self, field: &'static str, value: i32, min: i32, max: i32) {
+        if value < min || value > max {
+            self.add(field, format!("must be between {} and {}", min, max));
+        }
+    }
+
+    /// Parses a whole number, returning 0 and adding a message if it isn't one
+    pub fn parse_number(&mut self, field: &'static str, value: &str) -> i32 {
+        value.trim().parse().unwrap_or_else(|_| {
+            self.add(field, "must be a whole number".to_owned());
+            0
+        })
+    }
+}
+
+/// Lists all messages, like "name is required, pages must be a whole number"
+impl fmt::Display for FieldErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<String> = self
+            .errors
+            .iter()
+            .map(|(field, message)| format!("{} {}", field, message))
+            .collect();
+        write!(f, "{}", messages.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_first_message_per_field() {
+        let mut errors = FieldErrors::default();
+        assert!(errors.is_empty());
+        errors.add("name", "is required".to_owned());
+        errors.add("name", "must not be longer than 5 characters".to_owned());
+        assert!(!errors.is_empty());
+        assert_eq!(errors.get("name"), "is required");
+        assert_eq!(errors.get("pages"), "");
+    }
+
+    #[test]
+    fn extend_keeps_existing_messages() {
+        let mut errors = FieldErrors::default();
+        errors.add("pages", "must be a whole number".to_owned());
+        let mut other = FieldErrors::default();
+        other.add("pages", "must be between 0 and 10".to_owned());
+        other.add("name", "is required".to_owned());
+        errors.extend(other);
+        assert_eq!(
+            errors.to_string(),
+            "pages must be a whole number, name is required"
+        );
+    }
+
+    #[test]
+    fn checks_required_text_and_length() {
+        let mut errors = FieldErrors::default();
+        errors.check_required("name", "  ", 5);
+        errors.check_required("author", "Frank Herbert", 5);
+        errors.check_length("language", "Deutsch", 7);
+        assert_eq!(errors.get("name"), "is required");
+        assert_eq!(errors.get("author"), "must not be longer than 5 characters");
+        assert_eq!(errors.get("language"), "");
+    }
+
+    #[test]
+    fn checks_ranges_and_numbers() {
+        let mut errors = FieldErrors::default();
+        errors.check_range("rating", 6, 1, 5);
+        errors.check_range("pages", 5, 0, 5);
+        assert_eq!(errors.parse_number("year", " 1965 "), 1965);
+        assert_eq!(errors.parse_number("count", "some"), 0);
+        assert_eq!(errors.get("rating"), "must be between 1 and 5");
+        assert_eq!(errors.get("pages"), "");
+        assert_eq!(errors.get("year"), "");
+        assert_eq!(errors.get("count"), "must be a whole number");
+    }
+}
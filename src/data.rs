@@ -0,0 +1,434 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Book {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub author: String,
+    pub language: String,
+    pub num_pages: usize,
+    pub added_at: DateTime<Utc>,
+    pub status: ReadingStatus,
+    pub current_page: usize,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub progress: Vec<ProgressEntry>,
+    pub rating: Option<usize>,
+    /// the reader's review, in Markdown
+    pub review: String,
+    pub notes: Vec<BookNote>,
+    /// ids of the user's tags assigned to the book
+    pub tags: Vec<String>,
+}
+
+impl Book {
+    /// Creates a book nobody started reading yet
+    pub fn new(
+        id: &str,
+        user_id: &str,
+        name: &str,
+        author: &str,
+        language: &str,
+        num_pages: usize,
+        added_at: &DateTime<Utc>,
+    ) -> Self {
+        Book {
+            id: id.to_owned(),
+            user_id: user_id.to_owned(),
+            name: name.to_owned(),
+            author: author.to_owned(),
+            language: language.to_owned(),
+            num_pages,
+            added_at: *added_at,
+            status: ReadingStatus::WantToRead,
+            current_page: 0,
+            started_at: None,
+            finished_at: None,
+            progress: Vec::new(),
+            rating: None,
+            review: String::new(),
+            notes: Vec::new(),
+            tags: Vec::new(),
+        }
+    }
+
+    /// Percentage of pages read, 0 for books without a page count
+    pub fn progress_percent(&self) -> usize {
+        if self.num_pages == 0 {
+            return 0;
+        }
+        (self.current_page * 100 / self.num_pages).min(100)
+    }
+
+    pub fn started_date(&self) -> String {
+        self.started_at
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn finished_date(&self) -> String {
+        self.finished_at
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadingStatus {
+    WantToRead,
+    Reading,
+    Finished,
+    Abandoned,
+}
+
+impl ReadingStatus {
+    pub const ALL: [ReadingStatus; 4] = [
+        ReadingStatus::WantToRead,
+        ReadingStatus::Reading,
+        ReadingStatus::Finished,
+        ReadingStatus::Abandoned,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadingStatus::WantToRead => "want_to_read",
+            ReadingStatus::Reading => "reading",
+            ReadingStatus::Finished => "finished",
+            ReadingStatus::Abandoned => "abandoned",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReadingStatus::WantToRead => "want to read",
+            ReadingStatus::Reading => "reading",
+            ReadingStatus::Finished => "finished",
+            ReadingStatus::Abandoned => "abandoned",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "want_to_read" => Some(ReadingStatus::WantToRead),
+            "reading" => Some(ReadingStatus::Reading),
+            "finished" => Some(ReadingStatus::Finished),
+            "abandoned" => Some(ReadingStatus::Abandoned),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteKind {
+    Note,
+    Quote,
+}
+
+impl NoteKind {
+    pub const ALL: [NoteKind; 2] = [NoteKind::Note, NoteKind::Quote];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NoteKind::Note => "note",
+            NoteKind::Quote => "quote",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "note" => Some(NoteKind::Note),
+            "quote" => Some(NoteKind::Quote),
+            _ => None,
+        }
+    }
+}
+
+/// A note on or a quote from a book, optionally with the page it refers to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BookNote {
+    pub id: String,
+    pub kind: NoteKind,
+    /// the note's text, in Markdown
+    pub text: String,
+    pub page: Option<usize>,
+    pub added_at: DateTime<Utc>,
+}
+
+/// A dated entry of the reading log, recording the page the reader got to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProgressEntry {
+    pub id: String,
+    pub logged_at: DateTime<Utc>,
+    pub page: usize,
+}
+
+/// A label of the user, which can be assigned to any kind of tracked item
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tag {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    /// hex color like `#3366cc`
+    pub color: String,
+    pub added_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct User {
+    pub id: String,
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    pub id: String,
+    pub session_id: String,
+    pub user_id: String,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Restaurant {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub cuisine: String,
+    pub address: String,
+    pub price_level: usize,
+    pub rating: usize,
+    pub visits: Vec<Visit>,
+    pub added_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Visit {
+    pub id: String,
+    pub visited_at: DateTime<Utc>,
+    pub notes: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Recipe {
+    pub id: String,
+    pub user_id: String,
+    pub title: String,
+    pub servings: usize,
+    pub ingredients: Vec<Ingredient>,
+    pub steps: Vec<String>,
+    pub prep_time: usize,
+    pub cook_time: usize,
+    pub source: String,
+    pub added_at: DateTime<Utc>,
+}
+
+impl Recipe {
+    /// Returns the ingredients with their quantities rescaled to the given servings
+    pub fn scaled_ingredients(&self, servings: usize) -> Vec<Ingredient> {
+        let factor = if self.servings == 0 {
+            1.0
+        } else {
+            servings as f64 / self.servings as f64
+        };
+        self.ingredients
+            .iter()
+            .map(|i| Ingredient {
+                quantity: i.quantity * factor,
+                unit: i.unit.clone(),
+                name: i.name.clone(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Ingredient {
+    pub quantity: f64,
+    pub unit: String,
+    pub name: String,
+}
+
+impl Ingredient {
+    /// Formats the quantity with at most two decimals and without trailing zeros
+    pub fn display_quantity(&self) -> String {
+        let formatted = format!("{:.2}", self.quantity);
+        formatted
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_owned()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+        }
+    }
+
+    pub fn parse(priority: &str) -> Option<Self> {
+        match priority {
+            "low" => Some(Priority::Low),
+            "medium" => Some(Priority::Medium),
+            "high" => Some(Priority::High),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Todo {
+    pub id: String,
+    pub user_id: String,
+    pub title: String,
+    pub description: String,
+    pub due_at: Option<DateTime<Utc>>,
+    pub priority: Priority,
+    pub done: bool,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub added_at: DateTime<Utc>,
+}
+
+impl Todo {
+    pub fn is_overdue(&self) -> bool {
+        match self.due_at {
+            Some(due_at) => !self.done && due_at.date_naive() < Utc::now().date_naive(),
+            None => false,
+        }
+    }
+
+    pub fn due_date(&self) -> String {
+        self.due_at
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// The user an API request was authenticated as, either by session or API token
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiUser {
+    pub user_id: String,
+}
+
+/// Midnight at the start of the day, in UTC
+pub fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(servings: usize) -> Recipe {
+        Recipe {
+            id: "1".to_owned(),
+            user_id: "1".to_owned(),
+            title: "Pancakes".to_owned(),
+            servings,
+            ingredients: vec![
+                Ingredient {
+                    quantity: 200.0,
+                    unit: "g".to_owned(),
+                    name: "flour".to_owned(),
+                },
+                Ingredient {
+                    quantity: 3.0,
+                    unit: String::new(),
+                    name: "eggs".to_owned(),
+                },
+            ],
+            steps: Vec::new(),
+            prep_time: 10,
+            cook_time: 20,
+            source: String::new(),
+            added_at: Utc::now(),
+        }
+    }
+
+    fn quantities(ingredients: &[Ingredient]) -> Vec<String> {
+        ingredients
+            .iter()
+            .map(Ingredient::display_quantity)
+            .collect()
+    }
+
+    #[test]
+    fn scales_quantities_to_servings() {
+        let recipe = recipe(4);
+        assert_eq!(quantities(&recipe.scaled_ingredients(4)), vec!["200", "3"]);
+        assert_eq!(
+            quantities(&recipe.scaled_ingredients(2)),
+            vec!["100", "1.5"]
+        );
+        assert_eq!(
+            quantities(&recipe.scaled_ingredients(6)),
+            vec!["300", "4.5"]
+        );
+        assert_eq!(
+            quantities(&recipe.scaled_ingredients(3)),
+            vec!["150", "2.25"]
+        );
+    }
+
+    #[test]
+    fn keeps_quantities_without_servings() {
+        let recipe = recipe(0);
+        assert_eq!(quantities(&recipe.scaled_ingredients(2)), vec!["200", "3"]);
+    }
+
+    #[test]
+    fn displays_quantities_without_trailing_zeros() {
+        let ingredient = |quantity| Ingredient {
+            quantity,
+            unit: String::new(),
+            name: String::new(),
+        };
+        assert_eq!(ingredient(1.0).display_quantity(), "1");
+        assert_eq!(ingredient(0.5).display_quantity(), "0.5");
+        assert_eq!(ingredient(1.0 / 3.0).display_quantity(), "0.33");
+    }
+
+    #[test]
+    fn progress_percent_is_capped() {
+        let mut book = Book::new(
+            "1",
+            "1",
+            "Dune",
+            "Frank Herbert",
+            "English",
+            400,
+            &Utc::now(),
+        );
+        book.current_page = 100;
+        assert_eq!(book.progress_percent(), 25);
+        book.current_page = 500;
+        assert_eq!(book.progress_percent(), 100);
+        book.num_pages = 0;
+        assert_eq!(book.progress_percent(), 0);
+    }
+}
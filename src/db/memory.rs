@@ -0,0 +1,849 @@
+//! In-memory storage backend, used for running the app without a database
+
+use crate::app::books::{BookQuery, BookSort, EditedBook, NewBook, ReadingState, SortDirection};
+use crate::app::recipes::RecipeEntry;
+use crate::app::restaurants::{EditedRestaurant, NewRestaurant};
+use crate::app::tags::{same_name, TagEntry};
+use crate::app::todos::{TodoEntry, TodoFilter};
+use crate::data::{
+    start_of_day, ApiToken, Book, BookNote, NoteKind, ProgressEntry, Recipe, Restaurant, Session,
+    Tag, Todo, User, Visit,
+};
+use crate::db::{
+    session_expiry, BookStore, RecipeStore, RestaurantStore, SessionStore, Store, TagStore,
+    TodoStore, TokenStore, UserStore,
+};
+use crate::{error::Error::*, Result};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use uuid::Uuid;
+
+#[derive(Default)]
+struct Data {
+    books: HashMap<String, Book>,
+    users: HashMap<String, User>,
+    sessions: HashMap<String, Session>,
+    restaurants: HashMap<String, Restaurant>,
+    recipes: HashMap<String, Recipe>,
+    todos: HashMap<String, Todo>,
+    tokens: HashMap<String, (ApiToken, String)>,
+    tags: HashMap<String, Tag>,
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    data: RwLock<Data>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Data> {
+        self.data.read().expect("memory store lock is not poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Data> {
+        self.data
+            .write()
+            .expect("memory store lock is not poisoned")
+    }
+}
+
+fn new_id() -> String {
+    Uuid::new_v4().to_simple().to_string()
+}
+
+/// Returns the entry with the given id, if it belongs to the user
+fn owned<'a, T>(
+    entries: &'a mut HashMap<String, T>,
+    id: &str,
+    user_id: &str,
+    owner: fn(&T) -> &str,
+) -> Result<&'a mut T> {
+    match entries.get_mut(id) {
+        Some(entry) if owner(entry) == user_id => Ok(entry),
+        _ => Err(NoEntryFoundError(id.to_owned())),
+    }
+}
+
+fn matches_query(book: &Book, query: &BookQuery) -> bool {
+    query.author.as_ref().is_none_or(|a| &book.author == a)
+        && query.language.as_ref().is_none_or(|l| &book.language == l)
+        && query.status.is_none_or(|s| book.status == s)
+        && query.tag.as_ref().is_none_or(|t| book.tags.contains(t))
+}
+
+impl Data {
+    /// Fails if the user already has another tag with the given name
+    fn check_tag_name(&self, name: &str, user_id: &str, except_id: Option<&str>) -> Result<()> {
+        let exists = self.tags.values().any(|t| {
+            t.user_id == user_id && same_name(&t.name, name) && Some(t.id.as_str()) != except_id
+        });
+        if exists {
+            return Err(TagExistsError(name.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Replaces the tag `tag_id` with `into_id` on all of the user's books, `None` removes it
+    fn replace_tag(&mut self, user_id: &str, tag_id: &str, into_id: Option<&str>) {
+        for book in self.books.values_mut().filter(|b| b.user_id == user_id) {
+            if !book.tags.iter().any(|t| t == tag_id) {
+                continue;
+            }
+            book.tags.retain(|t| t != tag_id);
+            if let Some(into_id) = into_id {
+                if !book.tags.iter().any(|t| t == into_id) {
+                    book.tags.push(into_id.to_owned());
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BookStore for MemoryStore {
+    async fn fetch_books(&self, user_id: &str, query: &BookQuery) -> Result<Vec<Book>> {
+        let mut result: Vec<Book> = self
+            .read()
+            .books
+            .values()
+            .filter(|b| b.user_id == user_id && matches_query(b, query))
+            .cloned()
+            .collect();
+        result.sort_by(|a, b| {
+            let ordering = match query.sort {
+                BookSort::Name => a.name.cmp(&b.name),
+                BookSort::Author => a.author.cmp(&b.author),
+                BookSort::AddedAt => a.added_at.cmp(&b.added_at),
+                BookSort::Pages => a.num_pages.cmp(&b.num_pages),
+            }
+            .then_with(|| a.id.cmp(&b.id));
+            match query.dir {
+                SortDirection::Asc => ordering,
+                SortDirection::Desc => ordering.reverse(),
+            }
+        });
+        let books = result.into_iter().skip(query.skip());
+        Ok(match query.per_page {
+            Some(per_page) => books.take(per_page).collect(),
+            None => books.collect(),
+        })
+    }
+
+    async fn search_books(&self, user_id: &str, q: &str) -> Result<Vec<Book>> {
+        let q = q.to_lowercase();
+        let mut result: Vec<Book> = self
+            .read()
+            .books
+            .values()
+            .filter(|b| {
+                b.user_id == user_id
+                    && (b.name.to_lowercase().contains(&q) || b.author.to_lowercase().contains(&q))
+            })
+            .cloned()
+            .collect();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(result)
+    }
+
+    async fn count_books(&self, user_id: &str, query: &BookQuery) -> Result<i64> {
+        let count = self
+            .read()
+            .books
+            .values()
+            .filter(|b| b.user_id == user_id && matches_query(b, query))
+            .count();
+        Ok(count as i64)
+    }
+
+    async fn fetch_book(&self, id: &str, user_id: &str) -> Result<Book> {
+        self.read()
+            .books
+            .get(id)
+            .filter(|b| b.user_id == user_id)
+            .cloned()
+            .ok_or_else(|| NoEntryFoundError(id.to_owned()))
+    }
+
+    async fn create_book(&self, entry: &NewBook, user_id: &str) -> Result<String> {
+        let id = new_id();
+        let book = Book::new(
+            &id,
+            user_id,
+            &entry.name,
+            &entry.author,
+            &entry.language,
+            entry.pages as usize,
+            &Utc::now(),
+        );
+        self.write().books.insert(id.clone(), book);
+        Ok(id)
+    }
+
+    async fn import_books(&self, books: &[Book], user_id: &str) -> Result<usize> {
+        let mut data = self.write();
+        for book in books {
+            let id = new_id();
+            let book = Book {
+                id: id.clone(),
+                user_id: user_id.to_owned(),
+                ..book.clone()
+            };
+            data.books.insert(id, book);
+        }
+        Ok(books.len())
+    }
+
+    async fn edit_book(&self, id: &str, user_id: &str, entry: &EditedBook) -> Result<()> {
+        let mut data = self.write();
+        let book = owned(&mut data.books, id, user_id, |b| b.user_id.as_str())?;
+        book.name = entry.name.clone();
+        book.author = entry.author.clone();
+        book.language = entry.language.clone();
+        book.num_pages = entry.pages as usize;
+        Ok(())
+    }
+
+    async fn delete_book(&self, id: &str, user_id: &str) -> Result<()> {
+        let mut data = self.write();
+        owned(&mut data.books, id, user_id, |b| b.user_id.as_str())?;
+        data.books.remove(id);
+        Ok(())
+    }
+
+    async fn set_reading_state(&self, id: &str, user_id: &str, state: &ReadingState) -> Result<()> {
+        let mut data = self.write();
+        let book = owned(&mut data.books, id, user_id, |b| b.user_id.as_str())?;
+        book.status = state.status;
+        book.current_page = state.current_page;
+        book.started_at = state.started_at;
+        book.finished_at = state.finished_at;
+        Ok(())
+    }
+
+    async fn add_progress(
+        &self,
+        id: &str,
+        user_id: &str,
+        logged_at: &DateTime<Utc>,
+        page: usize,
+    ) -> Result<()> {
+        let mut data = self.write();
+        let book = owned(&mut data.books, id, user_id, |b| b.user_id.as_str())?;
+        book.progress.push(ProgressEntry {
+            id: Uuid::new_v4().to_string(),
+            logged_at: *logged_at,
+            page,
+        });
+        Ok(())
+    }
+
+    async fn delete_progress(&self, id: &str, user_id: &str, progress_id: &str) -> Result<()> {
+        let mut data = self.write();
+        let book = owned(&mut data.books, id, user_id, |b| b.user_id.as_str())?;
+        let before = book.progress.len();
+        book.progress.retain(|p| p.id != progress_id);
+        if book.progress.len() == before {
+            return Err(NoEntryFoundError(progress_id.to_owned()));
+        }
+        Ok(())
+    }
+
+    async fn set_review(
+        &self,
+        id: &str,
+        user_id: &str,
+        rating: Option<usize>,
+        review: &str,
+    ) -> Result<()> {
+        let mut data = self.write();
+        let book = owned(&mut data.books, id, user_id, |b| b.user_id.as_str())?;
+        book.rating = rating;
+        book.review = review.to_owned();
+        Ok(())
+    }
+
+    async fn add_note(
+        &self,
+        id: &str,
+        user_id: &str,
+        kind: NoteKind,
+        text: &str,
+        page: Option<usize>,
+    ) -> Result<()> {
+        let mut data = self.write();
+        let book = owned(&mut data.books, id, user_id, |b| b.user_id.as_str())?;
+        book.notes.push(BookNote {
+            id: Uuid::new_v4().to_string(),
+            kind,
+            text: text.to_owned(),
+            page,
+            added_at: Utc::now(),
+        });
+        Ok(())
+    }
+
+    async fn delete_note(&self, id: &str, user_id: &str, note_id: &str) -> Result<()> {
+        let mut data = self.write();
+        let book = owned(&mut data.books, id, user_id, |b| b.user_id.as_str())?;
+        let before = book.notes.len();
+        book.notes.retain(|n| n.id != note_id);
+        if book.notes.len() == before {
+            return Err(NoEntryFoundError(note_id.to_owned()));
+        }
+        Ok(())
+    }
+
+    async fn set_book_tags(&self, id: &str, user_id: &str, tag_ids: &[String]) -> Result<()> {
+        let mut data = self.write();
+        let book = owned(&mut data.books, id, user_id, |b| b.user_id.as_str())?;
+        book.tags = tag_ids.to_vec();
+        Ok(())
+    }
+
+    async fn delete_user_books(&self, user_id: &str) -> Result<()> {
+        self.write().books.retain(|_, b| b.user_id != user_id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TagStore for MemoryStore {
+    async fn fetch_tags(&self, user_id: &str) -> Result<Vec<Tag>> {
+        let mut result: Vec<Tag> = self
+            .read()
+            .tags
+            .values()
+            .filter(|t| t.user_id == user_id)
+            .cloned()
+            .collect();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(result)
+    }
+
+    async fn fetch_tag(&self, id: &str, user_id: &str) -> Result<Tag> {
+        self.read()
+            .tags
+            .get(id)
+            .filter(|t| t.user_id == user_id)
+            .cloned()
+            .ok_or_else(|| NoEntryFoundError(id.to_owned()))
+    }
+
+    async fn create_tag(&self, entry: &TagEntry, user_id: &str) -> Result<String> {
+        let mut data = self.write();
+        data.check_tag_name(&entry.name, user_id, None)?;
+        let id = new_id();
+        let tag = Tag {
+            id: id.clone(),
+            user_id: user_id.to_owned(),
+            name: entry.name.clone(),
+            color: entry.color.clone(),
+            added_at: Utc::now(),
+        };
+        data.tags.insert(id.clone(), tag);
+        Ok(id)
+    }
+
+    async fn edit_tag(&self, id: &str, user_id: &str, entry: &TagEntry) -> Result<()> {
+        let mut data = self.write();
+        data.check_tag_name(&entry.name, user_id, Some(id))?;
+        let tag = owned(&mut data.tags, id, user_id, |t| t.user_id.as_str())?;
+        tag.name = entry.name.clone();
+        tag.color = entry.color.clone();
+        Ok(())
+    }
+
+    async fn merge_tag(&self, id: &str, user_id: &str, into_id: &str) -> Result<()> {
+        let mut data = self.write();
+        owned(&mut data.tags, id, user_id, |t| t.user_id.as_str())?;
+        data.replace_tag(user_id, id, Some(into_id));
+        data.tags.remove(id);
+        Ok(())
+    }
+
+    async fn delete_tag(&self, id: &str, user_id: &str) -> Result<()> {
+        let mut data = self.write();
+        owned(&mut data.tags, id, user_id, |t| t.user_id.as_str())?;
+        data.replace_tag(user_id, id, None);
+        data.tags.remove(id);
+        Ok(())
+    }
+
+    async fn delete_user_tags(&self, user_id: &str) -> Result<()> {
+        self.write().tags.retain(|_, t| t.user_id != user_id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn fetch_user(&self, email: &str) -> Result<User> {
+        self.read()
+            .users
+            .values()
+            .find(|u| u.email == email)
+            .cloned()
+            .ok_or_else(|| NoEntryFoundError(email.to_owned()))
+    }
+
+    async fn fetch_user_by_id(&self, id: &str) -> Result<User> {
+        self.read()
+            .users
+            .get(id)
+            .cloned()
+            .ok_or_else(|| NoEntryFoundError(id.to_owned()))
+    }
+
+    async fn create_user(&self, email: &str, password_hash: &str) -> Result<String> {
+        let mut data = self.write();
+        if data.users.values().any(|u| u.email == email) {
+            return Err(EmailTakenError(email.to_owned()));
+        }
+        let id = new_id();
+        data.users.insert(
+            id.clone(),
+            User {
+                id: id.clone(),
+                email: email.to_owned(),
+                password: password_hash.to_owned(),
+            },
+        );
+        Ok(id)
+    }
+
+    async fn update_password(&self, id: &str, password_hash: &str) -> Result<()> {
+        match self.write().users.get_mut(id) {
+            Some(user) => {
+                user.password = password_hash.to_owned();
+                Ok(())
+            }
+            None => Err(NoEntryFoundError(id.to_owned())),
+        }
+    }
+
+    async fn delete_user(&self, id: &str) -> Result<()> {
+        match self.write().users.remove(id) {
+            Some(_) => Ok(()),
+            None => Err(NoEntryFoundError(id.to_owned())),
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn create_session(&self, user_id: &str) -> Result<Session> {
+        let now = Utc::now();
+        let session = Session {
+            id: new_id(),
+            session_id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_owned(),
+            created_at: now,
+            last_seen_at: now,
+            expires_at: session_expiry(&now, &now),
+        };
+        self.write()
+            .sessions
+            .insert(session.session_id.clone(), session.clone());
+        Ok(session)
+    }
+
+    async fn find_session(&self, session_id: &str) -> Result<Session> {
+        match self.read().sessions.get(session_id) {
+            Some(session) if session.expires_at > Utc::now() => Ok(session.clone()),
+            _ => Err(NoEntryFoundError(session_id.to_owned())),
+        }
+    }
+
+    async fn touch_session(&self, session: &Session) -> Result<()> {
+        if let Some(stored) = self.write().sessions.get_mut(&session.session_id) {
+            let now = Utc::now();
+            stored.last_seen_at = now;
+            stored.expires_at = session_expiry(&stored.created_at, &now);
+        }
+        Ok(())
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<()> {
+        self.write().sessions.remove(session_id);
+        Ok(())
+    }
+
+    async fn delete_user_sessions(
+        &self,
+        user_id: &str,
+        keep_session_id: Option<&str>,
+    ) -> Result<()> {
+        self.write().sessions.retain(|session_id, s| {
+            s.user_id != user_id || Some(session_id.as_str()) == keep_session_id
+        });
+        Ok(())
+    }
+
+    async fn delete_expired_sessions(&self) -> Result<i64> {
+        let now = Utc::now();
+        let mut data = self.write();
+        let before = data.sessions.len();
+        data.sessions.retain(|_, s| s.expires_at > now);
+        Ok((before - data.sessions.len()) as i64)
+    }
+}
+
+#[async_trait]
+impl RestaurantStore for MemoryStore {
+    async fn fetch_restaurants(&self, user_id: &str) -> Result<Vec<Restaurant>> {
+        let mut result: Vec<Restaurant> = self
+            .read()
+            .restaurants
+            .values()
+            .filter(|r| r.user_id == user_id)
+            .cloned()
+            .collect();
+        result.sort_by_key(|r| r.added_at);
+        Ok(result)
+    }
+
+    async fn fetch_restaurant(&self, id: &str, user_id: &str) -> Result<Restaurant> {
+        self.read()
+            .restaurants
+            .get(id)
+            .filter(|r| r.user_id == user_id)
+            .cloned()
+            .ok_or_else(|| NoEntryFoundError(id.to_owned()))
+    }
+
+    async fn create_restaurant(&self, entry: &NewRestaurant, user_id: &str) -> Result<()> {
+        let id = new_id();
+        let restaurant = Restaurant {
+            id: id.clone(),
+            user_id: user_id.to_owned(),
+            name: entry.name.clone(),
+            cuisine: entry.cuisine.clone(),
+            address: entry.address.clone(),
+            price_level: entry.price_level as usize,
+            rating: entry.rating as usize,
+            visits: vec![],
+            added_at: Utc::now(),
+        };
+        self.write().restaurants.insert(id, restaurant);
+        Ok(())
+    }
+
+    async fn import_restaurants(&self, restaurants: &[Restaurant], user_id: &str) -> Result<usize> {
+        let mut data = self.write();
+        for restaurant in restaurants {
+            let id = new_id();
+            let restaurant = Restaurant {
+                id: id.clone(),
+                user_id: user_id.to_owned(),
+                ..restaurant.clone()
+            };
+            data.restaurants.insert(id, restaurant);
+        }
+        Ok(restaurants.len())
+    }
+
+    async fn edit_restaurant(
+        &self,
+        id: &str,
+        user_id: &str,
+        entry: &EditedRestaurant,
+    ) -> Result<()> {
+        let mut data = self.write();
+        let restaurant = owned(&mut data.restaurants, id, user_id, |r| r.user_id.as_str())?;
+        restaurant.name = entry.name.clone();
+        restaurant.cuisine = entry.cuisine.clone();
+        restaurant.address = entry.address.clone();
+        restaurant.price_level = entry.price_level as usize;
+        restaurant.rating = entry.rating as usize;
+        Ok(())
+    }
+
+    async fn delete_restaurant(&self, id: &str, user_id: &str) -> Result<()> {
+        let mut data = self.write();
+        owned(&mut data.restaurants, id, user_id, |r| r.user_id.as_str())?;
+        data.restaurants.remove(id);
+        Ok(())
+    }
+
+    async fn add_visit(
+        &self,
+        id: &str,
+        user_id: &str,
+        visited_at: &DateTime<Utc>,
+        notes: &str,
+    ) -> Result<()> {
+        let mut data = self.write();
+        let restaurant = owned(&mut data.restaurants, id, user_id, |r| r.user_id.as_str())?;
+        restaurant.visits.push(Visit {
+            id: Uuid::new_v4().to_string(),
+            visited_at: *visited_at,
+            notes: notes.to_owned(),
+        });
+        Ok(())
+    }
+
+    async fn delete_visit(&self, id: &str, user_id: &str, visit_id: &str) -> Result<()> {
+        let mut data = self.write();
+        let restaurant = owned(&mut data.restaurants, id, user_id, |r| r.user_id.as_str())?;
+        let before = restaurant.visits.len();
+        restaurant.visits.retain(|v| v.id != visit_id);
+        if restaurant.visits.len() == before {
+            return Err(NoEntryFoundError(visit_id.to_owned()));
+        }
+        Ok(())
+    }
+
+    async fn delete_user_restaurants(&self, user_id: &str) -> Result<()> {
+        self.write().restaurants.retain(|_, r| r.user_id != user_id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RecipeStore for MemoryStore {
+    async fn fetch_recipes(&self, user_id: &str) -> Result<Vec<Recipe>> {
+        let mut result: Vec<Recipe> = self
+            .read()
+            .recipes
+            .values()
+            .filter(|r| r.user_id == user_id)
+            .cloned()
+            .collect();
+        result.sort_by_key(|r| r.added_at);
+        Ok(result)
+    }
+
+    async fn fetch_recipe(&self, id: &str, user_id: &str) -> Result<Recipe> {
+        self.read()
+            .recipes
+            .get(id)
+            .filter(|r| r.user_id == user_id)
+            .cloned()
+            .ok_or_else(|| NoEntryFoundError(id.to_owned()))
+    }
+
+    async fn create_recipe(&self, entry: &RecipeEntry, user_id: &str) -> Result<()> {
+        let id = new_id();
+        let recipe = Recipe {
+            id: id.clone(),
+            user_id: user_id.to_owned(),
+            title: entry.title.clone(),
+            servings: entry.servings,
+            ingredients: entry.ingredients.clone(),
+            steps: entry.steps.clone(),
+            prep_time: entry.prep_time,
+            cook_time: entry.cook_time,
+            source: entry.source.clone(),
+            added_at: Utc::now(),
+        };
+        self.write().recipes.insert(id, recipe);
+        Ok(())
+    }
+
+    async fn import_recipes(&self, recipes: &[Recipe], user_id: &str) -> Result<usize> {
+        let mut data = self.write();
+        for recipe in recipes {
+            let id = new_id();
+            let recipe = Recipe {
+                id: id.clone(),
+                user_id: user_id.to_owned(),
+                ..recipe.clone()
+            };
+            data.recipes.insert(id, recipe);
+        }
+        Ok(recipes.len())
+    }
+
+    async fn edit_recipe(&self, id: &str, user_id: &str, entry: &RecipeEntry) -> Result<()> {
+        let mut data = self.write();
+        let recipe = owned(&mut data.recipes, id, user_id, |r| r.user_id.as_str())?;
+        recipe.title = entry.title.clone();
+        recipe.servings = entry.servings;
+        recipe.ingredients = entry.ingredients.clone();
+        recipe.steps = entry.steps.clone();
+        recipe.prep_time = entry.prep_time;
+        recipe.cook_time = entry.cook_time;
+        recipe.source = entry.source.clone();
+        Ok(())
+    }
+
+    async fn delete_recipe(&self, id: &str, user_id: &str) -> Result<()> {
+        let mut data = self.write();
+        owned(&mut data.recipes, id, user_id, |r| r.user_id.as_str())?;
+        data.recipes.remove(id);
+        Ok(())
+    }
+
+    async fn delete_user_recipes(&self, user_id: &str) -> Result<()> {
+        self.write().recipes.retain(|_, r| r.user_id != user_id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TodoStore for MemoryStore {
+    async fn fetch_todos(&self, user_id: &str, filter: TodoFilter) -> Result<Vec<Todo>> {
+        let today = start_of_day(Utc::now().date_naive());
+        let mut result: Vec<Todo> = self
+            .read()
+            .todos
+            .values()
+            .filter(|t| t.user_id == user_id)
+            .filter(|t| match filter {
+                TodoFilter::Open => !t.done,
+                TodoFilter::Overdue => !t.done && t.due_at.is_some_and(|d| d < today),
+                TodoFilter::Done => t.done,
+            })
+            .cloned()
+            .collect();
+        match filter {
+            TodoFilter::Done => result.sort_by_key(|t| Reverse(t.completed_at)),
+            _ => result.sort_by_key(|t| t.due_at),
+        };
+        Ok(result)
+    }
+
+    async fn fetch_todo(&self, id: &str, user_id: &str) -> Result<Todo> {
+        self.read()
+            .todos
+            .get(id)
+            .filter(|t| t.user_id == user_id)
+            .cloned()
+            .ok_or_else(|| NoEntryFoundError(id.to_owned()))
+    }
+
+    async fn create_todo(&self, entry: &TodoEntry, user_id: &str) -> Result<()> {
+        let id = new_id();
+        let todo = Todo {
+            id: id.clone(),
+            user_id: user_id.to_owned(),
+            title: entry.title.clone(),
+            description: entry.description.clone(),
+            due_at: entry.due_at,
+            priority: entry.priority,
+            done: false,
+            completed_at: None,
+            added_at: Utc::now(),
+        };
+        self.write().todos.insert(id, todo);
+        Ok(())
+    }
+
+    async fn import_todos(&self, todos: &[Todo], user_id: &str) -> Result<usize> {
+        let mut data = self.write();
+        for todo in todos {
+            let id = new_id();
+            let todo = Todo {
+                id: id.clone(),
+                user_id: user_id.to_owned(),
+                ..todo.clone()
+            };
+            data.todos.insert(id, todo);
+        }
+        Ok(todos.len())
+    }
+
+    async fn edit_todo(&self, id: &str, user_id: &str, entry: &TodoEntry) -> Result<()> {
+        let mut data = self.write();
+        let todo = owned(&mut data.todos, id, user_id, |t| t.user_id.as_str())?;
+        todo.title = entry.title.clone();
+        todo.description = entry.description.clone();
+        todo.due_at = entry.due_at;
+        todo.priority = entry.priority;
+        Ok(())
+    }
+
+    async fn set_todo_done(&self, id: &str, user_id: &str, done: bool) -> Result<()> {
+        let mut data = self.write();
+        let todo = owned(&mut data.todos, id, user_id, |t| t.user_id.as_str())?;
+        todo.done = done;
+        todo.completed_at = if done { Some(Utc::now()) } else { None };
+        Ok(())
+    }
+
+    async fn delete_todo(&self, id: &str, user_id: &str) -> Result<()> {
+        let mut data = self.write();
+        owned(&mut data.todos, id, user_id, |t| t.user_id.as_str())?;
+        data.todos.remove(id);
+        Ok(())
+    }
+
+    async fn delete_user_todos(&self, user_id: &str) -> Result<()> {
+        self.write().todos.retain(|_, t| t.user_id != user_id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TokenStore for MemoryStore {
+    async fn create_token(&self, user_id: &str, name: &str, token_hash: &str) -> Result<ApiToken> {
+        let token = ApiToken {
+            id: new_id(),
+            user_id: user_id.to_owned(),
+            name: name.to_owned(),
+            created_at: Utc::now(),
+            last_used_at: None,
+        };
+        self.write()
+            .tokens
+            .insert(token.id.clone(), (token.clone(), token_hash.to_owned()));
+        Ok(token)
+    }
+
+    async fn fetch_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>> {
+        let mut result: Vec<ApiToken> = self
+            .read()
+            .tokens
+            .values()
+            .filter(|(t, _)| t.user_id == user_id)
+            .map(|(t, _)| t.clone())
+            .collect();
+        result.sort_by_key(|t| Reverse(t.created_at));
+        Ok(result)
+    }
+
+    async fn find_token(&self, token_hash: &str) -> Result<ApiToken> {
+        self.read()
+            .tokens
+            .values()
+            .find(|(_, hash)| hash == token_hash)
+            .map(|(t, _)| t.clone())
+            .ok_or_else(|| NoEntryFoundError(token_hash.to_owned()))
+    }
+
+    async fn touch_token(&self, id: &str) -> Result<()> {
+        match self.write().tokens.get_mut(id) {
+            Some((token, _)) => {
+                token.last_used_at = Some(Utc::now());
+                Ok(())
+            }
+            None => Err(NoEntryFoundError(id.to_owned())),
+        }
+    }
+
+    async fn delete_token(&self, id: &str, user_id: &str) -> Result<()> {
+        let mut data = self.write();
+        owned(&mut data.tokens, id, user_id, |(t, _)| t.user_id.as_str())?;
+        data.tokens.remove(id);
+        Ok(())
+    }
+
+    async fn delete_user_tokens(&self, user_id: &str) -> Result<()> {
+        self.write().tokens.retain(|_, (t, _)| t.user_id != user_id);
+        Ok(())
+    }
+}
@@ -1,29 +1,266 @@
-use crate::{Result, CONFIG};
-use log::info;
-use mongodb::{options::ClientOptions, Client, Database};
-
-pub mod books;
-pub mod session;
-pub mod user;
-
-pub async fn init() -> Result<(Database, Client)> {
-    let mut client_options =
-        ClientOptions::parse(&format!("mongodb://{}:{}", CONFIG.db.host, CONFIG.db.port)).await?;
-
-    client_options.app_name = Some("Toodeloo".to_string());
-    info!(
-        "Connecting to MongoDB at {}:{}",
-        CONFIG.db.host, CONFIG.db.port
-    );
-    let client = Client::with_options(client_options)?;
-    let db = client.database(&CONFIG.db.name);
+use crate::app::auth::normalize_email;
+use crate::app::books::{BookQuery, EditedBook, NewBook, ReadingState};
+use crate::app::recipes::RecipeEntry;
+use crate::app::restaurants::{EditedRestaurant, NewRestaurant};
+use crate::app::tags::TagEntry;
+use crate::app::todos::{TodoEntry, TodoFilter};
+use crate::data::{ApiToken, Book, NoteKind, Recipe, Restaurant, Session, Tag, Todo, User};
+use crate::settings::Backend;
+use crate::{error::Error::*, Result, CONFIG, DB};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use chrono::Duration;
+use log::{error, info};
+use std::sync::Arc;
+
+pub mod memory;
+pub mod mongo;
+pub mod sqlite;
+
+#[async_trait]
+pub trait BookStore {
+    /// Fetches the page of the user's books matching the query
+    async fn fetch_books(&self, user_id: &str, query: &BookQuery) -> Result<Vec<Book>>;
+    /// Counts all of the user's books matching the query's filters
+    async fn count_books(&self, user_id: &str, query: &BookQuery) -> Result<i64>;
+    /// Searches the user's books by name and author, best matches first. Mongo matches whole
+    /// words of the query using its text index, so "Dun" doesn't find "Dune" there, the other
+    /// backends match the query as a substring.
+    async fn search_books(&self, user_id: &str, q: &str) -> Result<Vec<Book>>;
+    async fn fetch_book(&self, id: &str, user_id: &str) -> Result<Book>;
+    /// Creates a book for the user and returns the new book's id
+    async fn create_book(&self, entry: &NewBook, user_id: &str) -> Result<String>;
+    /// Inserts complete books for the user under new ids, returning the number of inserted books
+    async fn import_books(&self, books: &[Book], user_id: &str) -> Result<usize>;
+    async fn edit_book(&self, id: &str, user_id: &str, entry: &EditedBook) -> Result<()>;
+    async fn delete_book(&self, id: &str, user_id: &str) -> Result<()>;
+    /// Sets the reading status, current page and reading dates of the book
+    async fn set_reading_state(&self, id: &str, user_id: &str, state: &ReadingState) -> Result<()>;
+    /// Appends an entry for the page reached at `logged_at` to the book's reading log
+    async fn add_progress(
+        &self,
+        id: &str,
+        user_id: &str,
+        logged_at: &DateTime<Utc>,
+        page: usize,
+    ) -> Result<()>;
+    async fn delete_progress(&self, id: &str, user_id: &str, progress_id: &str) -> Result<()>;
+    /// Sets the rating and the Markdown review of the book, `None` removes the rating
+    async fn set_review(
+        &self,
+        id: &str,
+        user_id: &str,
+        rating: Option<usize>,
+        review: &str,
+    ) -> Result<()>;
+    async fn add_note(
+        &self,
+        id: &str,
+        user_id: &str,
+        kind: NoteKind,
+        text: &str,
+        page: Option<usize>,
+    ) -> Result<()>;
+    async fn delete_note(&self, id: &str, user_id: &str, note_id: &str) -> Result<()>;
+    /// Replaces the tags assigned to the book
+    async fn set_book_tags(&self, id: &str, user_id: &str, tag_ids: &[String]) -> Result<()>;
+    /// Deletes all books of the given user
+    async fn delete_user_books(&self, user_id: &str) -> Result<()>;
+}
+
+/// Tags are shared by all item types, changes to a tag's identity are applied to all tagged items
+#[async_trait]
+pub trait TagStore {
+    /// Fetches all of the user's tags, ordered by name
+    async fn fetch_tags(&self, user_id: &str) -> Result<Vec<Tag>>;
+    async fn fetch_tag(&self, id: &str, user_id: &str) -> Result<Tag>;
+    /// Creates a tag and returns its id, names are unique per user
+    async fn create_tag(&self, entry: &TagEntry, user_id: &str) -> Result<String>;
+    async fn edit_tag(&self, id: &str, user_id: &str, entry: &TagEntry) -> Result<()>;
+    /// Replaces the tag with the tag `into_id` on all tagged items and deletes it
+    async fn merge_tag(&self, id: &str, user_id: &str, into_id: &str) -> Result<()>;
+    /// Removes the tag from all tagged items and deletes it
+    async fn delete_tag(&self, id: &str, user_id: &str) -> Result<()>;
+    /// Deletes all tags of the given user
+    async fn delete_user_tags(&self, user_id: &str) -> Result<()>;
+}
+
+#[async_trait]
+pub trait UserStore {
+    async fn fetch_user(&self, email: &str) -> Result<User>;
+    async fn fetch_user_by_id(&self, id: &str) -> Result<User>;
+    /// Creates a user with an already hashed password and returns the new user's id
+    async fn create_user(&self, email: &str, password_hash: &str) -> Result<String>;
+    async fn update_password(&self, id: &str, password_hash: &str) -> Result<()>;
+    async fn delete_user(&self, id: &str) -> Result<()>;
+}
+
+#[async_trait]
+pub trait SessionStore {
+    async fn create_session(&self, user_id: &str) -> Result<Session>;
+    /// Finds a session, which has not expired yet
+    async fn find_session(&self, session_id: &str) -> Result<Session>;
+    /// Marks the session as active, sliding its expiry
+    async fn touch_session(&self, session: &Session) -> Result<()>;
+    async fn delete_session(&self, session_id: &str) -> Result<()>;
+    /// Deletes all sessions of the given user, except for the session with `keep_session_id`
+    async fn delete_user_sessions(
+        &self,
+        user_id: &str,
+        keep_session_id: Option<&str>,
+    ) -> Result<()>;
+    /// Deletes all expired sessions and returns how many were deleted
+    async fn delete_expired_sessions(&self) -> Result<i64>;
+}
+
+#[async_trait]
+pub trait RestaurantStore {
+    async fn fetch_restaurants(&self, user_id: &str) -> Result<Vec<Restaurant>>;
+    async fn fetch_restaurant(&self, id: &str, user_id: &str) -> Result<Restaurant>;
+    async fn create_restaurant(&self, entry: &NewRestaurant, user_id: &str) -> Result<()>;
+    /// Inserts complete restaurants for the user under new ids, returning the number of inserted
+    /// restaurants
+    async fn import_restaurants(&self, restaurants: &[Restaurant], user_id: &str) -> Result<usize>;
+    async fn edit_restaurant(
+        &self,
+        id: &str,
+        user_id: &str,
+        entry: &EditedRestaurant,
+    ) -> Result<()>;
+    async fn delete_restaurant(&self, id: &str, user_id: &str) -> Result<()>;
+    async fn add_visit(
+        &self,
+        id: &str,
+        user_id: &str,
+        visited_at: &DateTime<Utc>,
+        notes: &str,
+    ) -> Result<()>;
+    async fn delete_visit(&self, id: &str, user_id: &str, visit_id: &str) -> Result<()>;
+    /// Deletes all restaurants of the given user
+    async fn delete_user_restaurants(&self, user_id: &str) -> Result<()>;
+}
+
+#[async_trait]
+pub trait RecipeStore {
+    async fn fetch_recipes(&self, user_id: &str) -> Result<Vec<Recipe>>;
+    async fn fetch_recipe(&self, id: &str, user_id: &str) -> Result<Recipe>;
+    async fn create_recipe(&self, entry: &RecipeEntry, user_id: &str) -> Result<()>;
+    /// Inserts complete recipes for the user under new ids, returning the number of inserted recipes
+    async fn import_recipes(&self, recipes: &[Recipe], user_id: &str) -> Result<usize>;
+    async fn edit_recipe(&self, id: &str, user_id: &str, entry: &RecipeEntry) -> Result<()>;
+    async fn delete_recipe(&self, id: &str, user_id: &str) -> Result<()>;
+    /// Deletes all recipes of the given user
+    async fn delete_user_recipes(&self, user_id: &str) -> Result<()>;
+}
+
+#[async_trait]
+pub trait TodoStore {
+    async fn fetch_todos(&self, user_id: &str, filter: TodoFilter) -> Result<Vec<Todo>>;
+    async fn fetch_todo(&self, id: &str, user_id: &str) -> Result<Todo>;
+    async fn create_todo(&self, entry: &TodoEntry, user_id: &str) -> Result<()>;
+    /// Inserts complete todos for the user under new ids, returning the number of inserted todos
+    async fn import_todos(&self, todos: &[Todo], user_id: &str) -> Result<usize>;
+    async fn edit_todo(&self, id: &str, user_id: &str, entry: &TodoEntry) -> Result<()>;
+    /// Marks the todo as done or undone, setting `completed_at` accordingly
+    async fn set_todo_done(&self, id: &str, user_id: &str, done: bool) -> Result<()>;
+    async fn delete_todo(&self, id: &str, user_id: &str) -> Result<()>;
+    /// Deletes all todos of the given user
+    async fn delete_user_todos(&self, user_id: &str) -> Result<()>;
+}
+
+#[async_trait]
+pub trait TokenStore {
+    /// Stores a new token for the user, only the hash of the token itself is persisted
+    async fn create_token(&self, user_id: &str, name: &str, token_hash: &str) -> Result<ApiToken>;
+    async fn fetch_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>>;
+    async fn find_token(&self, token_hash: &str) -> Result<ApiToken>;
+    /// Sets the token's last used timestamp to now
+    async fn touch_token(&self, id: &str) -> Result<()>;
+    async fn delete_token(&self, id: &str, user_id: &str) -> Result<()>;
+    /// Deletes all tokens of the given user
+    async fn delete_user_tokens(&self, user_id: &str) -> Result<()>;
+}
+
+/// Everything the handlers need from a storage backend
+#[async_trait]
+pub trait Store:
+    BookStore
+    + UserStore
+    + SessionStore
+    + RestaurantStore
+    + RecipeStore
+    + TodoStore
+    + TokenStore
+    + TagStore
+    + Send
+    + Sync
+{
+    /// Checks whether the backend is reachable
+    async fn ping(&self) -> Result<()>;
+}
+
+pub async fn init() -> Result<DB> {
+    let db: DB = match CONFIG.db.backend {
+        Backend::Mongo => Arc::new(mongo::MongoStore::init().await?),
+        Backend::Sqlite => Arc::new(sqlite::SqliteStore::init()?),
+        Backend::Memory => {
+            info!("Using in-memory storage, data is lost on restart");
+            Arc::new(memory::MemoryStore::new())
+        }
+    };
 
     if CONFIG.app.init_db {
-        info!("Initializing collections...");
-        // initialize books
-        // initialize users
-        // initialize sessions
+        seed_admin(&db).await?;
+    }
+    Ok(db)
+}
+
+/// Applies pending data migrations, only the Mongo backend has any
+pub async fn migrate(dry_run: bool) -> Result<()> {
+    match CONFIG.db.backend {
+        Backend::Mongo => mongo::migrate(dry_run).await,
+        _ => {
+            info!("No migrations for the configured storage backend");
+            Ok(())
+        }
     }
+}
+
+/// Creates the configured admin user, unless a user with that email already exists
+async fn seed_admin(db: &DB) -> Result<()> {
+    let (email, password) = match (&CONFIG.app.admin_email, &CONFIG.app.admin_password) {
+        (Some(email), Some(password)) => (normalize_email(email), password),
+        _ => return Ok(()),
+    };
+    match db.fetch_user(&email).await {
+        Ok(_) => return Ok(()),
+        Err(NoEntryFoundError(_)) => (),
+        Err(e) => return Err(e),
+    };
+    let password_hash = bcrypt::hash(password, bcrypt::DEFAULT_COST)?;
+    db.create_user(&email, &password_hash).await?;
+    info!("Created admin user {}", email);
+    Ok(())
+}
+
+/// Periodically purges expired sessions, never returns
+pub async fn run_session_cleanup(db: DB) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        CONFIG.session.cleanup_interval_secs,
+    ));
+    loop {
+        interval.tick().await;
+        match db.delete_expired_sessions().await {
+            Ok(deleted) if deleted > 0 => info!("Purged {} expired sessions", deleted),
+            Ok(_) => (),
+            Err(e) => error!("could not purge expired sessions: {}", e),
+        };
+    }
+}
 
-    Ok((db, client))
+/// Returns when a session expires, either after being idle or after its max lifetime
+pub fn session_expiry(created_at: &DateTime<Utc>, last_seen_at: &DateTime<Utc>) -> DateTime<Utc> {
+    let idle_expiry = *last_seen_at + Duration::seconds(CONFIG.session.lifetime_secs);
+    let max_expiry = *created_at + Duration::seconds(CONFIG.session.max_lifetime_secs);
+    std::cmp::min(idle_expiry, max_expiry)
 }
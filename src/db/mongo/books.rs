@@ -0,0 +1,680 @@
+use crate::app::books::{BookQuery, BookSort, EditedBook, NewBook, ReadingState, SortDirection};
+use crate::data::{Book, BookNote, NoteKind, ProgressEntry, ReadingStatus};
+use crate::{error::Error::*, Result};
+use bson::ordered::OrderedDocument;
+use bson::{doc, oid::ObjectId, Bson};
+use chrono::prelude::*;
+use futures::StreamExt;
+use mongodb::{options::FindOptions, Database};
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+const BOOKS: &str = "books";
+const ID: &str = "_id";
+const USER_ID: &str = "user_id";
+const NAME: &str = "name";
+const AUTHOR: &str = "author";
+const LANG: &str = "language";
+const NUM_PAGES: &str = "num_pages";
+const ADDED_AT: &str = "added_at";
+const STATUS: &str = "status";
+const CURRENT_PAGE: &str = "current_page";
+const STARTED_AT: &str = "started_at";
+const FINISHED_AT: &str = "finished_at";
+const PROGRESS: &str = "progress";
+const PROGRESS_ID: &str = "id";
+const LOGGED_AT: &str = "logged_at";
+const PAGE: &str = "page";
+const RATING: &str = "rating";
+const REVIEW: &str = "review";
+const NOTES: &str = "notes";
+const NOTE_ID: &str = "id";
+const KIND: &str = "kind";
+const TEXT: &str = "text";
+const TAGS: &str = "tags";
+const SCORE: &str = "score";
+const MAX_SEARCH_RESULTS: i64 = 100;
+
+pub async fn fetch_books(user_id: &str, query: &BookQuery, db: &Database) -> Result<Vec<Book>> {
+    let coll = db.collection(BOOKS);
+    let filter = books_filter(user_id, query)?;
+    let field = match query.sort {
+        BookSort::Name => NAME,
+        BookSort::Author => AUTHOR,
+        BookSort::AddedAt => ADDED_AT,
+        BookSort::Pages => NUM_PAGES,
+    };
+    let direction = match query.dir {
+        SortDirection::Asc => 1,
+        SortDirection::Desc => -1,
+    };
+    let options = FindOptions::builder()
+        // sorting by id as well keeps the order stable across pages
+        .sort(doc! { field: direction, ID: direction })
+        .skip(i64::try_from(query.skip()).unwrap_or(i64::MAX))
+        .limit(query.per_page.map(|l| l as i64))
+        .build();
+
+    let mut cursor = coll.find(filter, options).await.map_err(MongoQueryError)?;
+    let mut result: Vec<Book> = Vec::new();
+
+    while let Some(doc) = cursor.next().await {
+        result.push(doc_to_book(&doc?)?);
+    }
+    Ok(result)
+}
+
+pub async fn count_books(user_id: &str, query: &BookQuery, db: &Database) -> Result<i64> {
+    let coll = db.collection(BOOKS);
+    let filter = books_filter(user_id, query)?;
+    let count = coll
+        .count_documents(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(count)
+}
+
+/// Searches using the text index on name and author, ordered by relevance. The index matches
+/// whole words without stemming, unlike the substring matching of the other backends.
+pub async fn search_books(user_id: &str, q: &str, db: &Database) -> Result<Vec<Book>> {
+    let coll = db.collection(BOOKS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+        "$text": { "$search": q },
+    };
+    let options = FindOptions::builder()
+        .projection(doc! { SCORE: { "$meta": "textScore" } })
+        .sort(doc! { SCORE: { "$meta": "textScore" } })
+        .limit(MAX_SEARCH_RESULTS)
+        .build();
+
+    let mut cursor = coll.find(filter, options).await.map_err(MongoQueryError)?;
+    let mut result: Vec<Book> = Vec::new();
+
+    while let Some(doc) = cursor.next().await {
+        result.push(doc_to_book(&doc?)?);
+    }
+    Ok(result)
+}
+
+fn books_filter(user_id: &str, query: &BookQuery) -> Result<OrderedDocument> {
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let mut filter = doc! {
+        USER_ID: user_oid,
+    };
+    if let Some(ref author) = query.author {
+        filter.insert(AUTHOR, author.clone());
+    }
+    if let Some(ref language) = query.language {
+        filter.insert(LANG, language.clone());
+    }
+    if let Some(status) = query.status {
+        filter.insert(STATUS, status.as_str());
+    }
+    if let Some(ref tag) = query.tag {
+        filter.insert(TAGS, tag.clone());
+    }
+    Ok(filter)
+}
+
+pub async fn fetch_book(id: &str, user_id: &str, db: &Database) -> Result<Book> {
+    let coll = db.collection(BOOKS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+
+    let result = coll.find_one(filter, None).await.map_err(MongoQueryError)?;
+    match result {
+        Some(v) => {
+            let book = doc_to_book(&v)?;
+            Ok(book)
+        }
+        None => Err(NoEntryFoundError(id.to_owned())),
+    }
+}
+
+/// Creates a book and returns the new book's id
+pub async fn create_book(entry: &NewBook, user_id: &str, db: &Database) -> Result<String> {
+    let coll = db.collection(BOOKS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let doc = doc! {
+        USER_ID: user_oid,
+        NAME: entry.name.clone(),
+        AUTHOR: entry.author.clone(),
+        LANG: entry.language.clone(),
+        NUM_PAGES: entry.pages,
+        ADDED_AT: Utc::now(),
+        STATUS: ReadingStatus::WantToRead.as_str(),
+        CURRENT_PAGE: 0,
+        STARTED_AT: Bson::Null,
+        FINISHED_AT: Bson::Null,
+        PROGRESS: Bson::Array(vec![]),
+        RATING: Bson::Null,
+        REVIEW: "",
+        NOTES: Bson::Array(vec![]),
+        TAGS: Bson::Array(vec![]),
+    };
+    let result = coll.insert_one(doc, None).await.map_err(MongoQueryError)?;
+    match result.inserted_id {
+        Bson::ObjectId(oid) => Ok(oid.to_hex()),
+        _ => Err(InvalidIDError(entry.name.clone())),
+    }
+}
+
+/// Inserts complete books for the user under new ids, returning the number of inserted books
+pub async fn import_books(books: &[Book], user_id: &str, db: &Database) -> Result<usize> {
+    if books.is_empty() {
+        return Ok(0);
+    }
+    let coll = db.collection(BOOKS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let docs = books.iter().map(|book| book_to_doc(book, &user_oid));
+    let result = coll
+        .insert_many(docs, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(result.inserted_ids.len())
+}
+
+pub async fn edit_book(id: &str, user_id: &str, entry: &EditedBook, db: &Database) -> Result<()> {
+    let coll = db.collection(BOOKS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$set": {
+            NAME: entry.name.clone(),
+            AUTHOR: entry.author.clone(),
+            LANG: entry.language.clone(),
+            NUM_PAGES: entry.pages,
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn delete_book(id: &str, user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(BOOKS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let result = coll
+        .delete_one(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.deleted_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn set_reading_state(
+    id: &str,
+    user_id: &str,
+    state: &ReadingState,
+    db: &Database,
+) -> Result<()> {
+    let coll = db.collection(BOOKS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$set": {
+            STATUS: state.status.as_str(),
+            CURRENT_PAGE: state.current_page as i32,
+            STARTED_AT: optional_date(&state.started_at),
+            FINISHED_AT: optional_date(&state.finished_at),
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn add_progress(
+    id: &str,
+    user_id: &str,
+    logged_at: &DateTime<Utc>,
+    page: usize,
+    db: &Database,
+) -> Result<()> {
+    let coll = db.collection(BOOKS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$push": {
+            PROGRESS: {
+                PROGRESS_ID: Uuid::new_v4().to_string(),
+                LOGGED_AT: *logged_at,
+                PAGE: page as i32,
+            }
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn delete_progress(
+    id: &str,
+    user_id: &str,
+    progress_id: &str,
+    db: &Database,
+) -> Result<()> {
+    let coll = db.collection(BOOKS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$pull": {
+            PROGRESS: { PROGRESS_ID: progress_id }
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.modified_count == 0 {
+        return Err(NoEntryFoundError(progress_id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn set_review(
+    id: &str,
+    user_id: &str,
+    rating: Option<usize>,
+    review: &str,
+    db: &Database,
+) -> Result<()> {
+    let coll = db.collection(BOOKS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$set": {
+            RATING: optional_int(rating),
+            REVIEW: review,
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn add_note(
+    id: &str,
+    user_id: &str,
+    kind: NoteKind,
+    text: &str,
+    page: Option<usize>,
+    db: &Database,
+) -> Result<()> {
+    let coll = db.collection(BOOKS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$push": {
+            NOTES: {
+                NOTE_ID: Uuid::new_v4().to_string(),
+                KIND: kind.as_str(),
+                TEXT: text,
+                PAGE: optional_int(page),
+                ADDED_AT: Utc::now(),
+            }
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn delete_note(id: &str, user_id: &str, note_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(BOOKS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$pull": {
+            NOTES: { NOTE_ID: note_id }
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.modified_count == 0 {
+        return Err(NoEntryFoundError(note_id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn set_book_tags(
+    id: &str,
+    user_id: &str,
+    tag_ids: &[String],
+    db: &Database,
+) -> Result<()> {
+    let coll = db.collection(BOOKS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$set": {
+            TAGS: tag_ids.iter().map(|t| Bson::String(t.clone())).collect::<Vec<Bson>>(),
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Replaces the tag `tag_id` with `into_id` on all of the user's books
+pub async fn replace_tag(user_id: &str, tag_id: &str, into_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(BOOKS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+        TAGS: tag_id,
+    };
+    // mongo can't add to and pull from the same array in one update
+    let add = doc! {
+        "$addToSet": { TAGS: into_id },
+    };
+    coll.update_many(filter, add, None)
+        .await
+        .map_err(MongoQueryError)?;
+    remove_tag(user_id, tag_id, db).await
+}
+
+/// Removes the tag `tag_id` from all of the user's books
+pub async fn remove_tag(user_id: &str, tag_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(BOOKS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+        TAGS: tag_id,
+    };
+    let update = doc! {
+        "$pull": { TAGS: tag_id },
+    };
+    coll.update_many(filter, update, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
+
+/// Assigns all books without an owner to the given user, returning the number of updated books
+pub async fn backfill_owner(user_id: &str, db: &Database) -> Result<i64> {
+    let coll = db.collection(BOOKS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: { "$exists": false },
+    };
+    let update = doc! {
+        "$set": { USER_ID: user_oid },
+    };
+    let result = coll
+        .update_many(filter, update, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(result.modified_count)
+}
+
+/// Deletes all books of the given user
+pub async fn delete_user_books(user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(BOOKS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+    };
+    coll.delete_many(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
+
+fn book_to_doc(book: &Book, user_oid: &ObjectId) -> OrderedDocument {
+    let progress = book
+        .progress
+        .iter()
+        .map(|p| {
+            Bson::Document(doc! {
+                PROGRESS_ID: p.id.clone(),
+                LOGGED_AT: p.logged_at,
+                PAGE: p.page as i32,
+            })
+        })
+        .collect::<Vec<Bson>>();
+    let notes = book
+        .notes
+        .iter()
+        .map(|n| {
+            Bson::Document(doc! {
+                NOTE_ID: n.id.clone(),
+                KIND: n.kind.as_str(),
+                TEXT: n.text.clone(),
+                PAGE: optional_int(n.page),
+                ADDED_AT: n.added_at,
+            })
+        })
+        .collect::<Vec<Bson>>();
+    doc! {
+        USER_ID: user_oid.clone(),
+        NAME: book.name.clone(),
+        AUTHOR: book.author.clone(),
+        LANG: book.language.clone(),
+        NUM_PAGES: book.num_pages as i32,
+        ADDED_AT: book.added_at,
+        STATUS: book.status.as_str(),
+        CURRENT_PAGE: book.current_page as i32,
+        STARTED_AT: optional_date(&book.started_at),
+        FINISHED_AT: optional_date(&book.finished_at),
+        PROGRESS: progress,
+        RATING: optional_int(book.rating),
+        REVIEW: book.review.clone(),
+        NOTES: notes,
+        TAGS: book.tags.iter().map(|t| Bson::String(t.clone())).collect::<Vec<Bson>>(),
+    }
+}
+
+fn optional_date(date: &Option<DateTime<Utc>>) -> Bson {
+    match date {
+        Some(d) => Bson::from(*d),
+        None => Bson::Null,
+    }
+}
+
+fn optional_int(value: Option<usize>) -> Bson {
+    match value {
+        Some(v) => Bson::I32(v as i32),
+        None => Bson::Null,
+    }
+}
+
+fn get_optional_int(doc: &OrderedDocument, key: &str) -> Option<usize> {
+    match doc.get(key) {
+        Some(Bson::I32(v)) => Some(*v as usize),
+        _ => None,
+    }
+}
+
+fn get_optional_date(doc: &OrderedDocument, key: &str) -> Option<DateTime<Utc>> {
+    match doc.get(key) {
+        Some(Bson::UtcDatetime(d)) => Some(*d),
+        _ => None,
+    }
+}
+
+/// Fields added after the first version are missing on older books, which get their defaults
+fn doc_to_book(doc: &OrderedDocument) -> Result<Book> {
+    let id = doc.get_object_id(ID)?;
+    let user_id = doc.get_object_id(USER_ID)?;
+    let name = doc.get_str(NAME)?;
+    let author = doc.get_str(AUTHOR)?;
+    let lang = doc.get_str(LANG)?;
+    let num_pages = doc.get_i32(NUM_PAGES)?;
+    let added_at = doc.get_utc_datetime(ADDED_AT)?;
+    let status = doc.get_str(STATUS).ok().and_then(ReadingStatus::parse);
+    let current_page = doc.get_i32(CURRENT_PAGE).unwrap_or(0);
+
+    let mut progress = Vec::new();
+    if let Ok(entries) = doc.get_array(PROGRESS) {
+        for entry in entries {
+            if let Bson::Document(p) = entry {
+                progress.push(doc_to_progress(p)?);
+            }
+        }
+    }
+
+    let mut tags = Vec::new();
+    if let Ok(ids) = doc.get_array(TAGS) {
+        for id in ids {
+            if let Bson::String(id) = id {
+                tags.push(id.clone());
+            }
+        }
+    }
+
+    let mut notes = Vec::new();
+    if let Ok(entries) = doc.get_array(NOTES) {
+        for entry in entries {
+            if let Bson::Document(n) = entry {
+                notes.push(doc_to_note(n)?);
+            }
+        }
+    }
+
+    let book = Book {
+        status: status.unwrap_or(ReadingStatus::WantToRead),
+        current_page: current_page as usize,
+        started_at: get_optional_date(doc, STARTED_AT),
+        finished_at: get_optional_date(doc, FINISHED_AT),
+        progress,
+        rating: get_optional_int(doc, RATING),
+        review: doc.get_str(REVIEW).unwrap_or_default().to_owned(),
+        notes,
+        tags,
+        ..Book::new(
+            &id.to_hex(),
+            &user_id.to_hex(),
+            name,
+            author,
+            lang,
+            num_pages as usize,
+            added_at,
+        )
+    };
+    Ok(book)
+}
+
+fn doc_to_progress(doc: &OrderedDocument) -> Result<ProgressEntry> {
+    let id = doc.get_str(PROGRESS_ID)?;
+    let logged_at = doc.get_utc_datetime(LOGGED_AT)?;
+    let page = doc.get_i32(PAGE)?;
+
+    let entry = ProgressEntry {
+        id: id.to_owned(),
+        logged_at: *logged_at,
+        page: page as usize,
+    };
+    Ok(entry)
+}
+
+fn doc_to_note(doc: &OrderedDocument) -> Result<BookNote> {
+    let id = doc.get_str(NOTE_ID)?;
+    let kind = doc.get_str(KIND)?;
+    let text = doc.get_str(TEXT)?;
+    let added_at = doc.get_utc_datetime(ADDED_AT)?;
+
+    let note = BookNote {
+        id: id.to_owned(),
+        kind: NoteKind::parse(kind).unwrap_or(NoteKind::Note),
+        text: text.to_owned(),
+        page: get_optional_int(doc, PAGE),
+        added_at: *added_at,
+    };
+    Ok(note)
+}
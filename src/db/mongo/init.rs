@@ -0,0 +1,136 @@
+//! Creates collections and indexes, safe to run on every start
+
+use crate::{error::Error::*, Result};
+use bson::{doc, ordered::OrderedDocument, Bson};
+use log::info;
+use mongodb::{error::ErrorKind, Database};
+
+const COLLECTIONS: [&str; 8] = [
+    "users",
+    "sessions",
+    "books",
+    "restaurants",
+    "recipes",
+    "todos",
+    "tokens",
+    "tags",
+];
+
+/// Mongo's error code for dropping an index, which doesn't exist
+const INDEX_NOT_FOUND_CODE: i32 = 27;
+
+pub async fn init_collections(db: &Database) -> Result<()> {
+    let existing = db
+        .list_collection_names(None)
+        .await
+        .map_err(MongoQueryError)?;
+    for name in COLLECTIONS.iter() {
+        if !existing.iter().any(|e| e == name) {
+            info!("Creating collection {}", name);
+            db.create_collection(name, None)
+                .await
+                .map_err(MongoQueryError)?;
+        }
+    }
+
+    create_indexes(
+        db,
+        "users",
+        vec![doc! { "key": { "email": 1 }, "name": "email_unique", "unique": true }],
+    )
+    .await?;
+    create_indexes(
+        db,
+        "sessions",
+        vec![
+            doc! { "key": { "session_id": 1 }, "name": "session_id_unique", "unique": true },
+            doc! { "key": { "user_id": 1 }, "name": "user_id" },
+            // lets mongo purge expired sessions as well, in addition to the cleanup task
+            doc! { "key": { "expires_at": 1 }, "name": "expires_at_ttl", "expireAfterSeconds": 0 },
+        ],
+    )
+    .await?;
+    // the first text index read the language of the text from the books' `language` field, so
+    // saving books in languages mongo doesn't know, like "Deutsch", failed
+    drop_index(db, "books", "name_author_text").await?;
+    create_indexes(
+        db,
+        "books",
+        vec![
+            doc! { "key": { "user_id": 1, "added_at": 1 }, "name": "user_id_added_at" },
+            doc! { "key": { "user_id": 1, "name": 1 }, "name": "user_id_name" },
+            doc! { "key": { "user_id": 1, "author": 1 }, "name": "user_id_author" },
+            doc! { "key": { "user_id": 1, "num_pages": 1 }, "name": "user_id_num_pages" },
+            doc! { "key": { "user_id": 1, "status": 1 }, "name": "user_id_status" },
+            doc! { "key": { "user_id": 1, "tags": 1 }, "name": "user_id_tags" },
+            doc! {
+                "key": { "name": "text", "author": "text" },
+                "name": "name_author_search",
+                "default_language": "none",
+                "language_override": "_text_lang",
+            },
+        ],
+    )
+    .await?;
+    create_indexes(
+        db,
+        "tokens",
+        vec![
+            doc! { "key": { "token_hash": 1 }, "name": "token_hash_unique", "unique": true },
+            doc! { "key": { "user_id": 1 }, "name": "user_id" },
+        ],
+    )
+    .await?;
+    // tag names are unique ignoring case, which the first index didn't enforce
+    drop_index(db, "tags", "user_id_name_unique").await?;
+    create_indexes(
+        db,
+        "tags",
+        vec![doc! {
+            "key": { "user_id": 1, "name": 1 },
+            "name": "user_id_name_unique_ci",
+            "unique": true,
+            "collation": { "locale": "en", "strength": 2 },
+        }],
+    )
+    .await?;
+    for name in ["restaurants", "recipes", "todos"].iter() {
+        create_indexes(
+            db,
+            name,
+            vec![doc! { "key": { "user_id": 1 }, "name": "user_id" }],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Creates the given indexes, indexes which already exist with the same definition are ignored
+async fn create_indexes(db: &Database, coll: &str, indexes: Vec<OrderedDocument>) -> Result<()> {
+    let command = doc! {
+        "createIndexes": coll,
+        "indexes": indexes.into_iter().map(Bson::Document).collect::<Vec<Bson>>(),
+    };
+    db.run_command(command, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
+
+/// Drops the index, if it exists
+async fn drop_index(db: &Database, coll: &str, name: &str) -> Result<()> {
+    let command = doc! {
+        "dropIndexes": coll,
+        "index": name,
+    };
+    match db.run_command(command, None).await {
+        Ok(_) => {
+            info!("Dropped index {} of {}", name, coll);
+            Ok(())
+        }
+        Err(e) => match e.kind.as_ref() {
+            ErrorKind::CommandError(c) if c.code == INDEX_NOT_FOUND_CODE => Ok(()),
+            _ => Err(MongoQueryError(e)),
+        },
+    }
+}
@@ -0,0 +1,264 @@
+//! Versioned data migrations, applied in order and recorded in the `migrations` collection
+
+use crate::app::auth::normalize_email;
+use crate::{error::Error::*, Result};
+use bson::ordered::OrderedDocument;
+use bson::{doc, Bson};
+use chrono::prelude::*;
+use futures::StreamExt;
+use log::{info, warn};
+use mongodb::Database;
+use std::future::Future;
+use std::pin::Pin;
+
+const MIGRATIONS: &str = "migrations";
+const VERSION: &str = "version";
+const NAME: &str = "name";
+const APPLIED_AT: &str = "applied_at";
+const ID: &str = "_id";
+const BOOKS: &str = "books";
+const NUM_PAGES: &str = "num_pages";
+const ADDED_AT: &str = "added_at";
+const STATUS: &str = "status";
+const CURRENT_PAGE: &str = "current_page";
+const PROGRESS: &str = "progress";
+const USERS: &str = "users";
+const EMAIL: &str = "email";
+
+type MigrationResult<'a> = Pin<Box<dyn Future<Output = Result<i64>> + Send + 'a>>;
+
+/// A single migration step, which returns the number of (in dry-run mode: affected) documents
+struct Migration {
+    version: i32,
+    name: &'static str,
+    run: for<'a> fn(&'a Database, bool) -> MigrationResult<'a>,
+}
+
+/// All migrations in the order they need to be applied, never change or remove applied ones
+fn all() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            name: "books_num_pages_to_int",
+            run: |db, dry_run| Box::pin(books_num_pages_to_int(db, dry_run)),
+        },
+        Migration {
+            version: 2,
+            name: "books_added_at_from_id",
+            run: |db, dry_run| Box::pin(books_added_at_from_id(db, dry_run)),
+        },
+        Migration {
+            version: 3,
+            name: "books_reading_status",
+            run: |db, dry_run| Box::pin(books_reading_status(db, dry_run)),
+        },
+        Migration {
+            version: 4,
+            name: "users_lowercase_email",
+            run: |db, dry_run| Box::pin(users_lowercase_email(db, dry_run)),
+        },
+    ]
+}
+
+/// Applies all pending migrations, in dry-run mode only reports what would be changed
+pub async fn run(db: &Database, dry_run: bool) -> Result<()> {
+    let applied = applied_versions(db).await?;
+    let pending: Vec<Migration> = all()
+        .into_iter()
+        .filter(|m| !applied.contains(&m.version))
+        .collect();
+    if pending.is_empty() {
+        info!("No pending migrations");
+        return Ok(());
+    }
+
+    for migration in pending {
+        let affected = (migration.run)(db, dry_run).await?;
+        if dry_run {
+            info!(
+                "Migration {} ({}) would change {} documents",
+                migration.version, migration.name, affected
+            );
+            continue;
+        }
+        db.collection(MIGRATIONS)
+            .insert_one(
+                doc! {
+                    VERSION: migration.version,
+                    NAME: migration.name,
+                    APPLIED_AT: Utc::now(),
+                },
+                None,
+            )
+            .await
+            .map_err(MongoQueryError)?;
+        info!(
+            "Applied migration {} ({}), changed {} documents",
+            migration.version, migration.name, affected
+        );
+    }
+    Ok(())
+}
+
+async fn applied_versions(db: &Database) -> Result<Vec<i32>> {
+    let mut cursor = db
+        .collection(MIGRATIONS)
+        .find(None, None)
+        .await
+        .map_err(MongoQueryError)?;
+    let mut result = Vec::new();
+    while let Some(doc) = cursor.next().await {
+        result.push(doc?.get_i32(VERSION)?);
+    }
+    Ok(result)
+}
+
+/// `num_pages` used to be stored as string or double by some clients, normalize it to int.
+/// Missing values become 0, values which aren't numbers are left unchanged and logged.
+async fn books_num_pages_to_int(db: &Database, dry_run: bool) -> Result<i64> {
+    let coll = db.collection(BOOKS);
+    let filter = doc! {
+        NUM_PAGES: { "$not": { "$type": "int" } },
+    };
+    let mut cursor = coll.find(filter, None).await.map_err(MongoQueryError)?;
+    let mut changed = 0;
+    while let Some(doc) = cursor.next().await {
+        let doc = doc?;
+        let num_pages = match doc.get(NUM_PAGES) {
+            None | Some(Bson::Null) => Some(0),
+            Some(Bson::I64(v)) => Some(*v as i32),
+            Some(Bson::FloatingPoint(v)) if v.is_finite() => Some(*v as i32),
+            Some(Bson::String(v)) => v.trim().parse().ok(),
+            _ => None,
+        };
+        let num_pages = match num_pages {
+            Some(num_pages) => num_pages,
+            None => {
+                warn!(
+                    "Not converting {} of book {}, {:?} is not a number and needs to be fixed by hand",
+                    NUM_PAGES,
+                    doc.get_object_id(ID)?,
+                    doc.get(NUM_PAGES)
+                );
+                continue;
+            }
+        };
+        changed += 1;
+        if dry_run {
+            continue;
+        }
+        set_field(db, BOOKS, &doc, NUM_PAGES, Bson::I32(num_pages)).await?;
+    }
+    Ok(changed)
+}
+
+/// Books without a valid `added_at` get the creation time of their ObjectId
+async fn books_added_at_from_id(db: &Database, dry_run: bool) -> Result<i64> {
+    let coll = db.collection(BOOKS);
+    let filter = doc! {
+        ADDED_AT: { "$not": { "$type": "date" } },
+    };
+    let mut cursor = coll.find(filter, None).await.map_err(MongoQueryError)?;
+    let mut changed = 0;
+    while let Some(doc) = cursor.next().await {
+        let doc = doc?;
+        changed += 1;
+        if dry_run {
+            continue;
+        }
+        let created_at = Utc
+            .timestamp_opt(i64::from(doc.get_object_id(ID)?.timestamp()), 0)
+            .single()
+            .expect("ObjectId timestamps are in range");
+        set_field(db, BOOKS, &doc, ADDED_AT, Bson::from(created_at)).await?;
+    }
+    Ok(changed)
+}
+
+/// Books added before reading status tracking are marked as not started, so status filters match
+async fn books_reading_status(db: &Database, dry_run: bool) -> Result<i64> {
+    let coll = db.collection(BOOKS);
+    let filter = doc! {
+        STATUS: { "$exists": false },
+    };
+    if dry_run {
+        return coll
+            .count_documents(filter, None)
+            .await
+            .map_err(MongoQueryError);
+    }
+    let update = doc! {
+        "$set": {
+            STATUS: "want_to_read",
+            CURRENT_PAGE: 0,
+            PROGRESS: Bson::Array(vec![]),
+        },
+    };
+    let result = coll
+        .update_many(filter, update, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(result.modified_count)
+}
+
+/// Logins look up users by their normalized email, so stored emails are normalized as well.
+/// Users whose normalized email is taken by another user are left unchanged and logged.
+async fn users_lowercase_email(db: &Database, dry_run: bool) -> Result<i64> {
+    let coll = db.collection(USERS);
+    let mut cursor = coll.find(None, None).await.map_err(MongoQueryError)?;
+    let mut users = Vec::new();
+    while let Some(doc) = cursor.next().await {
+        users.push(doc?);
+    }
+    let emails: Vec<String> = users
+        .iter()
+        .filter_map(|u| u.get_str(EMAIL).ok())
+        .map(str::to_owned)
+        .collect();
+
+    let mut changed = 0;
+    for user in &users {
+        let email = user.get_str(EMAIL)?;
+        let normalized = normalize_email(email);
+        if normalized == email {
+            continue;
+        }
+        let taken = emails
+            .iter()
+            .any(|other| other != email && normalize_email(other) == normalized);
+        if taken {
+            warn!(
+                "Not normalizing email of user {}, {} is used by another user",
+                user.get_object_id(ID)?,
+                normalized
+            );
+            continue;
+        }
+        changed += 1;
+        if dry_run {
+            continue;
+        }
+        set_field(db, USERS, user, EMAIL, Bson::String(normalized)).await?;
+    }
+    Ok(changed)
+}
+
+async fn set_field(
+    db: &Database,
+    coll: &str,
+    doc: &OrderedDocument,
+    key: &str,
+    value: Bson,
+) -> Result<()> {
+    let query = doc! {
+        ID: doc.get_object_id(ID)?.clone(),
+    };
+    let update = doc! {
+        "$set": { key: value },
+    };
+    db.collection(coll)
+        .update_one(query, update, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
@@ -0,0 +1,436 @@
+//! MongoDB storage backend
+
+use crate::app::books::{BookQuery, EditedBook, NewBook, ReadingState};
+use crate::app::recipes::RecipeEntry;
+use crate::app::restaurants::{EditedRestaurant, NewRestaurant};
+use crate::app::tags::TagEntry;
+use crate::app::todos::{TodoEntry, TodoFilter};
+use crate::data::{ApiToken, Book, NoteKind, Recipe, Restaurant, Session, Tag, Todo, User};
+use crate::db::{
+    BookStore, RecipeStore, RestaurantStore, SessionStore, Store, TagStore, TodoStore, TokenStore,
+    UserStore,
+};
+use crate::{error::Error::*, Result, CONFIG};
+use async_trait::async_trait;
+use bson::doc;
+use chrono::prelude::*;
+use log::{info, warn};
+use mongodb::{
+    error::{ErrorKind, WriteFailure},
+    options::{auth::Credential, ClientOptions},
+    Client, Database,
+};
+
+pub mod books;
+mod init;
+mod migrations;
+pub mod recipes;
+pub mod restaurants;
+pub mod session;
+pub mod tags;
+pub mod todos;
+pub mod token;
+pub mod user;
+
+const DUPLICATE_KEY_CODE: i32 = 11000;
+
+pub struct MongoStore {
+    db: Database,
+}
+
+impl MongoStore {
+    pub async fn init() -> Result<Self> {
+        let db = connect().await?;
+
+        if CONFIG.app.init_db {
+            info!("Initializing collections...");
+            init::init_collections(&db).await?;
+            backfill_book_owners(&db).await?;
+        }
+        if CONFIG.app.run_migrations {
+            migrations::run(&db, false).await?;
+        }
+
+        Ok(MongoStore { db })
+    }
+}
+
+/// Applies pending migrations, without starting the app
+pub async fn migrate(dry_run: bool) -> Result<()> {
+    let db = connect().await?;
+    migrations::run(&db, dry_run).await
+}
+
+async fn connect() -> Result<Database> {
+    let mut client_options =
+        ClientOptions::parse(&format!("mongodb://{}:{}", CONFIG.db.host, CONFIG.db.port)).await?;
+
+    client_options.app_name = Some("Toodeloo".to_string());
+    if !CONFIG.db.user.is_empty() {
+        client_options.credential = Some(
+            Credential::builder()
+                .username(Some(CONFIG.db.user.clone()))
+                .password(Some(CONFIG.db.pw.clone()))
+                .build(),
+        );
+    }
+    info!(
+        "Connecting to MongoDB at {}:{}",
+        CONFIG.db.host, CONFIG.db.port
+    );
+    let client = Client::with_options(client_options)?;
+    Ok(client.database(&CONFIG.db.name))
+}
+
+/// Checks whether a unique index was violated
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    match e.kind.as_ref() {
+        ErrorKind::WriteError(WriteFailure::WriteError(w)) => w.code == DUPLICATE_KEY_CODE,
+        _ => false,
+    }
+}
+
+async fn backfill_book_owners(db: &Database) -> Result<()> {
+    let owner = match CONFIG.app.books_owner {
+        Some(ref email) => Some(user::fetch_user(email, db).await?),
+        None => user::fetch_first_user(db).await?,
+    };
+    match owner {
+        Some(owner) => {
+            let updated = books::backfill_owner(&owner.id, db).await?;
+            if updated > 0 {
+                info!(
+                    "Assigned {} books without owner to {}",
+                    updated, owner.email
+                );
+            }
+        }
+        None => warn!("No users found, books without owner were not backfilled"),
+    };
+    Ok(())
+}
+
+#[async_trait]
+impl Store for MongoStore {
+    async fn ping(&self) -> Result<()> {
+        self.db
+            .run_command(doc! { "ping": 1 }, None)
+            .await
+            .map_err(MongoError)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BookStore for MongoStore {
+    async fn fetch_books(&self, user_id: &str, query: &BookQuery) -> Result<Vec<Book>> {
+        books::fetch_books(user_id, query, &self.db).await
+    }
+
+    async fn count_books(&self, user_id: &str, query: &BookQuery) -> Result<i64> {
+        books::count_books(user_id, query, &self.db).await
+    }
+
+    async fn search_books(&self, user_id: &str, q: &str) -> Result<Vec<Book>> {
+        books::search_books(user_id, q, &self.db).await
+    }
+
+    async fn fetch_book(&self, id: &str, user_id: &str) -> Result<Book> {
+        books::fetch_book(id, user_id, &self.db).await
+    }
+
+    async fn create_book(&self, entry: &NewBook, user_id: &str) -> Result<String> {
+        books::create_book(entry, user_id, &self.db).await
+    }
+
+    async fn import_books(&self, books: &[Book], user_id: &str) -> Result<usize> {
+        books::import_books(books, user_id, &self.db).await
+    }
+
+    async fn edit_book(&self, id: &str, user_id: &str, entry: &EditedBook) -> Result<()> {
+        books::edit_book(id, user_id, entry, &self.db).await
+    }
+
+    async fn delete_book(&self, id: &str, user_id: &str) -> Result<()> {
+        books::delete_book(id, user_id, &self.db).await
+    }
+
+    async fn set_reading_state(&self, id: &str, user_id: &str, state: &ReadingState) -> Result<()> {
+        books::set_reading_state(id, user_id, state, &self.db).await
+    }
+
+    async fn add_progress(
+        &self,
+        id: &str,
+        user_id: &str,
+        logged_at: &DateTime<Utc>,
+        page: usize,
+    ) -> Result<()> {
+        books::add_progress(id, user_id, logged_at, page, &self.db).await
+    }
+
+    async fn delete_progress(&self, id: &str, user_id: &str, progress_id: &str) -> Result<()> {
+        books::delete_progress(id, user_id, progress_id, &self.db).await
+    }
+
+    async fn set_review(
+        &self,
+        id: &str,
+        user_id: &str,
+        rating: Option<usize>,
+        review: &str,
+    ) -> Result<()> {
+        books::set_review(id, user_id, rating, review, &self.db).await
+    }
+
+    async fn add_note(
+        &self,
+        id: &str,
+        user_id: &str,
+        kind: NoteKind,
+        text: &str,
+        page: Option<usize>,
+    ) -> Result<()> {
+        books::add_note(id, user_id, kind, text, page, &self.db).await
+    }
+
+    async fn delete_note(&self, id: &str, user_id: &str, note_id: &str) -> Result<()> {
+        books::delete_note(id, user_id, note_id, &self.db).await
+    }
+
+    async fn set_book_tags(&self, id: &str, user_id: &str, tag_ids: &[String]) -> Result<()> {
+        books::set_book_tags(id, user_id, tag_ids, &self.db).await
+    }
+
+    async fn delete_user_books(&self, user_id: &str) -> Result<()> {
+        books::delete_user_books(user_id, &self.db).await
+    }
+}
+
+#[async_trait]
+impl TagStore for MongoStore {
+    async fn fetch_tags(&self, user_id: &str) -> Result<Vec<Tag>> {
+        tags::fetch_tags(user_id, &self.db).await
+    }
+
+    async fn fetch_tag(&self, id: &str, user_id: &str) -> Result<Tag> {
+        tags::fetch_tag(id, user_id, &self.db).await
+    }
+
+    async fn create_tag(&self, entry: &TagEntry, user_id: &str) -> Result<String> {
+        tags::create_tag(entry, user_id, &self.db).await
+    }
+
+    async fn edit_tag(&self, id: &str, user_id: &str, entry: &TagEntry) -> Result<()> {
+        tags::edit_tag(id, user_id, entry, &self.db).await
+    }
+
+    async fn merge_tag(&self, id: &str, user_id: &str, into_id: &str) -> Result<()> {
+        tags::merge_tag(id, user_id, into_id, &self.db).await
+    }
+
+    async fn delete_tag(&self, id: &str, user_id: &str) -> Result<()> {
+        tags::delete_tag(id, user_id, &self.db).await
+    }
+
+    async fn delete_user_tags(&self, user_id: &str) -> Result<()> {
+        tags::delete_user_tags(user_id, &self.db).await
+    }
+}
+
+#[async_trait]
+impl UserStore for MongoStore {
+    async fn fetch_user(&self, email: &str) -> Result<User> {
+        user::fetch_user(email, &self.db).await
+    }
+
+    async fn fetch_user_by_id(&self, id: &str) -> Result<User> {
+        user::fetch_user_by_id(id, &self.db).await
+    }
+
+    async fn create_user(&self, email: &str, password_hash: &str) -> Result<String> {
+        user::create_user(email, password_hash, &self.db).await
+    }
+
+    async fn update_password(&self, id: &str, password_hash: &str) -> Result<()> {
+        user::update_password(id, password_hash, &self.db).await
+    }
+
+    async fn delete_user(&self, id: &str) -> Result<()> {
+        user::delete_user(id, &self.db).await
+    }
+}
+
+#[async_trait]
+impl SessionStore for MongoStore {
+    async fn create_session(&self, user_id: &str) -> Result<Session> {
+        session::create_session(user_id, &self.db).await
+    }
+
+    async fn find_session(&self, session_id: &str) -> Result<Session> {
+        session::find_session(session_id, &self.db).await
+    }
+
+    async fn touch_session(&self, session: &Session) -> Result<()> {
+        session::touch_session(session, &self.db).await
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<()> {
+        session::delete_session(session_id, &self.db).await
+    }
+
+    async fn delete_user_sessions(
+        &self,
+        user_id: &str,
+        keep_session_id: Option<&str>,
+    ) -> Result<()> {
+        session::delete_user_sessions(user_id, keep_session_id, &self.db).await
+    }
+
+    async fn delete_expired_sessions(&self) -> Result<i64> {
+        session::delete_expired_sessions(&self.db).await
+    }
+}
+
+#[async_trait]
+impl RestaurantStore for MongoStore {
+    async fn fetch_restaurants(&self, user_id: &str) -> Result<Vec<Restaurant>> {
+        restaurants::fetch_restaurants(user_id, &self.db).await
+    }
+
+    async fn fetch_restaurant(&self, id: &str, user_id: &str) -> Result<Restaurant> {
+        restaurants::fetch_restaurant(id, user_id, &self.db).await
+    }
+
+    async fn create_restaurant(&self, entry: &NewRestaurant, user_id: &str) -> Result<()> {
+        restaurants::create_restaurant(entry, user_id, &self.db).await
+    }
+
+    async fn import_restaurants(&self, restaurants: &[Restaurant], user_id: &str) -> Result<usize> {
+        restaurants::import_restaurants(restaurants, user_id, &self.db).await
+    }
+
+    async fn edit_restaurant(
+        &self,
+        id: &str,
+        user_id: &str,
+        entry: &EditedRestaurant,
+    ) -> Result<()> {
+        restaurants::edit_restaurant(id, user_id, entry, &self.db).await
+    }
+
+    async fn delete_restaurant(&self, id: &str, user_id: &str) -> Result<()> {
+        restaurants::delete_restaurant(id, user_id, &self.db).await
+    }
+
+    async fn add_visit(
+        &self,
+        id: &str,
+        user_id: &str,
+        visited_at: &DateTime<Utc>,
+        notes: &str,
+    ) -> Result<()> {
+        restaurants::add_visit(id, user_id, visited_at, notes, &self.db).await
+    }
+
+    async fn delete_visit(&self, id: &str, user_id: &str, visit_id: &str) -> Result<()> {
+        restaurants::delete_visit(id, user_id, visit_id, &self.db).await
+    }
+
+    async fn delete_user_restaurants(&self, user_id: &str) -> Result<()> {
+        restaurants::delete_user_restaurants(user_id, &self.db).await
+    }
+}
+
+#[async_trait]
+impl RecipeStore for MongoStore {
+    async fn fetch_recipes(&self, user_id: &str) -> Result<Vec<Recipe>> {
+        recipes::fetch_recipes(user_id, &self.db).await
+    }
+
+    async fn fetch_recipe(&self, id: &str, user_id: &str) -> Result<Recipe> {
+        recipes::fetch_recipe(id, user_id, &self.db).await
+    }
+
+    async fn create_recipe(&self, entry: &RecipeEntry, user_id: &str) -> Result<()> {
+        recipes::create_recipe(entry, user_id, &self.db).await
+    }
+
+    async fn import_recipes(&self, recipes: &[Recipe], user_id: &str) -> Result<usize> {
+        recipes::import_recipes(recipes, user_id, &self.db).await
+    }
+
+    async fn edit_recipe(&self, id: &str, user_id: &str, entry: &RecipeEntry) -> Result<()> {
+        recipes::edit_recipe(id, user_id, entry, &self.db).await
+    }
+
+    async fn delete_recipe(&self, id: &str, user_id: &str) -> Result<()> {
+        recipes::delete_recipe(id, user_id, &self.db).await
+    }
+
+    async fn delete_user_recipes(&self, user_id: &str) -> Result<()> {
+        recipes::delete_user_recipes(user_id, &self.db).await
+    }
+}
+
+#[async_trait]
+impl TodoStore for MongoStore {
+    async fn fetch_todos(&self, user_id: &str, filter: TodoFilter) -> Result<Vec<Todo>> {
+        todos::fetch_todos(user_id, filter, &self.db).await
+    }
+
+    async fn fetch_todo(&self, id: &str, user_id: &str) -> Result<Todo> {
+        todos::fetch_todo(id, user_id, &self.db).await
+    }
+
+    async fn create_todo(&self, entry: &TodoEntry, user_id: &str) -> Result<()> {
+        todos::create_todo(entry, user_id, &self.db).await
+    }
+
+    async fn import_todos(&self, todos: &[Todo], user_id: &str) -> Result<usize> {
+        todos::import_todos(todos, user_id, &self.db).await
+    }
+
+    async fn edit_todo(&self, id: &str, user_id: &str, entry: &TodoEntry) -> Result<()> {
+        todos::edit_todo(id, user_id, entry, &self.db).await
+    }
+
+    async fn set_todo_done(&self, id: &str, user_id: &str, done: bool) -> Result<()> {
+        todos::set_todo_done(id, user_id, done, &self.db).await
+    }
+
+    async fn delete_todo(&self, id: &str, user_id: &str) -> Result<()> {
+        todos::delete_todo(id, user_id, &self.db).await
+    }
+
+    async fn delete_user_todos(&self, user_id: &str) -> Result<()> {
+        todos::delete_user_todos(user_id, &self.db).await
+    }
+}
+
+#[async_trait]
+impl TokenStore for MongoStore {
+    async fn create_token(&self, user_id: &str, name: &str, token_hash: &str) -> Result<ApiToken> {
+        token::create_token(user_id, name, token_hash, &self.db).await
+    }
+
+    async fn fetch_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>> {
+        token::fetch_tokens(user_id, &self.db).await
+    }
+
+    async fn find_token(&self, token_hash: &str) -> Result<ApiToken> {
+        token::find_token(token_hash, &self.db).await
+    }
+
+    async fn touch_token(&self, id: &str) -> Result<()> {
+        token::touch_token(id, &self.db).await
+    }
+
+    async fn delete_token(&self, id: &str, user_id: &str) -> Result<()> {
+        token::delete_token(id, user_id, &self.db).await
+    }
+
+    async fn delete_user_tokens(&self, user_id: &str) -> Result<()> {
+        token::delete_user_tokens(user_id, &self.db).await
+    }
+}
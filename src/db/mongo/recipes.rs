@@ -0,0 +1,236 @@
+use crate::app::recipes::RecipeEntry;
+use crate::data::{Ingredient, Recipe};
+use crate::{error::Error::*, Result};
+use bson::ordered::OrderedDocument;
+use bson::{doc, oid::ObjectId, Bson};
+use chrono::prelude::*;
+use futures::StreamExt;
+use mongodb::Database;
+
+const RECIPES: &str = "recipes";
+const ID: &str = "_id";
+const USER_ID: &str = "user_id";
+const TITLE: &str = "title";
+const SERVINGS: &str = "servings";
+const INGREDIENTS: &str = "ingredients";
+const STEPS: &str = "steps";
+const PREP_TIME: &str = "prep_time";
+const COOK_TIME: &str = "cook_time";
+const SOURCE: &str = "source";
+const ADDED_AT: &str = "added_at";
+const QUANTITY: &str = "quantity";
+const UNIT: &str = "unit";
+const INGREDIENT_NAME: &str = "name";
+
+pub async fn fetch_recipes(user_id: &str, db: &Database) -> Result<Vec<Recipe>> {
+    let coll = db.collection(RECIPES);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+    };
+
+    let mut cursor = coll.find(filter, None).await.map_err(MongoQueryError)?;
+    let mut result: Vec<Recipe> = Vec::new();
+
+    while let Some(doc) = cursor.next().await {
+        result.push(doc_to_recipe(&doc?)?);
+    }
+    Ok(result)
+}
+
+pub async fn fetch_recipe(id: &str, user_id: &str, db: &Database) -> Result<Recipe> {
+    let coll = db.collection(RECIPES);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+
+    let result = coll.find_one(filter, None).await.map_err(MongoQueryError)?;
+    match result {
+        Some(v) => {
+            let recipe = doc_to_recipe(&v)?;
+            Ok(recipe)
+        }
+        None => Err(NoEntryFoundError(id.to_owned())),
+    }
+}
+
+pub async fn create_recipe(entry: &RecipeEntry, user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(RECIPES);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let doc = doc! {
+        USER_ID: user_oid,
+        TITLE: entry.title.clone(),
+        SERVINGS: entry.servings as i32,
+        INGREDIENTS: ingredients_to_bson(&entry.ingredients),
+        STEPS: steps_to_bson(&entry.steps),
+        PREP_TIME: entry.prep_time as i32,
+        COOK_TIME: entry.cook_time as i32,
+        SOURCE: entry.source.clone(),
+        ADDED_AT: Utc::now(),
+    };
+    coll.insert_one(doc, None).await.map_err(MongoQueryError)?;
+    Ok(())
+}
+
+/// Inserts complete recipes for the user under new ids, returning the number of inserted recipes
+pub async fn import_recipes(recipes: &[Recipe], user_id: &str, db: &Database) -> Result<usize> {
+    if recipes.is_empty() {
+        return Ok(0);
+    }
+    let coll = db.collection(RECIPES);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let docs = recipes.iter().map(|r| {
+        doc! {
+            USER_ID: user_oid.clone(),
+            TITLE: r.title.clone(),
+            SERVINGS: r.servings as i32,
+            INGREDIENTS: ingredients_to_bson(&r.ingredients),
+            STEPS: steps_to_bson(&r.steps),
+            PREP_TIME: r.prep_time as i32,
+            COOK_TIME: r.cook_time as i32,
+            SOURCE: r.source.clone(),
+            ADDED_AT: r.added_at,
+        }
+    });
+    let result = coll
+        .insert_many(docs, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(result.inserted_ids.len())
+}
+
+pub async fn edit_recipe(
+    id: &str,
+    user_id: &str,
+    entry: &RecipeEntry,
+    db: &Database,
+) -> Result<()> {
+    let coll = db.collection(RECIPES);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$set": {
+            TITLE: entry.title.clone(),
+            SERVINGS: entry.servings as i32,
+            INGREDIENTS: ingredients_to_bson(&entry.ingredients),
+            STEPS: steps_to_bson(&entry.steps),
+            PREP_TIME: entry.prep_time as i32,
+            COOK_TIME: entry.cook_time as i32,
+            SOURCE: entry.source.clone(),
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn delete_recipe(id: &str, user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(RECIPES);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let result = coll
+        .delete_one(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.deleted_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Deletes all recipes of the given user
+pub async fn delete_user_recipes(user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(RECIPES);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+    };
+    coll.delete_many(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
+
+fn ingredients_to_bson(ingredients: &[Ingredient]) -> Bson {
+    Bson::Array(
+        ingredients
+            .iter()
+            .map(|i| {
+                Bson::Document(doc! {
+                    QUANTITY: i.quantity,
+                    UNIT: i.unit.clone(),
+                    INGREDIENT_NAME: i.name.clone(),
+                })
+            })
+            .collect(),
+    )
+}
+
+fn steps_to_bson(steps: &[String]) -> Bson {
+    Bson::Array(steps.iter().map(|s| Bson::String(s.clone())).collect())
+}
+
+fn doc_to_recipe(doc: &OrderedDocument) -> Result<Recipe> {
+    let id = doc.get_object_id(ID)?;
+    let user_id = doc.get_object_id(USER_ID)?;
+    let title = doc.get_str(TITLE)?;
+    let servings = doc.get_i32(SERVINGS)?;
+    let prep_time = doc.get_i32(PREP_TIME)?;
+    let cook_time = doc.get_i32(COOK_TIME)?;
+    let source = doc.get_str(SOURCE)?;
+    let added_at = doc.get_utc_datetime(ADDED_AT)?;
+
+    let mut ingredients = Vec::new();
+    for ingredient in doc.get_array(INGREDIENTS)? {
+        if let Bson::Document(i) = ingredient {
+            ingredients.push(Ingredient {
+                quantity: i.get_f64(QUANTITY)?,
+                unit: i.get_str(UNIT)?.to_owned(),
+                name: i.get_str(INGREDIENT_NAME)?.to_owned(),
+            });
+        }
+    }
+
+    let steps = doc
+        .get_array(STEPS)?
+        .iter()
+        .filter_map(|s| s.as_str().map(|s| s.to_owned()))
+        .collect();
+
+    let recipe = Recipe {
+        id: id.to_hex(),
+        user_id: user_id.to_hex(),
+        title: title.to_owned(),
+        servings: servings as usize,
+        ingredients,
+        steps,
+        prep_time: prep_time as usize,
+        cook_time: cook_time as usize,
+        source: source.to_owned(),
+        added_at: *added_at,
+    };
+    Ok(recipe)
+}
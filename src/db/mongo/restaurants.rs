@@ -0,0 +1,288 @@
+use crate::app::restaurants::{EditedRestaurant, NewRestaurant};
+use crate::data::{Restaurant, Visit};
+use crate::{error::Error::*, Result};
+use bson::ordered::OrderedDocument;
+use bson::{doc, oid::ObjectId, Bson};
+use chrono::prelude::*;
+use futures::StreamExt;
+use mongodb::Database;
+use uuid::Uuid;
+
+const RESTAURANTS: &str = "restaurants";
+const ID: &str = "_id";
+const USER_ID: &str = "user_id";
+const NAME: &str = "name";
+const CUISINE: &str = "cuisine";
+const ADDRESS: &str = "address";
+const PRICE_LEVEL: &str = "price_level";
+const RATING: &str = "rating";
+const VISITS: &str = "visits";
+const ADDED_AT: &str = "added_at";
+const VISIT_ID: &str = "id";
+const VISITED_AT: &str = "visited_at";
+const NOTES: &str = "notes";
+
+pub async fn fetch_restaurants(user_id: &str, db: &Database) -> Result<Vec<Restaurant>> {
+    let coll = db.collection(RESTAURANTS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+    };
+
+    let mut cursor = coll.find(filter, None).await.map_err(MongoQueryError)?;
+    let mut result: Vec<Restaurant> = Vec::new();
+
+    while let Some(doc) = cursor.next().await {
+        result.push(doc_to_restaurant(&doc?)?);
+    }
+    Ok(result)
+}
+
+pub async fn fetch_restaurant(id: &str, user_id: &str, db: &Database) -> Result<Restaurant> {
+    let coll = db.collection(RESTAURANTS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+
+    let result = coll.find_one(filter, None).await.map_err(MongoQueryError)?;
+    match result {
+        Some(v) => {
+            let restaurant = doc_to_restaurant(&v)?;
+            Ok(restaurant)
+        }
+        None => Err(NoEntryFoundError(id.to_owned())),
+    }
+}
+
+pub async fn create_restaurant(entry: &NewRestaurant, user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(RESTAURANTS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let doc = doc! {
+        USER_ID: user_oid,
+        NAME: entry.name.clone(),
+        CUISINE: entry.cuisine.clone(),
+        ADDRESS: entry.address.clone(),
+        PRICE_LEVEL: entry.price_level,
+        RATING: entry.rating,
+        VISITS: Bson::Array(vec![]),
+        ADDED_AT: Utc::now(),
+    };
+    coll.insert_one(doc, None).await.map_err(MongoQueryError)?;
+    Ok(())
+}
+
+/// Inserts complete restaurants for the user under new ids, returning the number of inserted ones
+pub async fn import_restaurants(
+    restaurants: &[Restaurant],
+    user_id: &str,
+    db: &Database,
+) -> Result<usize> {
+    if restaurants.is_empty() {
+        return Ok(0);
+    }
+    let coll = db.collection(RESTAURANTS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let docs = restaurants.iter().map(|r| {
+        let visits = r
+            .visits
+            .iter()
+            .map(|v| {
+                Bson::Document(doc! {
+                    VISIT_ID: v.id.clone(),
+                    VISITED_AT: v.visited_at,
+                    NOTES: v.notes.clone(),
+                })
+            })
+            .collect::<Vec<Bson>>();
+        doc! {
+            USER_ID: user_oid.clone(),
+            NAME: r.name.clone(),
+            CUISINE: r.cuisine.clone(),
+            ADDRESS: r.address.clone(),
+            PRICE_LEVEL: r.price_level as i32,
+            RATING: r.rating as i32,
+            VISITS: visits,
+            ADDED_AT: r.added_at,
+        }
+    });
+    let result = coll
+        .insert_many(docs, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(result.inserted_ids.len())
+}
+
+pub async fn edit_restaurant(
+    id: &str,
+    user_id: &str,
+    entry: &EditedRestaurant,
+    db: &Database,
+) -> Result<()> {
+    let coll = db.collection(RESTAURANTS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$set": {
+            NAME: entry.name.clone(),
+            CUISINE: entry.cuisine.clone(),
+            ADDRESS: entry.address.clone(),
+            PRICE_LEVEL: entry.price_level,
+            RATING: entry.rating,
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn delete_restaurant(id: &str, user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(RESTAURANTS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let result = coll
+        .delete_one(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.deleted_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn add_visit(
+    id: &str,
+    user_id: &str,
+    visited_at: &DateTime<Utc>,
+    notes: &str,
+    db: &Database,
+) -> Result<()> {
+    let coll = db.collection(RESTAURANTS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$push": {
+            VISITS: {
+                VISIT_ID: Uuid::new_v4().to_string(),
+                VISITED_AT: *visited_at,
+                NOTES: notes,
+            }
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn delete_visit(id: &str, user_id: &str, visit_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(RESTAURANTS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$pull": {
+            VISITS: { VISIT_ID: visit_id }
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.modified_count == 0 {
+        return Err(NoEntryFoundError(visit_id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Deletes all restaurants of the given user
+pub async fn delete_user_restaurants(user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(RESTAURANTS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+    };
+    coll.delete_many(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
+
+fn doc_to_restaurant(doc: &OrderedDocument) -> Result<Restaurant> {
+    let id = doc.get_object_id(ID)?;
+    let user_id = doc.get_object_id(USER_ID)?;
+    let name = doc.get_str(NAME)?;
+    let cuisine = doc.get_str(CUISINE)?;
+    let address = doc.get_str(ADDRESS)?;
+    let price_level = doc.get_i32(PRICE_LEVEL)?;
+    let rating = doc.get_i32(RATING)?;
+    let added_at = doc.get_utc_datetime(ADDED_AT)?;
+
+    let mut visits = Vec::new();
+    for visit in doc.get_array(VISITS)? {
+        if let Bson::Document(v) = visit {
+            visits.push(doc_to_visit(v)?);
+        }
+    }
+
+    let restaurant = Restaurant {
+        id: id.to_hex(),
+        user_id: user_id.to_hex(),
+        name: name.to_owned(),
+        cuisine: cuisine.to_owned(),
+        address: address.to_owned(),
+        price_level: price_level as usize,
+        rating: rating as usize,
+        visits,
+        added_at: *added_at,
+    };
+    Ok(restaurant)
+}
+
+fn doc_to_visit(doc: &OrderedDocument) -> Result<Visit> {
+    let id = doc.get_str(VISIT_ID)?;
+    let visited_at = doc.get_utc_datetime(VISITED_AT)?;
+    let notes = doc.get_str(NOTES)?;
+
+    let visit = Visit {
+        id: id.to_owned(),
+        visited_at: *visited_at,
+        notes: notes.to_owned(),
+    };
+    Ok(visit)
+}
@@ -0,0 +1,150 @@
+use crate::data::Session;
+use crate::db::session_expiry;
+use crate::{error::Error::*, Result};
+use bson::ordered::OrderedDocument;
+use bson::{doc, oid::ObjectId, Bson};
+use chrono::prelude::*;
+use mongodb::Database;
+use uuid::Uuid;
+
+const SESSIONS: &str = "sessions";
+const ID: &str = "_id";
+const SESSION_ID: &str = "session_id";
+const USER_ID: &str = "user_id";
+const CREATED_AT: &str = "created_at";
+const LAST_SEEN_AT: &str = "last_seen_at";
+const EXPIRES_AT: &str = "expires_at";
+
+pub async fn create_session(user_id: &str, db: &Database) -> Result<Session> {
+    let coll = db.collection(SESSIONS);
+    let oid = ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let session_id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let expires_at = session_expiry(&now, &now);
+    let doc = doc! {
+        SESSION_ID: session_id.clone(),
+        USER_ID: oid,
+        CREATED_AT: now,
+        LAST_SEEN_AT: now,
+        EXPIRES_AT: expires_at,
+    };
+    let result = coll.insert_one(doc, None).await.map_err(MongoQueryError)?;
+    let id = match result.inserted_id {
+        Bson::ObjectId(oid) => oid.to_hex(),
+        _ => return Err(CreateSessionError),
+    };
+    Ok(Session {
+        id,
+        session_id,
+        user_id: user_id.to_owned(),
+        created_at: now,
+        last_seen_at: now,
+        expires_at,
+    })
+}
+
+/// Finds a session, which has not expired yet
+pub async fn find_session(session_id: &str, db: &Database) -> Result<Session> {
+    let coll = db.collection(SESSIONS);
+    let filter = doc! {
+        SESSION_ID: session_id,
+        EXPIRES_AT: { "$gt": Utc::now() },
+    };
+
+    let result = coll.find_one(filter, None).await.map_err(MongoQueryError)?;
+    match result {
+        Some(v) => {
+            let session = doc_to_session(&v)?;
+            Ok(session)
+        }
+        None => Err(NoEntryFoundError(session_id.to_owned())),
+    }
+}
+
+/// Marks the session as active, sliding its expiry
+pub async fn touch_session(session: &Session, db: &Database) -> Result<()> {
+    let coll = db.collection(SESSIONS);
+    let now = Utc::now();
+    let query = doc! {
+        SESSION_ID: session.session_id.clone(),
+    };
+    let doc = doc! {
+        "$set": {
+            LAST_SEEN_AT: now,
+            EXPIRES_AT: session_expiry(&session.created_at, &now),
+        }
+    };
+    coll.update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
+
+pub async fn delete_session(session_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(SESSIONS);
+    let filter = doc! {
+        SESSION_ID: session_id,
+    };
+    coll.delete_one(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
+
+/// Deletes all sessions of the given user, except for the session with `keep_session_id`
+pub async fn delete_user_sessions(
+    user_id: &str,
+    keep_session_id: Option<&str>,
+    db: &Database,
+) -> Result<()> {
+    let coll = db.collection(SESSIONS);
+    let oid = ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = match keep_session_id {
+        Some(session_id) => doc! {
+            USER_ID: oid,
+            SESSION_ID: { "$ne": session_id },
+        },
+        None => doc! {
+            USER_ID: oid,
+        },
+    };
+    coll.delete_many(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
+
+/// Deletes all expired sessions, as well as sessions created before expiry was tracked
+pub async fn delete_expired_sessions(db: &Database) -> Result<i64> {
+    let coll = db.collection(SESSIONS);
+    let filter = doc! {
+        "$or": [
+            { EXPIRES_AT: { "$lte": Utc::now() } },
+            { EXPIRES_AT: { "$exists": false } },
+        ]
+    };
+    let result = coll
+        .delete_many(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(result.deleted_count)
+}
+
+fn doc_to_session(doc: &OrderedDocument) -> Result<Session> {
+    let id = doc.get_object_id(ID)?;
+    let session_id = doc.get_str(SESSION_ID)?;
+    let user_id = doc.get_object_id(USER_ID)?;
+    let created_at = doc.get_utc_datetime(CREATED_AT)?;
+    let last_seen_at = doc.get_utc_datetime(LAST_SEEN_AT)?;
+    let expires_at = doc.get_utc_datetime(EXPIRES_AT)?;
+
+    let session = Session {
+        id: id.to_hex(),
+        session_id: session_id.to_owned(),
+        user_id: user_id.to_hex(),
+        created_at: *created_at,
+        last_seen_at: *last_seen_at,
+        expires_at: *expires_at,
+    };
+    Ok(session)
+}
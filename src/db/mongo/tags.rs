@@ -0,0 +1,189 @@
+use super::{books, is_duplicate_key};
+use crate::app::tags::{same_name, TagEntry};
+use crate::data::Tag;
+use crate::{error::Error::*, Result};
+use bson::ordered::OrderedDocument;
+use bson::{doc, oid::ObjectId, Bson};
+use chrono::prelude::*;
+use futures::StreamExt;
+use mongodb::{options::FindOptions, Database};
+
+const TAGS: &str = "tags";
+const ID: &str = "_id";
+const USER_ID: &str = "user_id";
+const NAME: &str = "name";
+const COLOR: &str = "color";
+const ADDED_AT: &str = "added_at";
+
+pub async fn fetch_tags(user_id: &str, db: &Database) -> Result<Vec<Tag>> {
+    let coll = db.collection(TAGS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+    };
+    let options = FindOptions::builder().sort(doc! { NAME: 1 }).build();
+
+    let mut cursor = coll.find(filter, options).await.map_err(MongoQueryError)?;
+    let mut result: Vec<Tag> = Vec::new();
+
+    while let Some(doc) = cursor.next().await {
+        result.push(doc_to_tag(&doc?)?);
+    }
+    Ok(result)
+}
+
+pub async fn fetch_tag(id: &str, user_id: &str, db: &Database) -> Result<Tag> {
+    let coll = db.collection(TAGS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+
+    let result = coll.find_one(filter, None).await.map_err(MongoQueryError)?;
+    match result {
+        Some(v) => {
+            let tag = doc_to_tag(&v)?;
+            Ok(tag)
+        }
+        None => Err(NoEntryFoundError(id.to_owned())),
+    }
+}
+
+/// Creates a tag and returns the new tag's id
+pub async fn create_tag(entry: &TagEntry, user_id: &str, db: &Database) -> Result<String> {
+    check_name(&entry.name, user_id, None, db).await?;
+    let coll = db.collection(TAGS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let doc = doc! {
+        USER_ID: user_oid,
+        NAME: entry.name.clone(),
+        COLOR: entry.color.clone(),
+        ADDED_AT: Utc::now(),
+    };
+    let result = coll.insert_one(doc, None).await.map_err(|e| {
+        if is_duplicate_key(&e) {
+            TagExistsError(entry.name.clone())
+        } else {
+            MongoQueryError(e)
+        }
+    })?;
+    match result.inserted_id {
+        Bson::ObjectId(oid) => Ok(oid.to_hex()),
+        _ => Err(InvalidIDError(entry.name.clone())),
+    }
+}
+
+/// Tagged items only reference the tag's id, so renaming doesn't need to touch them
+pub async fn edit_tag(id: &str, user_id: &str, entry: &TagEntry, db: &Database) -> Result<()> {
+    check_name(&entry.name, user_id, Some(id), db).await?;
+    let coll = db.collection(TAGS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$set": {
+            NAME: entry.name.clone(),
+            COLOR: entry.color.clone(),
+        }
+    };
+    let result = coll.update_one(query, doc, None).await.map_err(|e| {
+        if is_duplicate_key(&e) {
+            TagExistsError(entry.name.clone())
+        } else {
+            MongoQueryError(e)
+        }
+    })?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Fails if another of the user's tags has the same name, ignoring case. The unique index
+/// catches concurrent requests, which both pass this check.
+async fn check_name(
+    name: &str,
+    user_id: &str,
+    except_id: Option<&str>,
+    db: &Database,
+) -> Result<()> {
+    let exists = fetch_tags(user_id, db)
+        .await?
+        .iter()
+        .any(|t| same_name(&t.name, name) && Some(t.id.as_str()) != except_id);
+    if exists {
+        return Err(TagExistsError(name.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn merge_tag(id: &str, user_id: &str, into_id: &str, db: &Database) -> Result<()> {
+    fetch_tag(id, user_id, db).await?;
+    books::replace_tag(user_id, id, into_id, db).await?;
+    delete_tag_doc(id, user_id, db).await
+}
+
+pub async fn delete_tag(id: &str, user_id: &str, db: &Database) -> Result<()> {
+    fetch_tag(id, user_id, db).await?;
+    books::remove_tag(user_id, id, db).await?;
+    delete_tag_doc(id, user_id, db).await
+}
+
+async fn delete_tag_doc(id: &str, user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(TAGS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let result = coll
+        .delete_one(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.deleted_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Deletes all tags of the given user
+pub async fn delete_user_tags(user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(TAGS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+    };
+    coll.delete_many(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
+
+fn doc_to_tag(doc: &OrderedDocument) -> Result<Tag> {
+    let id = doc.get_object_id(ID)?;
+    let user_id = doc.get_object_id(USER_ID)?;
+    let name = doc.get_str(NAME)?;
+    let color = doc.get_str(COLOR)?;
+    let added_at = doc.get_utc_datetime(ADDED_AT)?;
+
+    let tag = Tag {
+        id: id.to_hex(),
+        user_id: user_id.to_hex(),
+        name: name.to_owned(),
+        color: color.to_owned(),
+        added_at: *added_at,
+    };
+    Ok(tag)
+}
@@ -0,0 +1,251 @@
+use crate::app::todos::{TodoEntry, TodoFilter};
+use crate::data::{start_of_day, Priority, Todo};
+use crate::{error::Error::*, Result};
+use bson::ordered::OrderedDocument;
+use bson::{doc, oid::ObjectId, Bson};
+use chrono::prelude::*;
+use futures::StreamExt;
+use mongodb::{options::FindOptions, Database};
+
+const TODOS: &str = "todos";
+const ID: &str = "_id";
+const USER_ID: &str = "user_id";
+const TITLE: &str = "title";
+const DESCRIPTION: &str = "description";
+const DUE_AT: &str = "due_at";
+const PRIORITY: &str = "priority";
+const DONE: &str = "done";
+const COMPLETED_AT: &str = "completed_at";
+const ADDED_AT: &str = "added_at";
+
+pub async fn fetch_todos(user_id: &str, filter: TodoFilter, db: &Database) -> Result<Vec<Todo>> {
+    let coll = db.collection(TODOS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let (query, sort) = match filter {
+        TodoFilter::Open => (
+            doc! {
+                USER_ID: user_oid,
+                DONE: false,
+            },
+            doc! { DUE_AT: 1 },
+        ),
+        TodoFilter::Overdue => (
+            doc! {
+                USER_ID: user_oid,
+                DONE: false,
+                DUE_AT: { "$lt": start_of_day(Utc::now().date_naive()) },
+            },
+            doc! { DUE_AT: 1 },
+        ),
+        TodoFilter::Done => (
+            doc! {
+                USER_ID: user_oid,
+                DONE: true,
+            },
+            doc! { COMPLETED_AT: -1 },
+        ),
+    };
+    let options = FindOptions::builder().sort(sort).build();
+
+    let mut cursor = coll.find(query, options).await.map_err(MongoQueryError)?;
+    let mut result: Vec<Todo> = Vec::new();
+
+    while let Some(doc) = cursor.next().await {
+        result.push(doc_to_todo(&doc?)?);
+    }
+    Ok(result)
+}
+
+pub async fn fetch_todo(id: &str, user_id: &str, db: &Database) -> Result<Todo> {
+    let coll = db.collection(TODOS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+
+    let result = coll.find_one(filter, None).await.map_err(MongoQueryError)?;
+    match result {
+        Some(v) => {
+            let todo = doc_to_todo(&v)?;
+            Ok(todo)
+        }
+        None => Err(NoEntryFoundError(id.to_owned())),
+    }
+}
+
+pub async fn create_todo(entry: &TodoEntry, user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(TODOS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let doc = doc! {
+        USER_ID: user_oid,
+        TITLE: entry.title.clone(),
+        DESCRIPTION: entry.description.clone(),
+        DUE_AT: optional_date(&entry.due_at),
+        PRIORITY: entry.priority.as_str(),
+        DONE: false,
+        COMPLETED_AT: Bson::Null,
+        ADDED_AT: Utc::now(),
+    };
+    coll.insert_one(doc, None).await.map_err(MongoQueryError)?;
+    Ok(())
+}
+
+/// Inserts complete todos for the user under new ids, returning the number of inserted todos
+pub async fn import_todos(todos: &[Todo], user_id: &str, db: &Database) -> Result<usize> {
+    if todos.is_empty() {
+        return Ok(0);
+    }
+    let coll = db.collection(TODOS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let docs = todos.iter().map(|t| {
+        doc! {
+            USER_ID: user_oid.clone(),
+            TITLE: t.title.clone(),
+            DESCRIPTION: t.description.clone(),
+            DUE_AT: optional_date(&t.due_at),
+            PRIORITY: t.priority.as_str(),
+            DONE: t.done,
+            COMPLETED_AT: optional_date(&t.completed_at),
+            ADDED_AT: t.added_at,
+        }
+    });
+    let result = coll
+        .insert_many(docs, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(result.inserted_ids.len())
+}
+
+pub async fn edit_todo(id: &str, user_id: &str, entry: &TodoEntry, db: &Database) -> Result<()> {
+    let coll = db.collection(TODOS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let doc = doc! {
+        "$set": {
+            TITLE: entry.title.clone(),
+            DESCRIPTION: entry.description.clone(),
+            DUE_AT: optional_date(&entry.due_at),
+            PRIORITY: entry.priority.as_str(),
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Marks the todo as done or undone, setting `completed_at` accordingly
+pub async fn set_todo_done(id: &str, user_id: &str, done: bool, db: &Database) -> Result<()> {
+    let coll = db.collection(TODOS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let completed_at = if done {
+        Bson::from(Utc::now())
+    } else {
+        Bson::Null
+    };
+    let doc = doc! {
+        "$set": {
+            DONE: done,
+            COMPLETED_AT: completed_at,
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn delete_todo(id: &str, user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(TODOS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let result = coll
+        .delete_one(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.deleted_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Deletes all todos of the given user
+pub async fn delete_user_todos(user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(TODOS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+    };
+    coll.delete_many(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
+
+fn optional_date(date: &Option<DateTime<Utc>>) -> Bson {
+    match date {
+        Some(d) => Bson::from(*d),
+        None => Bson::Null,
+    }
+}
+
+fn get_optional_date(doc: &OrderedDocument, key: &str) -> Option<DateTime<Utc>> {
+    match doc.get(key) {
+        Some(Bson::UtcDatetime(d)) => Some(*d),
+        _ => None,
+    }
+}
+
+fn doc_to_todo(doc: &OrderedDocument) -> Result<Todo> {
+    let id = doc.get_object_id(ID)?;
+    let user_id = doc.get_object_id(USER_ID)?;
+    let title = doc.get_str(TITLE)?;
+    let description = doc.get_str(DESCRIPTION)?;
+    let priority = doc.get_str(PRIORITY)?;
+    let done = doc.get_bool(DONE)?;
+    let added_at = doc.get_utc_datetime(ADDED_AT)?;
+
+    let todo = Todo {
+        id: id.to_hex(),
+        user_id: user_id.to_hex(),
+        title: title.to_owned(),
+        description: description.to_owned(),
+        due_at: get_optional_date(doc, DUE_AT),
+        priority: Priority::parse(priority).unwrap_or(Priority::Medium),
+        done,
+        completed_at: get_optional_date(doc, COMPLETED_AT),
+        added_at: *added_at,
+    };
+    Ok(todo)
+}
@@ -0,0 +1,151 @@
+use crate::data::ApiToken;
+use crate::{error::Error::*, Result};
+use bson::ordered::OrderedDocument;
+use bson::{doc, oid::ObjectId, Bson};
+use chrono::prelude::*;
+use futures::StreamExt;
+use mongodb::{options::FindOptions, Database};
+
+const TOKENS: &str = "tokens";
+const ID: &str = "_id";
+const USER_ID: &str = "user_id";
+const NAME: &str = "name";
+const TOKEN_HASH: &str = "token_hash";
+const CREATED_AT: &str = "created_at";
+const LAST_USED_AT: &str = "last_used_at";
+
+/// Stores a new token for the user, only the hash of the token itself is persisted
+pub async fn create_token(
+    user_id: &str,
+    name: &str,
+    token_hash: &str,
+    db: &Database,
+) -> Result<ApiToken> {
+    let coll = db.collection(TOKENS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let now = Utc::now();
+    let doc = doc! {
+        USER_ID: user_oid,
+        NAME: name,
+        TOKEN_HASH: token_hash,
+        CREATED_AT: now,
+        LAST_USED_AT: Bson::Null,
+    };
+    let result = coll.insert_one(doc, None).await.map_err(MongoQueryError)?;
+    let id = match result.inserted_id {
+        Bson::ObjectId(oid) => oid.to_hex(),
+        _ => return Err(InvalidIDError(name.to_owned())),
+    };
+    Ok(ApiToken {
+        id,
+        user_id: user_id.to_owned(),
+        name: name.to_owned(),
+        created_at: now,
+        last_used_at: None,
+    })
+}
+
+pub async fn fetch_tokens(user_id: &str, db: &Database) -> Result<Vec<ApiToken>> {
+    let coll = db.collection(TOKENS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+    };
+    let options = FindOptions::builder().sort(doc! { CREATED_AT: -1 }).build();
+
+    let mut cursor = coll.find(filter, options).await.map_err(MongoQueryError)?;
+    let mut result: Vec<ApiToken> = Vec::new();
+
+    while let Some(doc) = cursor.next().await {
+        result.push(doc_to_token(&doc?)?);
+    }
+    Ok(result)
+}
+
+pub async fn find_token(token_hash: &str, db: &Database) -> Result<ApiToken> {
+    let coll = db.collection(TOKENS);
+    let filter = doc! {
+        TOKEN_HASH: token_hash,
+    };
+
+    let result = coll.find_one(filter, None).await.map_err(MongoQueryError)?;
+    match result {
+        Some(v) => {
+            let token = doc_to_token(&v)?;
+            Ok(token)
+        }
+        None => Err(NoEntryFoundError(token_hash.to_owned())),
+    }
+}
+
+pub async fn touch_token(id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(TOKENS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+    };
+    let doc = doc! {
+        "$set": {
+            LAST_USED_AT: Utc::now(),
+        }
+    };
+    coll.update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
+
+pub async fn delete_token(id: &str, user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(TOKENS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        ID: oid,
+        USER_ID: user_oid,
+    };
+    let result = coll
+        .delete_one(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.deleted_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Deletes all tokens of the given user
+pub async fn delete_user_tokens(user_id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(TOKENS);
+    let user_oid =
+        ObjectId::with_string(user_id).map_err(|_| InvalidIDError(user_id.to_owned()))?;
+    let filter = doc! {
+        USER_ID: user_oid,
+    };
+    coll.delete_many(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    Ok(())
+}
+
+fn doc_to_token(doc: &OrderedDocument) -> Result<ApiToken> {
+    let id = doc.get_object_id(ID)?;
+    let user_id = doc.get_object_id(USER_ID)?;
+    let name = doc.get_str(NAME)?;
+    let created_at = doc.get_utc_datetime(CREATED_AT)?;
+    let last_used_at = match doc.get(LAST_USED_AT) {
+        Some(Bson::UtcDatetime(d)) => Some(*d),
+        _ => None,
+    };
+
+    let token = ApiToken {
+        id: id.to_hex(),
+        user_id: user_id.to_hex(),
+        name: name.to_owned(),
+        created_at: *created_at,
+        last_used_at,
+    };
+    Ok(token)
+}
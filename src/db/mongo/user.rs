@@ -0,0 +1,131 @@
+use super::is_duplicate_key;
+use crate::data::User;
+use crate::{error::Error::*, Result};
+use bson::ordered::OrderedDocument;
+use bson::{doc, oid::ObjectId, Bson};
+use mongodb::{options::FindOneOptions, Database};
+
+const USERS: &str = "users";
+const ID: &str = "_id";
+const EMAIL: &str = "email";
+const PASSWORD: &str = "password";
+
+pub async fn fetch_user(email: &str, db: &Database) -> Result<User> {
+    let coll = db.collection(USERS);
+
+    let filter = doc! {
+        EMAIL: email,
+    };
+
+    let result = coll.find_one(filter, None).await.map_err(MongoQueryError)?;
+    match result {
+        Some(v) => {
+            let user = doc_to_user(&v)?;
+            Ok(user)
+        }
+        None => Err(NoEntryFoundError(email.to_owned())),
+    }
+}
+
+pub async fn fetch_user_by_id(id: &str, db: &Database) -> Result<User> {
+    let coll = db.collection(USERS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+
+    let filter = doc! {
+        ID: oid,
+    };
+
+    let result = coll.find_one(filter, None).await.map_err(MongoQueryError)?;
+    match result {
+        Some(v) => {
+            let user = doc_to_user(&v)?;
+            Ok(user)
+        }
+        None => Err(NoEntryFoundError(id.to_owned())),
+    }
+}
+
+/// Creates a user with an already hashed password and returns the new user's id
+pub async fn create_user(email: &str, password_hash: &str, db: &Database) -> Result<String> {
+    let coll = db.collection(USERS);
+    let doc = doc! {
+        EMAIL: email,
+        PASSWORD: password_hash,
+    };
+    let result = coll.insert_one(doc, None).await.map_err(|e| {
+        if is_duplicate_key(&e) {
+            EmailTakenError(email.to_owned())
+        } else {
+            MongoQueryError(e)
+        }
+    })?;
+    match result.inserted_id {
+        Bson::ObjectId(oid) => Ok(oid.to_hex()),
+        _ => Err(InvalidIDError(email.to_owned())),
+    }
+}
+
+pub async fn update_password(id: &str, password_hash: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(USERS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let query = doc! {
+        ID: oid,
+    };
+    let doc = doc! {
+        "$set": {
+            PASSWORD: password_hash,
+        }
+    };
+    let result = coll
+        .update_one(query, doc, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.matched_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub async fn delete_user(id: &str, db: &Database) -> Result<()> {
+    let coll = db.collection(USERS);
+    let oid = ObjectId::with_string(id).map_err(|_| InvalidIDError(id.to_owned()))?;
+    let filter = doc! {
+        ID: oid,
+    };
+    let result = coll
+        .delete_one(filter, None)
+        .await
+        .map_err(MongoQueryError)?;
+    if result.deleted_count == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Fetches the user who registered first, if there is any
+pub async fn fetch_first_user(db: &Database) -> Result<Option<User>> {
+    let coll = db.collection(USERS);
+    let options = FindOneOptions::builder().sort(doc! { ID: 1 }).build();
+
+    let result = coll
+        .find_one(None, options)
+        .await
+        .map_err(MongoQueryError)?;
+    match result {
+        Some(v) => Ok(Some(doc_to_user(&v)?)),
+        None => Ok(None),
+    }
+}
+
+fn doc_to_user(doc: &OrderedDocument) -> Result<User> {
+    let id = doc.get_object_id(ID)?;
+    let email = doc.get_str(EMAIL)?;
+    let password = doc.get_str(PASSWORD)?;
+
+    let user = User {
+        id: id.to_hex(),
+        email: email.to_owned(),
+        password: password.to_owned(),
+    };
+    Ok(user)
+}
@@ -0,0 +1,395 @@
+use super::{get_date, get_json, get_optional_date, new_id, to_json, to_millis};
+use crate::app::books::{BookQuery, BookSort, EditedBook, NewBook, ReadingState, SortDirection};
+use crate::data::{Book, BookNote, NoteKind, ProgressEntry, ReadingStatus};
+use crate::{error::Error::*, Result};
+use chrono::prelude::*;
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use uuid::Uuid;
+
+const COLUMNS: &str = "id, user_id, name, author, language, num_pages, added_at, status, \
+                       current_page, started_at, finished_at, progress, rating, review, notes, tags";
+const MAX_SEARCH_RESULTS: usize = 100;
+
+pub fn fetch_books(user_id: &str, query: &BookQuery, conn: &Connection) -> Result<Vec<Book>> {
+    let (condition, params) = books_filter(user_id, query);
+    let column = match query.sort {
+        BookSort::Name => "name",
+        BookSort::Author => "author",
+        BookSort::AddedAt => "added_at",
+        BookSort::Pages => "num_pages",
+    };
+    let direction = match query.dir {
+        SortDirection::Asc => "ASC",
+        SortDirection::Desc => "DESC",
+    };
+    // sqlite treats a negative limit as no limit
+    let limit = query.per_page.map(|l| l as i64).unwrap_or(-1);
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM books WHERE {} ORDER BY {} {}, id {} LIMIT {} OFFSET {}",
+        COLUMNS,
+        condition,
+        column,
+        direction,
+        direction,
+        limit,
+        query.skip()
+    ))?;
+    let books = stmt
+        .query_map(&params, row_to_book)?
+        .collect::<rusqlite::Result<Vec<Book>>>()?;
+    Ok(books)
+}
+
+pub fn count_books(user_id: &str, query: &BookQuery, conn: &Connection) -> Result<i64> {
+    let (condition, params) = books_filter(user_id, query);
+    let count = conn.query_row(
+        &format!("SELECT COUNT(*) FROM books WHERE {}", condition),
+        &params,
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+/// Matches substrings of name and author, case-insensitive for ASCII letters
+pub fn search_books(user_id: &str, q: &str, conn: &Connection) -> Result<Vec<Book>> {
+    let pattern = format!(
+        "%{}%",
+        q.replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM books
+         WHERE user_id = ?1 AND (name LIKE ?2 ESCAPE '\\' OR author LIKE ?2 ESCAPE '\\')
+         ORDER BY name LIMIT {}",
+        COLUMNS, MAX_SEARCH_RESULTS
+    ))?;
+    let books = stmt
+        .query_map(params![user_id, pattern], row_to_book)?
+        .collect::<rusqlite::Result<Vec<Book>>>()?;
+    Ok(books)
+}
+
+/// Returns the WHERE condition for the query's filters and its parameters
+fn books_filter(user_id: &str, query: &BookQuery) -> (String, Vec<String>) {
+    let mut condition = String::from("user_id = ?");
+    let mut params = vec![user_id.to_owned()];
+    if let Some(ref author) = query.author {
+        condition.push_str(" AND author = ?");
+        params.push(author.clone());
+    }
+    if let Some(ref language) = query.language {
+        condition.push_str(" AND language = ?");
+        params.push(language.clone());
+    }
+    if let Some(status) = query.status {
+        condition.push_str(" AND status = ?");
+        params.push(status.as_str().to_owned());
+    }
+    if let Some(ref tag) = query.tag {
+        condition.push_str(" AND EXISTS (SELECT 1 FROM json_each(books.tags) WHERE value = ?)");
+        params.push(tag.clone());
+    }
+    (condition, params)
+}
+
+pub fn fetch_book(id: &str, user_id: &str, conn: &Connection) -> Result<Book> {
+    let result = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM books WHERE id = ?1 AND user_id = ?2",
+                COLUMNS
+            ),
+            params![id, user_id],
+            row_to_book,
+        )
+        .optional()?;
+    result.ok_or_else(|| NoEntryFoundError(id.to_owned()))
+}
+
+/// Creates a book and returns the new book's id
+pub fn create_book(entry: &NewBook, user_id: &str, conn: &Connection) -> Result<String> {
+    let id = new_id();
+    conn.execute(
+        &format!(
+            "INSERT INTO books ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 0, NULL, NULL, '[]', NULL, '', '[]', '[]')",
+            COLUMNS
+        ),
+        params![
+            id,
+            user_id,
+            entry.name,
+            entry.author,
+            entry.language,
+            entry.pages,
+            to_millis(&Utc::now()),
+            ReadingStatus::WantToRead.as_str(),
+        ],
+    )?;
+    Ok(id)
+}
+
+/// Inserts complete books for the user under new ids, returning the number of inserted books
+pub fn import_books(books: &[Book], user_id: &str, conn: &Connection) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut stmt = tx.prepare(&format!(
+            "INSERT INTO books ({}) VALUES \
+             (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+            COLUMNS
+        ))?;
+        for book in books {
+            stmt.execute(params![
+                new_id(),
+                user_id,
+                book.name,
+                book.author,
+                book.language,
+                book.num_pages as i64,
+                to_millis(&book.added_at),
+                book.status.as_str(),
+                book.current_page as i64,
+                book.started_at.as_ref().map(to_millis),
+                book.finished_at.as_ref().map(to_millis),
+                to_json(&book.progress)?,
+                book.rating.map(|r| r as i64),
+                book.review,
+                to_json(&book.notes)?,
+                to_json(&book.tags)?,
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(books.len())
+}
+
+pub fn edit_book(id: &str, user_id: &str, entry: &EditedBook, conn: &Connection) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE books SET name = ?1, author = ?2, language = ?3, num_pages = ?4
+         WHERE id = ?5 AND user_id = ?6",
+        params![
+            entry.name,
+            entry.author,
+            entry.language,
+            entry.pages,
+            id,
+            user_id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub fn delete_book(id: &str, user_id: &str, conn: &Connection) -> Result<()> {
+    let deleted = conn.execute(
+        "DELETE FROM books WHERE id = ?1 AND user_id = ?2",
+        params![id, user_id],
+    )?;
+    if deleted == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub fn set_reading_state(
+    id: &str,
+    user_id: &str,
+    state: &ReadingState,
+    conn: &Connection,
+) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE books SET status = ?1, current_page = ?2, started_at = ?3, finished_at = ?4
+         WHERE id = ?5 AND user_id = ?6",
+        params![
+            state.status.as_str(),
+            state.current_page as i64,
+            state.started_at.as_ref().map(to_millis),
+            state.finished_at.as_ref().map(to_millis),
+            id,
+            user_id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub fn add_progress(
+    id: &str,
+    user_id: &str,
+    logged_at: &DateTime<Utc>,
+    page: usize,
+    conn: &Connection,
+) -> Result<()> {
+    let mut book = fetch_book(id, user_id, conn)?;
+    book.progress.push(ProgressEntry {
+        id: Uuid::new_v4().to_string(),
+        logged_at: *logged_at,
+        page,
+    });
+    update_progress(&book, conn)
+}
+
+pub fn delete_progress(
+    id: &str,
+    user_id: &str,
+    progress_id: &str,
+    conn: &Connection,
+) -> Result<()> {
+    let mut book = fetch_book(id, user_id, conn)?;
+    let before = book.progress.len();
+    book.progress.retain(|p| p.id != progress_id);
+    if book.progress.len() == before {
+        return Err(NoEntryFoundError(progress_id.to_owned()));
+    }
+    update_progress(&book, conn)
+}
+
+fn update_progress(book: &Book, conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE books SET progress = ?1 WHERE id = ?2 AND user_id = ?3",
+        params![to_json(&book.progress)?, book.id, book.user_id],
+    )?;
+    Ok(())
+}
+
+pub fn set_review(
+    id: &str,
+    user_id: &str,
+    rating: Option<usize>,
+    review: &str,
+    conn: &Connection,
+) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE books SET rating = ?1, review = ?2 WHERE id = ?3 AND user_id = ?4",
+        params![rating.map(|r| r as i64), review, id, user_id],
+    )?;
+    if updated == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub fn add_note(
+    id: &str,
+    user_id: &str,
+    kind: NoteKind,
+    text: &str,
+    page: Option<usize>,
+    conn: &Connection,
+) -> Result<()> {
+    let mut book = fetch_book(id, user_id, conn)?;
+    book.notes.push(BookNote {
+        id: Uuid::new_v4().to_string(),
+        kind,
+        text: text.to_owned(),
+        page,
+        added_at: Utc::now(),
+    });
+    update_notes(&book, conn)
+}
+
+pub fn delete_note(id: &str, user_id: &str, note_id: &str, conn: &Connection) -> Result<()> {
+    let mut book = fetch_book(id, user_id, conn)?;
+    let before = book.notes.len();
+    book.notes.retain(|n| n.id != note_id);
+    if book.notes.len() == before {
+        return Err(NoEntryFoundError(note_id.to_owned()));
+    }
+    update_notes(&book, conn)
+}
+
+fn update_notes(book: &Book, conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE books SET notes = ?1 WHERE id = ?2 AND user_id = ?3",
+        params![to_json(&book.notes)?, book.id, book.user_id],
+    )?;
+    Ok(())
+}
+
+pub fn set_book_tags(id: &str, user_id: &str, tag_ids: &[String], conn: &Connection) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE books SET tags = ?1 WHERE id = ?2 AND user_id = ?3",
+        params![to_json(&tag_ids)?, id, user_id],
+    )?;
+    if updated == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Replaces the tag `tag_id` with `into_id` on all of the user's books, `None` removes it
+pub fn replace_tag(
+    user_id: &str,
+    tag_id: &str,
+    into_id: Option<&str>,
+    conn: &Connection,
+) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT id, tags FROM books
+         WHERE user_id = ?1 AND EXISTS (SELECT 1 FROM json_each(books.tags) WHERE value = ?2)",
+    )?;
+    let tagged = stmt
+        .query_map(params![user_id, tag_id], |row| {
+            Ok((row.get::<_, String>(0)?, get_json::<Vec<String>>(row, 1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<(String, Vec<String>)>>>()?;
+    for (id, tags) in tagged {
+        let mut replaced: Vec<String> = tags.into_iter().filter(|t| t != tag_id).collect();
+        if let Some(into_id) = into_id {
+            if !replaced.iter().any(|t| t == into_id) {
+                replaced.push(into_id.to_owned());
+            }
+        }
+        set_book_tags(&id, user_id, &replaced, conn)?;
+    }
+    Ok(())
+}
+
+/// Deletes all books of the given user
+pub fn delete_user_books(user_id: &str, conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM books WHERE user_id = ?1", params![user_id])?;
+    Ok(())
+}
+
+fn row_to_book(row: &Row) -> rusqlite::Result<Book> {
+    let id: String = row.get(0)?;
+    let user_id: String = row.get(1)?;
+    let name: String = row.get(2)?;
+    let author: String = row.get(3)?;
+    let lang: String = row.get(4)?;
+    let num_pages: i64 = row.get(5)?;
+    let added_at = get_date(row, 6)?;
+    let status: String = row.get(7)?;
+    let status = ReadingStatus::parse(&status).ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(7, Type::Text, status.clone().into())
+    })?;
+    let current_page: i64 = row.get(8)?;
+
+    let book = Book {
+        status,
+        current_page: current_page as usize,
+        started_at: get_optional_date(row, 9)?,
+        finished_at: get_optional_date(row, 10)?,
+        progress: get_json(row, 11)?,
+        rating: row.get::<_, Option<i64>>(12)?.map(|r| r as usize),
+        review: row.get(13)?,
+        notes: get_json(row, 14)?,
+        tags: get_json(row, 15)?,
+        ..Book::new(
+            &id,
+            &user_id,
+            &name,
+            &author,
+            &lang,
+            num_pages as usize,
+            &added_at,
+        )
+    };
+    Ok(book)
+}
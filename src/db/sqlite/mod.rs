@@ -0,0 +1,547 @@
+//! SQLite storage backend
+//!
+//! rusqlite is synchronous, the connection is shared behind a mutex and queries run directly on
+//! the calling task, which is fine for the small, single-user deployments this backend is for.
+
+use crate::app::books::{BookQuery, EditedBook, NewBook, ReadingState};
+use crate::app::recipes::RecipeEntry;
+use crate::app::restaurants::{EditedRestaurant, NewRestaurant};
+use crate::app::tags::TagEntry;
+use crate::app::todos::{TodoEntry, TodoFilter};
+use crate::data::{ApiToken, Book, NoteKind, Recipe, Restaurant, Session, Tag, Todo, User};
+use crate::db::{
+    BookStore, RecipeStore, RestaurantStore, SessionStore, Store, TagStore, TodoStore, TokenStore,
+    UserStore,
+};
+use crate::{Result, CONFIG};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use log::info;
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Row};
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::{Mutex, MutexGuard};
+use uuid::Uuid;
+
+pub mod books;
+pub mod recipes;
+pub mod restaurants;
+pub mod session;
+pub mod tags;
+pub mod todos;
+pub mod token;
+pub mod user;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY,
+    email TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    session_id TEXT NOT NULL UNIQUE,
+    user_id TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    last_seen_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sessions_user_id ON sessions (user_id);
+CREATE INDEX IF NOT EXISTS sessions_expires_at ON sessions (expires_at);
+CREATE TABLE IF NOT EXISTS books (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    author TEXT NOT NULL,
+    language TEXT NOT NULL,
+    num_pages INTEGER NOT NULL,
+    added_at INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'want_to_read',
+    current_page INTEGER NOT NULL DEFAULT 0,
+    started_at INTEGER,
+    finished_at INTEGER,
+    progress TEXT NOT NULL DEFAULT '[]',
+    rating INTEGER,
+    review TEXT NOT NULL DEFAULT '',
+    notes TEXT NOT NULL DEFAULT '[]',
+    tags TEXT NOT NULL DEFAULT '[]'
+);
+CREATE INDEX IF NOT EXISTS books_user_id ON books (user_id, added_at);
+CREATE TABLE IF NOT EXISTS restaurants (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    cuisine TEXT NOT NULL,
+    address TEXT NOT NULL,
+    price_level INTEGER NOT NULL,
+    rating INTEGER NOT NULL,
+    visits TEXT NOT NULL,
+    added_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS restaurants_user_id ON restaurants (user_id, added_at);
+CREATE TABLE IF NOT EXISTS recipes (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    servings INTEGER NOT NULL,
+    ingredients TEXT NOT NULL,
+    steps TEXT NOT NULL,
+    prep_time INTEGER NOT NULL,
+    cook_time INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    added_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS recipes_user_id ON recipes (user_id, added_at);
+CREATE TABLE IF NOT EXISTS todos (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    due_at INTEGER,
+    priority TEXT NOT NULL,
+    done INTEGER NOT NULL,
+    completed_at INTEGER,
+    added_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS todos_user_id ON todos (user_id, done);
+CREATE TABLE IF NOT EXISTS tokens (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL,
+    last_used_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS tokens_user_id ON tokens (user_id);
+CREATE TABLE IF NOT EXISTS tags (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    color TEXT NOT NULL,
+    added_at INTEGER NOT NULL
+);
+CREATE UNIQUE INDEX IF NOT EXISTS tags_user_id_name ON tags (user_id, name COLLATE NOCASE);
+";
+
+/// Columns added to existing tables after their creation, as (table, column, definition)
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("books", "status", "TEXT NOT NULL DEFAULT 'want_to_read'"),
+    ("books", "current_page", "INTEGER NOT NULL DEFAULT 0"),
+    ("books", "started_at", "INTEGER"),
+    ("books", "finished_at", "INTEGER"),
+    ("books", "progress", "TEXT NOT NULL DEFAULT '[]'"),
+    ("books", "rating", "INTEGER"),
+    ("books", "review", "TEXT NOT NULL DEFAULT ''"),
+    ("books", "notes", "TEXT NOT NULL DEFAULT '[]'"),
+    ("books", "tags", "TEXT NOT NULL DEFAULT '[]'"),
+];
+
+/// Indexes on added columns, which can only be created once the columns exist
+const ADDED_INDEXES: &str = "
+CREATE INDEX IF NOT EXISTS books_user_id_status ON books (user_id, status);
+";
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn init() -> Result<Self> {
+        info!("Opening SQLite database at {}", CONFIG.db.sqlite_path);
+        let conn = Connection::open(&CONFIG.db.sqlite_path)?;
+
+        if CONFIG.app.init_db {
+            info!("Initializing tables...");
+            conn.execute_batch(SCHEMA)?;
+            add_missing_columns(&conn)?;
+            conn.execute_batch(ADDED_INDEXES)?;
+        }
+
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn
+            .lock()
+            .expect("sqlite connection lock is not poisoned")
+    }
+}
+
+/// Brings tables created by an older version up to date, as `CREATE TABLE IF NOT EXISTS` won't
+fn add_missing_columns(conn: &Connection) -> Result<()> {
+    for (table, column, definition) in ADDED_COLUMNS {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let exists = stmt
+            .query_map(params![], |row| row.get::<_, String>(1))?
+            .collect::<rusqlite::Result<Vec<String>>>()?
+            .iter()
+            .any(|c| c == column);
+        if !exists {
+            info!("Adding column {}.{}", table, column);
+            conn.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, definition
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+fn new_id() -> String {
+    Uuid::new_v4().to_simple().to_string()
+}
+
+/// Timestamps are stored as milliseconds since the epoch, so they can be compared in queries
+fn to_millis(date: &DateTime<Utc>) -> i64 {
+    date.timestamp_millis()
+}
+
+fn from_millis(millis: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .expect("stored timestamps are in range")
+}
+
+fn get_date(row: &Row, idx: usize) -> rusqlite::Result<DateTime<Utc>> {
+    Ok(from_millis(row.get(idx)?))
+}
+
+fn get_optional_date(row: &Row, idx: usize) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    let millis: Option<i64> = row.get(idx)?;
+    Ok(millis.map(from_millis))
+}
+
+/// Nested values like restaurant visits or recipe ingredients are stored as JSON text
+fn get_json<T: DeserializeOwned>(row: &Row, idx: usize) -> rusqlite::Result<T> {
+    let json: String = row.get(idx)?;
+    serde_json::from_str(&json)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+fn to_json<T: Serialize>(value: &T) -> rusqlite::Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+#[async_trait]
+impl Store for SqliteStore {
+    async fn ping(&self) -> Result<()> {
+        self.conn().execute_batch("SELECT 1")?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BookStore for SqliteStore {
+    async fn fetch_books(&self, user_id: &str, query: &BookQuery) -> Result<Vec<Book>> {
+        books::fetch_books(user_id, query, &self.conn())
+    }
+
+    async fn count_books(&self, user_id: &str, query: &BookQuery) -> Result<i64> {
+        books::count_books(user_id, query, &self.conn())
+    }
+
+    async fn search_books(&self, user_id: &str, q: &str) -> Result<Vec<Book>> {
+        books::search_books(user_id, q, &self.conn())
+    }
+
+    async fn fetch_book(&self, id: &str, user_id: &str) -> Result<Book> {
+        books::fetch_book(id, user_id, &self.conn())
+    }
+
+    async fn create_book(&self, entry: &NewBook, user_id: &str) -> Result<String> {
+        books::create_book(entry, user_id, &self.conn())
+    }
+
+    async fn import_books(&self, books: &[Book], user_id: &str) -> Result<usize> {
+        books::import_books(books, user_id, &self.conn())
+    }
+
+    async fn edit_book(&self, id: &str, user_id: &str, entry: &EditedBook) -> Result<()> {
+        books::edit_book(id, user_id, entry, &self.conn())
+    }
+
+    async fn delete_book(&self, id: &str, user_id: &str) -> Result<()> {
+        books::delete_book(id, user_id, &self.conn())
+    }
+
+    async fn set_reading_state(&self, id: &str, user_id: &str, state: &ReadingState) -> Result<()> {
+        books::set_reading_state(id, user_id, state, &self.conn())
+    }
+
+    async fn add_progress(
+        &self,
+        id: &str,
+        user_id: &str,
+        logged_at: &DateTime<Utc>,
+        page: usize,
+    ) -> Result<()> {
+        books::add_progress(id, user_id, logged_at, page, &self.conn())
+    }
+
+    async fn delete_progress(&self, id: &str, user_id: &str, progress_id: &str) -> Result<()> {
+        books::delete_progress(id, user_id, progress_id, &self.conn())
+    }
+
+    async fn set_review(
+        &self,
+        id: &str,
+        user_id: &str,
+        rating: Option<usize>,
+        review: &str,
+    ) -> Result<()> {
+        books::set_review(id, user_id, rating, review, &self.conn())
+    }
+
+    async fn add_note(
+        &self,
+        id: &str,
+        user_id: &str,
+        kind: NoteKind,
+        text: &str,
+        page: Option<usize>,
+    ) -> Result<()> {
+        books::add_note(id, user_id, kind, text, page, &self.conn())
+    }
+
+    async fn delete_note(&self, id: &str, user_id: &str, note_id: &str) -> Result<()> {
+        books::delete_note(id, user_id, note_id, &self.conn())
+    }
+
+    async fn set_book_tags(&self, id: &str, user_id: &str, tag_ids: &[String]) -> Result<()> {
+        books::set_book_tags(id, user_id, tag_ids, &self.conn())
+    }
+
+    async fn delete_user_books(&self, user_id: &str) -> Result<()> {
+        books::delete_user_books(user_id, &self.conn())
+    }
+}
+
+#[async_trait]
+impl TagStore for SqliteStore {
+    async fn fetch_tags(&self, user_id: &str) -> Result<Vec<Tag>> {
+        tags::fetch_tags(user_id, &self.conn())
+    }
+
+    async fn fetch_tag(&self, id: &str, user_id: &str) -> Result<Tag> {
+        tags::fetch_tag(id, user_id, &self.conn())
+    }
+
+    async fn create_tag(&self, entry: &TagEntry, user_id: &str) -> Result<String> {
+        tags::create_tag(entry, user_id, &self.conn())
+    }
+
+    async fn edit_tag(&self, id: &str, user_id: &str, entry: &TagEntry) -> Result<()> {
+        tags::edit_tag(id, user_id, entry, &self.conn())
+    }
+
+    async fn merge_tag(&self, id: &str, user_id: &str, into_id: &str) -> Result<()> {
+        tags::merge_tag(id, user_id, into_id, &self.conn())
+    }
+
+    async fn delete_tag(&self, id: &str, user_id: &str) -> Result<()> {
+        tags::delete_tag(id, user_id, &self.conn())
+    }
+
+    async fn delete_user_tags(&self, user_id: &str) -> Result<()> {
+        tags::delete_user_tags(user_id, &self.conn())
+    }
+}
+
+#[async_trait]
+impl UserStore for SqliteStore {
+    async fn fetch_user(&self, email: &str) -> Result<User> {
+        user::fetch_user(email, &self.conn())
+    }
+
+    async fn fetch_user_by_id(&self, id: &str) -> Result<User> {
+        user::fetch_user_by_id(id, &self.conn())
+    }
+
+    async fn create_user(&self, email: &str, password_hash: &str) -> Result<String> {
+        user::create_user(email, password_hash, &self.conn())
+    }
+
+    async fn update_password(&self, id: &str, password_hash: &str) -> Result<()> {
+        user::update_password(id, password_hash, &self.conn())
+    }
+
+    async fn delete_user(&self, id: &str) -> Result<()> {
+        user::delete_user(id, &self.conn())
+    }
+}
+
+#[async_trait]
+impl SessionStore for SqliteStore {
+    async fn create_session(&self, user_id: &str) -> Result<Session> {
+        session::create_session(user_id, &self.conn())
+    }
+
+    async fn find_session(&self, session_id: &str) -> Result<Session> {
+        session::find_session(session_id, &self.conn())
+    }
+
+    async fn touch_session(&self, session: &Session) -> Result<()> {
+        session::touch_session(session, &self.conn())
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<()> {
+        session::delete_session(session_id, &self.conn())
+    }
+
+    async fn delete_user_sessions(
+        &self,
+        user_id: &str,
+        keep_session_id: Option<&str>,
+    ) -> Result<()> {
+        session::delete_user_sessions(user_id, keep_session_id, &self.conn())
+    }
+
+    async fn delete_expired_sessions(&self) -> Result<i64> {
+        session::delete_expired_sessions(&self.conn())
+    }
+}
+
+#[async_trait]
+impl RestaurantStore for SqliteStore {
+    async fn fetch_restaurants(&self, user_id: &str) -> Result<Vec<Restaurant>> {
+        restaurants::fetch_restaurants(user_id, &self.conn())
+    }
+
+    async fn fetch_restaurant(&self, id: &str, user_id: &str) -> Result<Restaurant> {
+        restaurants::fetch_restaurant(id, user_id, &self.conn())
+    }
+
+    async fn create_restaurant(&self, entry: &NewRestaurant, user_id: &str) -> Result<()> {
+        restaurants::create_restaurant(entry, user_id, &self.conn())
+    }
+
+    async fn import_restaurants(&self, restaurants: &[Restaurant], user_id: &str) -> Result<usize> {
+        restaurants::import_restaurants(restaurants, user_id, &self.conn())
+    }
+
+    async fn edit_restaurant(
+        &self,
+        id: &str,
+        user_id: &str,
+        entry: &EditedRestaurant,
+    ) -> Result<()> {
+        restaurants::edit_restaurant(id, user_id, entry, &self.conn())
+    }
+
+    async fn delete_restaurant(&self, id: &str, user_id: &str) -> Result<()> {
+        restaurants::delete_restaurant(id, user_id, &self.conn())
+    }
+
+    async fn add_visit(
+        &self,
+        id: &str,
+        user_id: &str,
+        visited_at: &DateTime<Utc>,
+        notes: &str,
+    ) -> Result<()> {
+        restaurants::add_visit(id, user_id, visited_at, notes, &self.conn())
+    }
+
+    async fn delete_visit(&self, id: &str, user_id: &str, visit_id: &str) -> Result<()> {
+        restaurants::delete_visit(id, user_id, visit_id, &self.conn())
+    }
+
+    async fn delete_user_restaurants(&self, user_id: &str) -> Result<()> {
+        restaurants::delete_user_restaurants(user_id, &self.conn())
+    }
+}
+
+#[async_trait]
+impl RecipeStore for SqliteStore {
+    async fn fetch_recipes(&self, user_id: &str) -> Result<Vec<Recipe>> {
+        recipes::fetch_recipes(user_id, &self.conn())
+    }
+
+    async fn fetch_recipe(&self, id: &str, user_id: &str) -> Result<Recipe> {
+        recipes::fetch_recipe(id, user_id, &self.conn())
+    }
+
+    async fn create_recipe(&self, entry: &RecipeEntry, user_id: &str) -> Result<()> {
+        recipes::create_recipe(entry, user_id, &self.conn())
+    }
+
+    async fn import_recipes(&self, recipes: &[Recipe], user_id: &str) -> Result<usize> {
+        recipes::import_recipes(recipes, user_id, &self.conn())
+    }
+
+    async fn edit_recipe(&self, id: &str, user_id: &str, entry: &RecipeEntry) -> Result<()> {
+        recipes::edit_recipe(id, user_id, entry, &self.conn())
+    }
+
+    async fn delete_recipe(&self, id: &str, user_id: &str) -> Result<()> {
+        recipes::delete_recipe(id, user_id, &self.conn())
+    }
+
+    async fn delete_user_recipes(&self, user_id: &str) -> Result<()> {
+        recipes::delete_user_recipes(user_id, &self.conn())
+    }
+}
+
+#[async_trait]
+impl TodoStore for SqliteStore {
+    async fn fetch_todos(&self, user_id: &str, filter: TodoFilter) -> Result<Vec<Todo>> {
+        todos::fetch_todos(user_id, filter, &self.conn())
+    }
+
+    async fn fetch_todo(&self, id: &str, user_id: &str) -> Result<Todo> {
+        todos::fetch_todo(id, user_id, &self.conn())
+    }
+
+    async fn create_todo(&self, entry: &TodoEntry, user_id: &str) -> Result<()> {
+        todos::create_todo(entry, user_id, &self.conn())
+    }
+
+    async fn import_todos(&self, todos: &[Todo], user_id: &str) -> Result<usize> {
+        todos::import_todos(todos, user_id, &self.conn())
+    }
+
+    async fn edit_todo(&self, id: &str, user_id: &str, entry: &TodoEntry) -> Result<()> {
+        todos::edit_todo(id, user_id, entry, &self.conn())
+    }
+
+    async fn set_todo_done(&self, id: &str, user_id: &str, done: bool) -> Result<()> {
+        todos::set_todo_done(id, user_id, done, &self.conn())
+    }
+
+    async fn delete_todo(&self, id: &str, user_id: &str) -> Result<()> {
+        todos::delete_todo(id, user_id, &self.conn())
+    }
+
+    async fn delete_user_todos(&self, user_id: &str) -> Result<()> {
+        todos::delete_user_todos(user_id, &self.conn())
+    }
+}
+
+#[async_trait]
+impl TokenStore for SqliteStore {
+    async fn create_token(&self, user_id: &str, name: &str, token_hash: &str) -> Result<ApiToken> {
+        token::create_token(user_id, name, token_hash, &self.conn())
+    }
+
+    async fn fetch_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>> {
+        token::fetch_tokens(user_id, &self.conn())
+    }
+
+    async fn find_token(&self, token_hash: &str) -> Result<ApiToken> {
+        token::find_token(token_hash, &self.conn())
+    }
+
+    async fn touch_token(&self, id: &str) -> Result<()> {
+        token::touch_token(id, &self.conn())
+    }
+
+    async fn delete_token(&self, id: &str, user_id: &str) -> Result<()> {
+        token::delete_token(id, user_id, &self.conn())
+    }
+
+    async fn delete_user_tokens(&self, user_id: &str) -> Result<()> {
+        token::delete_user_tokens(user_id, &self.conn())
+    }
+}
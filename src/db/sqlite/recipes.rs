@@ -0,0 +1,143 @@
+use super::{get_date, get_json, new_id, to_json, to_millis};
+use crate::app::recipes::RecipeEntry;
+use crate::data::Recipe;
+use crate::{error::Error::*, Result};
+use chrono::prelude::*;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+const COLUMNS: &str =
+    "id, user_id, title, servings, ingredients, steps, prep_time, cook_time, source, added_at";
+
+pub fn fetch_recipes(user_id: &str, conn: &Connection) -> Result<Vec<Recipe>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM recipes WHERE user_id = ?1 ORDER BY added_at",
+        COLUMNS
+    ))?;
+    let recipes = stmt
+        .query_map(params![user_id], row_to_recipe)?
+        .collect::<rusqlite::Result<Vec<Recipe>>>()?;
+    Ok(recipes)
+}
+
+pub fn fetch_recipe(id: &str, user_id: &str, conn: &Connection) -> Result<Recipe> {
+    let result = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM recipes WHERE id = ?1 AND user_id = ?2",
+                COLUMNS
+            ),
+            params![id, user_id],
+            row_to_recipe,
+        )
+        .optional()?;
+    result.ok_or_else(|| NoEntryFoundError(id.to_owned()))
+}
+
+pub fn create_recipe(entry: &RecipeEntry, user_id: &str, conn: &Connection) -> Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO recipes ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            COLUMNS
+        ),
+        params![
+            new_id(),
+            user_id,
+            entry.title,
+            entry.servings as i64,
+            to_json(&entry.ingredients)?,
+            to_json(&entry.steps)?,
+            entry.prep_time as i64,
+            entry.cook_time as i64,
+            entry.source,
+            to_millis(&Utc::now()),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Inserts complete recipes for the user under new ids, returning the number of inserted recipes
+pub fn import_recipes(recipes: &[Recipe], user_id: &str, conn: &Connection) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut stmt = tx.prepare(&format!(
+            "INSERT INTO recipes ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            COLUMNS
+        ))?;
+        for recipe in recipes {
+            stmt.execute(params![
+                new_id(),
+                user_id,
+                recipe.title,
+                recipe.servings as i64,
+                to_json(&recipe.ingredients)?,
+                to_json(&recipe.steps)?,
+                recipe.prep_time as i64,
+                recipe.cook_time as i64,
+                recipe.source,
+                to_millis(&recipe.added_at),
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(recipes.len())
+}
+
+pub fn edit_recipe(id: &str, user_id: &str, entry: &RecipeEntry, conn: &Connection) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE recipes SET title = ?1, servings = ?2, ingredients = ?3, steps = ?4,
+         prep_time = ?5, cook_time = ?6, source = ?7
+         WHERE id = ?8 AND user_id = ?9",
+        params![
+            entry.title,
+            entry.servings as i64,
+            to_json(&entry.ingredients)?,
+            to_json(&entry.steps)?,
+            entry.prep_time as i64,
+            entry.cook_time as i64,
+            entry.source,
+            id,
+            user_id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub fn delete_recipe(id: &str, user_id: &str, conn: &Connection) -> Result<()> {
+    let deleted = conn.execute(
+        "DELETE FROM recipes WHERE id = ?1 AND user_id = ?2",
+        params![id, user_id],
+    )?;
+    if deleted == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Deletes all recipes of the given user
+pub fn delete_user_recipes(user_id: &str, conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM recipes WHERE user_id = ?1", params![user_id])?;
+    Ok(())
+}
+
+fn row_to_recipe(row: &Row) -> rusqlite::Result<Recipe> {
+    let servings: i64 = row.get(3)?;
+    let prep_time: i64 = row.get(6)?;
+    let cook_time: i64 = row.get(7)?;
+
+    let recipe = Recipe {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        title: row.get(2)?,
+        servings: servings as usize,
+        ingredients: get_json(row, 4)?,
+        steps: get_json(row, 5)?,
+        prep_time: prep_time as usize,
+        cook_time: cook_time as usize,
+        source: row.get(8)?,
+        added_at: get_date(row, 9)?,
+    };
+    Ok(recipe)
+}
@@ -0,0 +1,182 @@
+use super::{get_date, get_json, new_id, to_json, to_millis};
+use crate::app::restaurants::{EditedRestaurant, NewRestaurant};
+use crate::data::{Restaurant, Visit};
+use crate::{error::Error::*, Result};
+use chrono::prelude::*;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use uuid::Uuid;
+
+const COLUMNS: &str = "id, user_id, name, cuisine, address, price_level, rating, visits, added_at";
+
+pub fn fetch_restaurants(user_id: &str, conn: &Connection) -> Result<Vec<Restaurant>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM restaurants WHERE user_id = ?1 ORDER BY added_at",
+        COLUMNS
+    ))?;
+    let restaurants = stmt
+        .query_map(params![user_id], row_to_restaurant)?
+        .collect::<rusqlite::Result<Vec<Restaurant>>>()?;
+    Ok(restaurants)
+}
+
+pub fn fetch_restaurant(id: &str, user_id: &str, conn: &Connection) -> Result<Restaurant> {
+    let result = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM restaurants WHERE id = ?1 AND user_id = ?2",
+                COLUMNS
+            ),
+            params![id, user_id],
+            row_to_restaurant,
+        )
+        .optional()?;
+    result.ok_or_else(|| NoEntryFoundError(id.to_owned()))
+}
+
+pub fn create_restaurant(entry: &NewRestaurant, user_id: &str, conn: &Connection) -> Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO restaurants ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            COLUMNS
+        ),
+        params![
+            new_id(),
+            user_id,
+            entry.name,
+            entry.cuisine,
+            entry.address,
+            entry.price_level,
+            entry.rating,
+            "[]",
+            to_millis(&Utc::now()),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Inserts complete restaurants for the user under new ids, returning the number of inserted ones
+pub fn import_restaurants(
+    restaurants: &[Restaurant],
+    user_id: &str,
+    conn: &Connection,
+) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut stmt = tx.prepare(&format!(
+            "INSERT INTO restaurants ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            COLUMNS
+        ))?;
+        for restaurant in restaurants {
+            stmt.execute(params![
+                new_id(),
+                user_id,
+                restaurant.name,
+                restaurant.cuisine,
+                restaurant.address,
+                restaurant.price_level as i64,
+                restaurant.rating as i64,
+                to_json(&restaurant.visits)?,
+                to_millis(&restaurant.added_at),
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(restaurants.len())
+}
+
+pub fn edit_restaurant(
+    id: &str,
+    user_id: &str,
+    entry: &EditedRestaurant,
+    conn: &Connection,
+) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE restaurants SET name = ?1, cuisine = ?2, address = ?3, price_level = ?4, rating = ?5
+         WHERE id = ?6 AND user_id = ?7",
+        params![
+            entry.name,
+            entry.cuisine,
+            entry.address,
+            entry.price_level,
+            entry.rating,
+            id,
+            user_id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub fn delete_restaurant(id: &str, user_id: &str, conn: &Connection) -> Result<()> {
+    let deleted = conn.execute(
+        "DELETE FROM restaurants WHERE id = ?1 AND user_id = ?2",
+        params![id, user_id],
+    )?;
+    if deleted == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub fn add_visit(
+    id: &str,
+    user_id: &str,
+    visited_at: &DateTime<Utc>,
+    notes: &str,
+    conn: &Connection,
+) -> Result<()> {
+    let mut restaurant = fetch_restaurant(id, user_id, conn)?;
+    restaurant.visits.push(Visit {
+        id: Uuid::new_v4().to_string(),
+        visited_at: *visited_at,
+        notes: notes.to_owned(),
+    });
+    update_visits(&restaurant, conn)
+}
+
+pub fn delete_visit(id: &str, user_id: &str, visit_id: &str, conn: &Connection) -> Result<()> {
+    let mut restaurant = fetch_restaurant(id, user_id, conn)?;
+    let before = restaurant.visits.len();
+    restaurant.visits.retain(|v| v.id != visit_id);
+    if restaurant.visits.len() == before {
+        return Err(NoEntryFoundError(visit_id.to_owned()));
+    }
+    update_visits(&restaurant, conn)
+}
+
+/// Deletes all restaurants of the given user
+pub fn delete_user_restaurants(user_id: &str, conn: &Connection) -> Result<()> {
+    conn.execute(
+        "DELETE FROM restaurants WHERE user_id = ?1",
+        params![user_id],
+    )?;
+    Ok(())
+}
+
+fn update_visits(restaurant: &Restaurant, conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE restaurants SET visits = ?1 WHERE id = ?2",
+        params![to_json(&restaurant.visits)?, restaurant.id],
+    )?;
+    Ok(())
+}
+
+fn row_to_restaurant(row: &Row) -> rusqlite::Result<Restaurant> {
+    let price_level: i64 = row.get(5)?;
+    let rating: i64 = row.get(6)?;
+
+    let restaurant = Restaurant {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        name: row.get(2)?,
+        cuisine: row.get(3)?,
+        address: row.get(4)?,
+        price_level: price_level as usize,
+        rating: rating as usize,
+        visits: get_json(row, 7)?,
+        added_at: get_date(row, 8)?,
+    };
+    Ok(restaurant)
+}
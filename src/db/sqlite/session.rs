@@ -0,0 +1,110 @@
+use super::{get_date, new_id, to_millis};
+use crate::data::Session;
+use crate::db::session_expiry;
+use crate::{error::Error::*, Result};
+use chrono::prelude::*;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use uuid::Uuid;
+
+const COLUMNS: &str = "id, session_id, user_id, created_at, last_seen_at, expires_at";
+
+pub fn create_session(user_id: &str, conn: &Connection) -> Result<Session> {
+    let now = Utc::now();
+    let session = Session {
+        id: new_id(),
+        session_id: Uuid::new_v4().to_string(),
+        user_id: user_id.to_owned(),
+        created_at: now,
+        last_seen_at: now,
+        expires_at: session_expiry(&now, &now),
+    };
+    conn.execute(
+        &format!(
+            "INSERT INTO sessions ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            COLUMNS
+        ),
+        params![
+            session.id,
+            session.session_id,
+            session.user_id,
+            to_millis(&session.created_at),
+            to_millis(&session.last_seen_at),
+            to_millis(&session.expires_at),
+        ],
+    )?;
+    Ok(session)
+}
+
+/// Finds a session, which has not expired yet
+pub fn find_session(session_id: &str, conn: &Connection) -> Result<Session> {
+    let result = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM sessions WHERE session_id = ?1 AND expires_at > ?2",
+                COLUMNS
+            ),
+            params![session_id, to_millis(&Utc::now())],
+            row_to_session,
+        )
+        .optional()?;
+    result.ok_or_else(|| NoEntryFoundError(session_id.to_owned()))
+}
+
+/// Marks the session as active, sliding its expiry
+pub fn touch_session(session: &Session, conn: &Connection) -> Result<()> {
+    let now = Utc::now();
+    conn.execute(
+        "UPDATE sessions SET last_seen_at = ?1, expires_at = ?2 WHERE session_id = ?3",
+        params![
+            to_millis(&now),
+            to_millis(&session_expiry(&session.created_at, &now)),
+            session.session_id
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn delete_session(session_id: &str, conn: &Connection) -> Result<()> {
+    conn.execute(
+        "DELETE FROM sessions WHERE session_id = ?1",
+        params![session_id],
+    )?;
+    Ok(())
+}
+
+/// Deletes all sessions of the given user, except for the session with `keep_session_id`
+pub fn delete_user_sessions(
+    user_id: &str,
+    keep_session_id: Option<&str>,
+    conn: &Connection,
+) -> Result<()> {
+    match keep_session_id {
+        Some(session_id) => conn.execute(
+            "DELETE FROM sessions WHERE user_id = ?1 AND session_id != ?2",
+            params![user_id, session_id],
+        )?,
+        None => conn.execute("DELETE FROM sessions WHERE user_id = ?1", params![user_id])?,
+    };
+    Ok(())
+}
+
+/// Deletes all expired sessions
+pub fn delete_expired_sessions(conn: &Connection) -> Result<i64> {
+    let deleted = conn.execute(
+        "DELETE FROM sessions WHERE expires_at <= ?1",
+        params![to_millis(&Utc::now())],
+    )?;
+    Ok(deleted as i64)
+}
+
+fn row_to_session(row: &Row) -> rusqlite::Result<Session> {
+    let session = Session {
+        id: row.get(0)?,
+        session_id: row.get(1)?,
+        user_id: row.get(2)?,
+        created_at: get_date(row, 3)?,
+        last_seen_at: get_date(row, 4)?,
+        expires_at: get_date(row, 5)?,
+    };
+    Ok(session)
+}
@@ -0,0 +1,119 @@
+use super::{books, get_date, new_id, to_millis};
+use crate::app::tags::{same_name, TagEntry};
+use crate::data::Tag;
+use crate::{error::Error::*, Result};
+use chrono::prelude::*;
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Row};
+
+const COLUMNS: &str = "id, user_id, name, color, added_at";
+
+pub fn fetch_tags(user_id: &str, conn: &Connection) -> Result<Vec<Tag>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM tags WHERE user_id = ?1 ORDER BY name",
+        COLUMNS
+    ))?;
+    let tags = stmt
+        .query_map(params![user_id], row_to_tag)?
+        .collect::<rusqlite::Result<Vec<Tag>>>()?;
+    Ok(tags)
+}
+
+pub fn fetch_tag(id: &str, user_id: &str, conn: &Connection) -> Result<Tag> {
+    let result = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM tags WHERE id = ?1 AND user_id = ?2",
+                COLUMNS
+            ),
+            params![id, user_id],
+            row_to_tag,
+        )
+        .optional()?;
+    result.ok_or_else(|| NoEntryFoundError(id.to_owned()))
+}
+
+/// Creates a tag and returns the new tag's id
+pub fn create_tag(entry: &TagEntry, user_id: &str, conn: &Connection) -> Result<String> {
+    check_name(&entry.name, user_id, None, conn)?;
+    let id = new_id();
+    let result = conn.execute(
+        &format!("INSERT INTO tags ({}) VALUES (?1, ?2, ?3, ?4, ?5)", COLUMNS),
+        params![id, user_id, entry.name, entry.color, to_millis(&Utc::now())],
+    );
+    match result {
+        Ok(_) => Ok(id),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::ConstraintViolation => {
+            Err(TagExistsError(entry.name.clone()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Tagged items only reference the tag's id, so renaming doesn't need to touch them
+pub fn edit_tag(id: &str, user_id: &str, entry: &TagEntry, conn: &Connection) -> Result<()> {
+    check_name(&entry.name, user_id, Some(id), conn)?;
+    let result = conn.execute(
+        "UPDATE tags SET name = ?1, color = ?2 WHERE id = ?3 AND user_id = ?4",
+        params![entry.name, entry.color, id, user_id],
+    );
+    match result {
+        Ok(0) => Err(NoEntryFoundError(id.to_owned())),
+        Ok(_) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::ConstraintViolation => {
+            Err(TagExistsError(entry.name.clone()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn merge_tag(id: &str, user_id: &str, into_id: &str, conn: &Connection) -> Result<()> {
+    fetch_tag(id, user_id, conn)?;
+    books::replace_tag(user_id, id, Some(into_id), conn)?;
+    delete_tag_row(id, user_id, conn)
+}
+
+pub fn delete_tag(id: &str, user_id: &str, conn: &Connection) -> Result<()> {
+    fetch_tag(id, user_id, conn)?;
+    books::replace_tag(user_id, id, None, conn)?;
+    delete_tag_row(id, user_id, conn)
+}
+
+/// Fails if another of the user's tags has the same name, ignoring case. The unique index
+/// ignores ASCII case only, so other letters are compared here.
+fn check_name(name: &str, user_id: &str, except_id: Option<&str>, conn: &Connection) -> Result<()> {
+    let exists = fetch_tags(user_id, conn)?
+        .iter()
+        .any(|t| same_name(&t.name, name) && Some(t.id.as_str()) != except_id);
+    if exists {
+        return Err(TagExistsError(name.to_owned()));
+    }
+    Ok(())
+}
+
+fn delete_tag_row(id: &str, user_id: &str, conn: &Connection) -> Result<()> {
+    let deleted = conn.execute(
+        "DELETE FROM tags WHERE id = ?1 AND user_id = ?2",
+        params![id, user_id],
+    )?;
+    if deleted == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Deletes all tags of the given user
+pub fn delete_user_tags(user_id: &str, conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM tags WHERE user_id = ?1", params![user_id])?;
+    Ok(())
+}
+
+fn row_to_tag(row: &Row) -> rusqlite::Result<Tag> {
+    let tag = Tag {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        name: row.get(2)?,
+        color: row.get(3)?,
+        added_at: get_date(row, 4)?,
+    };
+    Ok(tag)
+}
@@ -0,0 +1,165 @@
+use super::{get_date, get_optional_date, new_id, to_millis};
+use crate::app::todos::{TodoEntry, TodoFilter};
+use crate::data::{start_of_day, Priority, Todo};
+use crate::{error::Error::*, Result};
+use chrono::prelude::*;
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+const COLUMNS: &str =
+    "id, user_id, title, description, due_at, priority, done, completed_at, added_at";
+
+pub fn fetch_todos(user_id: &str, filter: TodoFilter, conn: &Connection) -> Result<Vec<Todo>> {
+    let condition = match filter {
+        TodoFilter::Open => "done = 0 ORDER BY due_at",
+        TodoFilter::Overdue => "done = 0 AND due_at < ?2 ORDER BY due_at",
+        TodoFilter::Done => "done = 1 ORDER BY completed_at DESC",
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM todos WHERE user_id = ?1 AND {}",
+        COLUMNS, condition
+    ))?;
+    let rows = match filter {
+        TodoFilter::Overdue => {
+            let today = to_millis(&start_of_day(Utc::now().date_naive()));
+            stmt.query_map(params![user_id, today], row_to_todo)?
+                .collect::<rusqlite::Result<Vec<Todo>>>()
+        }
+        _ => stmt
+            .query_map(params![user_id], row_to_todo)?
+            .collect::<rusqlite::Result<Vec<Todo>>>(),
+    };
+    Ok(rows?)
+}
+
+pub fn fetch_todo(id: &str, user_id: &str, conn: &Connection) -> Result<Todo> {
+    let result = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM todos WHERE id = ?1 AND user_id = ?2",
+                COLUMNS
+            ),
+            params![id, user_id],
+            row_to_todo,
+        )
+        .optional()?;
+    result.ok_or_else(|| NoEntryFoundError(id.to_owned()))
+}
+
+pub fn create_todo(entry: &TodoEntry, user_id: &str, conn: &Connection) -> Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO todos ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0, NULL, ?7)",
+            COLUMNS
+        ),
+        params![
+            new_id(),
+            user_id,
+            entry.title,
+            entry.description,
+            entry.due_at.as_ref().map(to_millis),
+            entry.priority.as_str(),
+            to_millis(&Utc::now()),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Inserts complete todos for the user under new ids, returning the number of inserted todos
+pub fn import_todos(todos: &[Todo], user_id: &str, conn: &Connection) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut stmt = tx.prepare(&format!(
+            "INSERT INTO todos ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            COLUMNS
+        ))?;
+        for todo in todos {
+            stmt.execute(params![
+                new_id(),
+                user_id,
+                todo.title,
+                todo.description,
+                todo.due_at.as_ref().map(to_millis),
+                todo.priority.as_str(),
+                todo.done,
+                todo.completed_at.as_ref().map(to_millis),
+                to_millis(&todo.added_at),
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(todos.len())
+}
+
+pub fn edit_todo(id: &str, user_id: &str, entry: &TodoEntry, conn: &Connection) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE todos SET title = ?1, description = ?2, due_at = ?3, priority = ?4
+         WHERE id = ?5 AND user_id = ?6",
+        params![
+            entry.title,
+            entry.description,
+            entry.due_at.as_ref().map(to_millis),
+            entry.priority.as_str(),
+            id,
+            user_id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Marks the todo as done or undone, setting `completed_at` accordingly
+pub fn set_todo_done(id: &str, user_id: &str, done: bool, conn: &Connection) -> Result<()> {
+    let completed_at = if done {
+        Some(to_millis(&Utc::now()))
+    } else {
+        None
+    };
+    let updated = conn.execute(
+        "UPDATE todos SET done = ?1, completed_at = ?2 WHERE id = ?3 AND user_id = ?4",
+        params![done, completed_at, id, user_id],
+    )?;
+    if updated == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub fn delete_todo(id: &str, user_id: &str, conn: &Connection) -> Result<()> {
+    let deleted = conn.execute(
+        "DELETE FROM todos WHERE id = ?1 AND user_id = ?2",
+        params![id, user_id],
+    )?;
+    if deleted == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Deletes all todos of the given user
+pub fn delete_user_todos(user_id: &str, conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM todos WHERE user_id = ?1", params![user_id])?;
+    Ok(())
+}
+
+fn row_to_todo(row: &Row) -> rusqlite::Result<Todo> {
+    let priority: String = row.get(5)?;
+    let priority = Priority::parse(&priority).ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(5, Type::Text, priority.clone().into())
+    })?;
+
+    let todo = Todo {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        title: row.get(2)?,
+        description: row.get(3)?,
+        due_at: get_optional_date(row, 4)?,
+        priority,
+        done: row.get(6)?,
+        completed_at: get_optional_date(row, 7)?,
+        added_at: get_date(row, 8)?,
+    };
+    Ok(todo)
+}
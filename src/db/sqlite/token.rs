@@ -0,0 +1,96 @@
+use super::{get_date, get_optional_date, new_id, to_millis};
+use crate::data::ApiToken;
+use crate::{error::Error::*, Result};
+use chrono::prelude::*;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+const COLUMNS: &str = "id, user_id, name, created_at, last_used_at";
+
+/// Stores a new token for the user, only the hash of the token itself is persisted
+pub fn create_token(
+    user_id: &str,
+    name: &str,
+    token_hash: &str,
+    conn: &Connection,
+) -> Result<ApiToken> {
+    let token = ApiToken {
+        id: new_id(),
+        user_id: user_id.to_owned(),
+        name: name.to_owned(),
+        created_at: Utc::now(),
+        last_used_at: None,
+    };
+    conn.execute(
+        "INSERT INTO tokens (id, user_id, name, token_hash, created_at, last_used_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, NULL)",
+        params![
+            token.id,
+            token.user_id,
+            token.name,
+            token_hash,
+            to_millis(&token.created_at)
+        ],
+    )?;
+    Ok(token)
+}
+
+pub fn fetch_tokens(user_id: &str, conn: &Connection) -> Result<Vec<ApiToken>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM tokens WHERE user_id = ?1 ORDER BY created_at DESC",
+        COLUMNS
+    ))?;
+    let tokens = stmt
+        .query_map(params![user_id], row_to_token)?
+        .collect::<rusqlite::Result<Vec<ApiToken>>>()?;
+    Ok(tokens)
+}
+
+pub fn find_token(token_hash: &str, conn: &Connection) -> Result<ApiToken> {
+    let result = conn
+        .query_row(
+            &format!("SELECT {} FROM tokens WHERE token_hash = ?1", COLUMNS),
+            params![token_hash],
+            row_to_token,
+        )
+        .optional()?;
+    result.ok_or_else(|| NoEntryFoundError(token_hash.to_owned()))
+}
+
+pub fn touch_token(id: &str, conn: &Connection) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE tokens SET last_used_at = ?1 WHERE id = ?2",
+        params![to_millis(&Utc::now()), id],
+    )?;
+    if updated == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub fn delete_token(id: &str, user_id: &str, conn: &Connection) -> Result<()> {
+    let deleted = conn.execute(
+        "DELETE FROM tokens WHERE id = ?1 AND user_id = ?2",
+        params![id, user_id],
+    )?;
+    if deleted == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Deletes all tokens of the given user
+pub fn delete_user_tokens(user_id: &str, conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM tokens WHERE user_id = ?1", params![user_id])?;
+    Ok(())
+}
+
+fn row_to_token(row: &Row) -> rusqlite::Result<ApiToken> {
+    let token = ApiToken {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        name: row.get(2)?,
+        created_at: get_date(row, 3)?,
+        last_used_at: get_optional_date(row, 4)?,
+    };
+    Ok(token)
+}
@@ -0,0 +1,70 @@
+use super::new_id;
+use crate::data::User;
+use crate::{error::Error::*, Result};
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Row};
+
+pub fn fetch_user(email: &str, conn: &Connection) -> Result<User> {
+    let result = conn
+        .query_row(
+            "SELECT id, email, password FROM users WHERE email = ?1",
+            params![email],
+            row_to_user,
+        )
+        .optional()?;
+    result.ok_or_else(|| NoEntryFoundError(email.to_owned()))
+}
+
+pub fn fetch_user_by_id(id: &str, conn: &Connection) -> Result<User> {
+    let result = conn
+        .query_row(
+            "SELECT id, email, password FROM users WHERE id = ?1",
+            params![id],
+            row_to_user,
+        )
+        .optional()?;
+    result.ok_or_else(|| NoEntryFoundError(id.to_owned()))
+}
+
+/// Creates a user with an already hashed password and returns the new user's id
+pub fn create_user(email: &str, password_hash: &str, conn: &Connection) -> Result<String> {
+    let id = new_id();
+    let result = conn.execute(
+        "INSERT INTO users (id, email, password) VALUES (?1, ?2, ?3)",
+        params![id, email, password_hash],
+    );
+    match result {
+        Ok(_) => Ok(id),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::ConstraintViolation => {
+            Err(EmailTakenError(email.to_owned()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn update_password(id: &str, password_hash: &str, conn: &Connection) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE users SET password = ?1 WHERE id = ?2",
+        params![password_hash, id],
+    )?;
+    if updated == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+pub fn delete_user(id: &str, conn: &Connection) -> Result<()> {
+    let deleted = conn.execute("DELETE FROM users WHERE id = ?1", params![id])?;
+    if deleted == 0 {
+        return Err(NoEntryFoundError(id.to_owned()));
+    }
+    Ok(())
+}
+
+fn row_to_user(row: &Row) -> rusqlite::Result<User> {
+    let user = User {
+        id: row.get(0)?,
+        email: row.get(1)?,
+        password: row.get(2)?,
+    };
+    Ok(user)
+}
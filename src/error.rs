@@ -1,12 +1,14 @@
+use askama::Template;
+use log::error;
 use serde::Serialize;
-use std::convert::Infallible;
 use thiserror::Error;
 use warp::{
-    http::{StatusCode, Uri},
+    http::{header::ACCEPT, HeaderMap, StatusCode, Uri},
     redirect, reply, Rejection, Reply,
 };
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("mongodb error: {0}")]
     MongoError(#[from] mongodb::error::Error),
@@ -14,6 +16,8 @@ pub enum Error {
     MongoQueryError(mongodb::error::Error),
     #[error("could not access field in document: {0}")]
     MongoDataError(#[from] bson::ordered::ValueAccessError),
+    #[error("sqlite error: {0}")]
+    SqliteError(#[from] rusqlite::Error),
     #[error("could find entry for: {0}")]
     NoEntryFoundError(String),
     #[error("invalid id used: {0}")]
@@ -22,6 +26,22 @@ pub enum Error {
     TemplateError(#[from] askama::Error),
     #[error("error reading file: {0}")]
     ReadFileError(#[from] std::io::Error),
+    #[error("csv error: {0}")]
+    CsvError(#[from] csv::Error),
+    #[error("zip archive error: {0}")]
+    ArchiveError(#[from] zip::result::ZipError),
+    #[error("json error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("invalid backup: {0}")]
+    BackupError(String),
+    #[error("invalid input: {0}")]
+    InvalidInputError(String),
+    #[error("email already registered: {0}")]
+    EmailTakenError(String),
+    #[error("tag already exists: {0}")]
+    TagExistsError(String),
+    #[error("could not hash password: {0}")]
+    PasswordHashError(#[from] bcrypt::BcryptError),
     #[error("invalid credentials used")]
     InvalidCredentials,
     #[error("could not create session")]
@@ -30,50 +50,195 @@ pub enum Error {
     LogoutError,
     #[error("no session found")]
     NoSessionFoundError,
+    #[error("unauthorized")]
+    UnauthorizedError,
+    #[error("missing or invalid csrf token")]
+    InvalidCsrfTokenError,
+    #[error("could not create header: {0}")]
+    HeaderError(#[from] warp::http::header::InvalidHeaderValue),
 }
 
+/// The JSON error, `message` is the short title and `detail` describes what went wrong
 #[derive(Serialize)]
-struct ErrorResponse {
-    message: String,
+struct ErrorResponse<'a> {
+    message: &'a str,
+    detail: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorTemplate<'a> {
+    error: ErrorPage<'a>,
+    csrf_token: &'a str,
+}
+
+struct ErrorPage<'a> {
+    title: &'a str,
+    message: &'a str,
 }
 
 impl warp::reject::Reject for Error {}
 
-pub async fn handle_rejection(err: Rejection) -> std::result::Result<Box<dyn Reply>, Infallible> {
-    let code;
-    let message;
-    // TODO: create template for this
-    // on invalid body, should return to the previous page with an error, depending
+/// Replies to a rejected request with an HTML error page for browsers, which accept HTML, and
+/// with a JSON error for everything else
+pub fn handle_rejection(err: Rejection, headers: &HeaderMap) -> Box<dyn Reply> {
+    let wants_html = headers
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
 
-    if err.is_not_found() {
-        code = StatusCode::NOT_FOUND;
-        message = "Not Found";
-    } else if let Some(_) = err.find::<warp::filters::body::BodyDeserializeError>() {
-        code = StatusCode::BAD_REQUEST;
-        message = "Invalid Body";
+    let (code, title, message) = if err.is_not_found() {
+        (
+            StatusCode::NOT_FOUND,
+            "Not Found",
+            "The requested page does not exist.".to_owned(),
+        )
+    } else if let Some(e) = err.find::<warp::filters::body::BodyDeserializeError>() {
+        (StatusCode::BAD_REQUEST, "Invalid Body", e.to_string())
     } else if let Some(e) = err.find::<Error>() {
-        match e {
-            Error::NoSessionFoundError => {
-                return Ok(Box::new(redirect(Uri::from_static("/login"))));
-            }
-            _ => {
-                eprintln!("unhandled application error: {:?}", err);
-                code = StatusCode::INTERNAL_SERVER_ERROR;
-                message = "Internal Server Error";
+        if let Error::NoSessionFoundError = e {
+            if wants_html {
+                return Box::new(redirect(Uri::from_static("/login")));
             }
         }
-    } else if let Some(_) = err.find::<warp::reject::MethodNotAllowed>() {
-        code = StatusCode::METHOD_NOT_ALLOWED;
-        message = "Method Not Allowed";
+        error_status(e)
+    } else if is_missing_cookie(&err) {
+        // the session cookie is the only required cookie, so it's missing before logging in
+        if wants_html {
+            return Box::new(redirect(Uri::from_static("/login")));
+        }
+        error_status(&Error::NoSessionFoundError)
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "Payload Too Large",
+            "The request body is too large.".to_owned(),
+        )
+    } else if err.find::<warp::reject::LengthRequired>().is_some() {
+        (
+            StatusCode::LENGTH_REQUIRED,
+            "Length Required",
+            "The request needs a Content-Length header.".to_owned(),
+        )
+    } else if err.find::<warp::reject::InvalidQuery>().is_some() {
+        (
+            StatusCode::BAD_REQUEST,
+            "Invalid Query",
+            "The query parameters are invalid.".to_owned(),
+        )
+    } else if err.find::<warp::reject::UnsupportedMediaType>().is_some() {
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Unsupported Media Type",
+            "The request body has an unsupported content type.".to_owned(),
+        )
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        (
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Method Not Allowed",
+            "The request method is not supported here.".to_owned(),
+        )
     } else {
-        eprintln!("unhandled error: {:?}", err);
-        code = StatusCode::INTERNAL_SERVER_ERROR;
-        message = "Internal Server Error";
+        error!("unhandled error: {:?}", err);
+        internal_error()
+    };
+
+    if wants_html {
+        error_page(code, title, &message)
+    } else {
+        json_error(code, title, &message)
     }
+}
 
+/// Checks for a missing cookie, which warp reports as an invalid header if there are no cookies
+/// at all
+fn is_missing_cookie(err: &Rejection) -> bool {
+    err.find::<warp::reject::MissingCookie>().is_some()
+        || err
+            .find::<warp::reject::InvalidHeader>()
+            .is_some_and(|e| e.name() == "cookie")
+}
+
+/// Returns the status, title and description of an application error, the details of internal
+/// errors are only logged
+fn error_status(e: &Error) -> (StatusCode, &'static str, String) {
+    match e {
+        Error::NoEntryFoundError(_) => (
+            StatusCode::NOT_FOUND,
+            "Not Found",
+            "The requested entry does not exist.".to_owned(),
+        ),
+        Error::InvalidIDError(_) => (StatusCode::BAD_REQUEST, "Invalid ID", e.to_string()),
+        Error::InvalidInputError(_) => (StatusCode::BAD_REQUEST, "Invalid Input", e.to_string()),
+        Error::BackupError(_) | Error::ArchiveError(_) => {
+            (StatusCode::BAD_REQUEST, "Invalid Backup", e.to_string())
+        }
+        Error::InvalidCredentials => (
+            StatusCode::UNAUTHORIZED,
+            "Invalid Credentials",
+            "The email or password is wrong.".to_owned(),
+        ),
+        Error::NoSessionFoundError | Error::UnauthorizedError => (
+            StatusCode::UNAUTHORIZED,
+            "Unauthorized",
+            "You need to log in to see this page.".to_owned(),
+        ),
+        Error::InvalidCsrfTokenError => (
+            StatusCode::FORBIDDEN,
+            "Invalid CSRF Token",
+            "The form has expired, please reload the page and try again.".to_owned(),
+        ),
+        Error::EmailTakenError(_) => (
+            StatusCode::CONFLICT,
+            "Email Already Registered",
+            e.to_string(),
+        ),
+        Error::TagExistsError(_) => (StatusCode::CONFLICT, "Tag Already Exists", e.to_string()),
+        Error::MongoError(_)
+        | Error::MongoQueryError(_)
+        | Error::MongoDataError(_)
+        | Error::SqliteError(_)
+        | Error::TemplateError(_)
+        | Error::ReadFileError(_)
+        | Error::CsvError(_)
+        | Error::JsonError(_)
+        | Error::PasswordHashError(_)
+        | Error::CreateSessionError
+        | Error::LogoutError
+        | Error::HeaderError(_) => {
+            error!("unhandled application error: {:?}", e);
+            internal_error()
+        }
+    }
+}
+
+fn internal_error() -> (StatusCode, &'static str, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal Server Error",
+        "Something went wrong, please try again later.".to_owned(),
+    )
+}
+
+/// Renders the error page, falling back to a JSON error if the template fails
+fn error_page(code: StatusCode, title: &str, message: &str) -> Box<dyn Reply> {
+    let template = ErrorTemplate {
+        error: ErrorPage { title, message },
+        csrf_token: "",
+    };
+    match template.render() {
+        Ok(page) => Box::new(reply::with_status(reply::html(page), code)),
+        Err(e) => {
+            error!("could not render error page: {}", e);
+            json_error(code, title, message)
+        }
+    }
+}
+
+fn json_error(code: StatusCode, title: &str, message: &str) -> Box<dyn Reply> {
     let json = reply::json(&ErrorResponse {
-        message: message.into(),
+        message: title,
+        detail: message,
     });
-
-    Ok(Box::new(reply::with_status(json, code)))
+    Box::new(reply::with_status(json, code))
 }
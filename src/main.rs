@@ -6,8 +6,9 @@ use warp::Rejection;
 
 type Result<T> = std::result::Result<T, error::Error>;
 type WebResult<T> = std::result::Result<T, Rejection>;
-type DB = mongodb::Database;
+type DB = std::sync::Arc<dyn db::Store>;
 
+mod api;
 mod app;
 mod data;
 mod db;
@@ -26,11 +27,26 @@ lazy_static! {
 async fn main() -> Result<()> {
     logging::init(&CONFIG.log.level);
 
-    let (db, _client) = db::init().await?;
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|a| a == "--migrate") {
+        let dry_run = args.iter().any(|a| a == "--dry-run");
+        return db::migrate(dry_run).await;
+    }
+    if let Some(i) = args.iter().position(|a| a == "--backup") {
+        return app::backup::backup_command(&args[i + 1..]).await;
+    }
+    if let Some(i) = args.iter().position(|a| a == "--restore") {
+        return app::backup::restore_command(&args[i + 1..]).await;
+    }
 
-    info!("Started on port 8080");
+    let db = db::init().await?;
+    tokio::spawn(db::run_session_cleanup(db.clone()));
+
+    info!("Started on port {}", CONFIG.server.port);
     let routes = routes::router(db);
 
-    warp::serve(routes).run(([0, 0, 0, 0], 8080)).await;
+    warp::serve(routes)
+        .run(([0, 0, 0, 0], CONFIG.server.port))
+        .await;
     Ok(())
 }
@@ -1,10 +1,29 @@
-use crate::data::Session;
-use crate::db::session::find_session;
-use crate::{app, error, web, WebResult, DB};
+use crate::app::{
+    cookie,
+    csrf::{self, CsrfToken, CSRF_COOKIE_NAME},
+};
+use crate::data::{ApiUser, Session};
+use crate::{api, app, error, web, WebResult, DB};
+use bytes::{Buf, Bytes};
+use chrono::{Duration, Utc};
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
 use std::convert::Infallible;
-use warp::{reject, Filter, Rejection};
+use warp::{
+    http::{HeaderMap, Method},
+    multipart::FormData,
+    reject, Filter, Rejection,
+};
 
 const COOKIE_NAME: &str = "toodeloo";
+const SESSION_TOUCH_INTERVAL_SECS: i64 = 60;
+const MAX_JSON_BODY_SIZE: u64 = 1024 * 16;
+const MAX_FORM_BODY_SIZE: u64 = 1024 * 64;
+const MAX_UPLOAD_SIZE: u64 = 1024 * 1024 * 32;
+const AUTHORIZATION: &str = "authorization";
+const BEARER_PREFIX: &str = "Bearer ";
+const CSRF_HEADER: &str = "x-csrf-token";
 
 pub fn router(db: DB) -> impl Filter<Extract = impl warp::Reply, Error = Infallible> + Clone {
     let health_route = warp::path!("health")
@@ -21,23 +40,131 @@ pub fn router(db: DB) -> impl Filter<Extract = impl warp::Reply, Error = Infalli
     let list = warp::path("list");
     let edit = warp::path("edit");
     let delete = warp::path("delete");
+    let search = warp::path("search");
+    let import = warp::path("import");
+    let export_csv = warp::path("export.csv");
+    let export_json = warp::path("export.json");
+    let progress = warp::path("progress");
+    let status = warp::path("status");
+    let review = warp::path("review");
+    let notes = warp::path("notes");
+
+    let restaurants = warp::path("restaurants");
+    let visits = warp::path("visits");
+
+    let recipes = warp::path("recipes");
+    let view = warp::path("view");
+
+    let tags = warp::path("tags");
+    let merge = warp::path("merge");
+    let autocomplete = warp::path("autocomplete");
+
+    let todos = warp::path("todos");
+    let toggle = warp::path("toggle");
+
+    let api_books = warp::path("api")
+        .and(warp::path("v1"))
+        .and(warp::path("books"));
 
     let login = warp::path("login");
     let logout = warp::path("logout");
+    let register = warp::path("register");
+    let account = warp::path("account");
+    let password = warp::path("password");
 
     let auth_routes = login
         .and(warp::get())
         .and_then(app::auth::login_handler)
         .or(login
             .and(warp::post())
-            .and(warp::body::form())
+            .and(with_login_csrf_form())
             .and(with_db(db.clone()))
             .and_then(app::auth::do_login_handler))
         .or(logout
             .and(warp::post())
             .and(with_valid_session(db.clone()))
+            .and(with_csrf())
+            .and(with_db(db.clone()))
+            .and_then(app::auth::logout_handler))
+        .or(register
+            .and(warp::get())
+            .and_then(app::auth::register_handler))
+        .or(register
+            .and(warp::post())
+            .and(with_login_csrf_form())
             .and(with_db(db.clone()))
-            .and_then(app::auth::logout_handler));
+            .and_then(app::auth::do_register_handler));
+
+    let tokens = warp::path("tokens");
+    let revoke = warp::path("revoke");
+    let backup = warp::path("backup");
+    let restore = warp::path("restore");
+
+    let account_routes = account
+        .and(backup)
+        .and(warp::get())
+        .and(with_valid_session(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(app::account::backup_handler)
+        .or(account
+            .and(restore)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(with_csrf_multipart())
+            .and(with_db(db.clone()))
+            .and_then(app::account::restore_handler))
+        .or(account
+            .and(password)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::account::change_password_handler))
+        .or(account
+            .and(delete)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::account::delete_account_handler))
+        .or(account
+            .and(tokens)
+            .and(revoke)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::tokens::confirm_revoke_token_handler))
+        .or(account
+            .and(tokens)
+            .and(revoke)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf())
+            .and(with_db(db.clone()))
+            .and_then(app::tokens::revoke_token_handler))
+        .or(account
+            .and(tokens)
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(with_db(db.clone()))
+            .and_then(app::tokens::tokens_handler))
+        .or(account
+            .and(tokens)
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::tokens::create_token_handler))
+        .or(account
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(with_db(db.clone()))
+            .and_then(app::account::account_handler));
 
     let books_routes = books
         .and(new)
@@ -49,7 +176,7 @@ pub fn router(db: DB) -> impl Filter<Extract = impl warp::Reply, Error = Infalli
             .and(new)
             .and(warp::post())
             .and(with_valid_session(db.clone()))
-            .and(warp::body::form())
+            .and(with_csrf_form())
             .and(with_db(db.clone()))
             .and_then(app::books::create_book_handler))
         .or(books
@@ -64,7 +191,7 @@ pub fn router(db: DB) -> impl Filter<Extract = impl warp::Reply, Error = Infalli
             .and(warp::post())
             .and(with_valid_session(db.clone()))
             .and(warp::path::param())
-            .and(warp::body::form())
+            .and(with_csrf_form())
             .and(with_db(db.clone()))
             .and_then(app::books::do_edit_book_handler))
         .or(books
@@ -73,21 +200,467 @@ pub fn router(db: DB) -> impl Filter<Extract = impl warp::Reply, Error = Infalli
             .and(with_valid_session(db.clone()))
             .and(warp::path::param())
             .and(with_db(db.clone()))
+            .and_then(app::books::confirm_delete_book_handler))
+        .or(books
+            .and(delete)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf())
+            .and(with_db(db.clone()))
             .and_then(app::books::delete_book_handler))
         .or(books
+            .and(review)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::books::review_book_handler))
+        .or(books
+            .and(notes)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(delete)
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::books::confirm_delete_note_handler))
+        .or(books
+            .and(notes)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(delete)
+            .and(warp::path::param())
+            .and(with_csrf())
+            .and(with_db(db.clone()))
+            .and_then(app::books::delete_note_handler))
+        .or(books
+            .and(notes)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::books::add_note_handler))
+        .or(books
+            .and(progress)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(delete)
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::books::confirm_delete_progress_handler))
+        .or(books
+            .and(progress)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(delete)
+            .and(warp::path::param())
+            .and(with_csrf())
+            .and(with_db(db.clone()))
+            .and_then(app::books::delete_progress_handler))
+        .or(books
+            .and(progress)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::books::progress_handler))
+        .or(books
+            .and(progress)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::books::log_progress_handler))
+        .or(books
+            .and(status)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::books::set_status_handler))
+        .or(books
+            .and(export_csv)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(with_db(db.clone()))
+            .and_then(app::import::export_csv_handler))
+        .or(books
+            .and(export_json)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(with_db(db.clone()))
+            .and_then(app::import::export_json_handler))
+        .or(books
+            .and(import)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(with_db(db.clone()))
+            .and_then(app::import::import_handler))
+        .or(books
+            .and(import)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(with_csrf_upload_form())
+            .and(with_db(db.clone()))
+            .and_then(app::import::do_import_handler))
+        .or(books
+            .and(search)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::query())
+            .and(with_db(db.clone()))
+            .and_then(app::books::search_books_handler))
+        .or(books
+            .and(list)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::query())
+            .and(with_db(db.clone()))
+            .and_then(app::books::books_list_handler))
+        .or(books
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(with_db(db.clone()))
+            .and_then(app::books::view_book_handler));
+
+    let restaurants_routes = restaurants
+        .and(new)
+        .and(warp::get())
+        .and(with_valid_session(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(app::restaurants::new_restaurant_handler)
+        .or(restaurants
+            .and(new)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::restaurants::create_restaurant_handler))
+        .or(restaurants
+            .and(edit)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::restaurants::edit_restaurant_handler))
+        .or(restaurants
+            .and(edit)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::restaurants::do_edit_restaurant_handler))
+        .or(restaurants
+            .and(delete)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::restaurants::confirm_delete_restaurant_handler))
+        .or(restaurants
+            .and(delete)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf())
+            .and(with_db(db.clone()))
+            .and_then(app::restaurants::delete_restaurant_handler))
+        .or(restaurants
+            .and(visits)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(delete)
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::restaurants::confirm_delete_visit_handler))
+        .or(restaurants
+            .and(visits)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(delete)
+            .and(warp::path::param())
+            .and(with_csrf())
+            .and(with_db(db.clone()))
+            .and_then(app::restaurants::delete_visit_handler))
+        .or(restaurants
+            .and(visits)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::restaurants::visits_handler))
+        .or(restaurants
+            .and(visits)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::restaurants::add_visit_handler))
+        .or(restaurants
             .and(list)
             .and(warp::get())
             .and(with_valid_session(db.clone()))
             .and(with_db(db.clone()))
-            .and_then(app::books::books_list_handler));
+            .and_then(app::restaurants::restaurants_list_handler));
 
-    welcome_route
+    let recipes_routes = recipes
+        .and(new)
+        .and(warp::get())
+        .and(with_valid_session(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(app::recipes::new_recipe_handler)
+        .or(recipes
+            .and(new)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::recipes::create_recipe_handler))
+        .or(recipes
+            .and(edit)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::recipes::edit_recipe_handler))
+        .or(recipes
+            .and(edit)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::recipes::do_edit_recipe_handler))
+        .or(recipes
+            .and(delete)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::recipes::confirm_delete_recipe_handler))
+        .or(recipes
+            .and(delete)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf())
+            .and(with_db(db.clone()))
+            .and_then(app::recipes::delete_recipe_handler))
+        .or(recipes
+            .and(view)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(warp::query())
+            .and(with_db(db.clone()))
+            .and_then(app::recipes::view_recipe_handler))
+        .or(recipes
+            .and(list)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(with_db(db.clone()))
+            .and_then(app::recipes::recipes_list_handler));
+
+    let todos_routes = todos
+        .and(new)
+        .and(warp::get())
+        .and(with_valid_session(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(app::todos::new_todo_handler)
+        .or(todos
+            .and(new)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::todos::create_todo_handler))
+        .or(todos
+            .and(edit)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::todos::edit_todo_handler))
+        .or(todos
+            .and(edit)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::todos::do_edit_todo_handler))
+        .or(todos
+            .and(toggle)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf())
+            .and(with_db(db.clone()))
+            .and_then(app::todos::toggle_todo_handler))
+        .or(todos
+            .and(delete)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::todos::confirm_delete_todo_handler))
+        .or(todos
+            .and(delete)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf())
+            .and(with_db(db.clone()))
+            .and_then(app::todos::delete_todo_handler))
+        .or(todos
+            .and(list)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::query())
+            .and(with_db(db.clone()))
+            .and_then(app::todos::todos_list_handler));
+
+    let tags_routes = tags
+        .and(new)
+        .and(warp::post())
+        .and(with_valid_session(db.clone()))
+        .and(with_csrf_form())
+        .and(with_db(db.clone()))
+        .and_then(app::tags::create_tag_handler)
+        .or(tags
+            .and(edit)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::tags::edit_tag_handler))
+        .or(tags
+            .and(edit)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::tags::do_edit_tag_handler))
+        .or(tags
+            .and(merge)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf_form())
+            .and(with_db(db.clone()))
+            .and_then(app::tags::merge_tag_handler))
+        .or(tags
+            .and(delete)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_db(db.clone()))
+            .and_then(app::tags::confirm_delete_tag_handler))
+        .or(tags
+            .and(delete)
+            .and(warp::post())
+            .and(with_valid_session(db.clone()))
+            .and(warp::path::param())
+            .and(with_csrf())
+            .and(with_db(db.clone()))
+            .and_then(app::tags::delete_tag_handler))
+        .or(tags
+            .and(autocomplete)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(warp::query())
+            .and(with_db(db.clone()))
+            .and_then(app::tags::autocomplete_handler))
+        .or(tags
+            .and(list)
+            .and(warp::get())
+            .and(with_valid_session(db.clone()))
+            .and(with_db(db.clone()))
+            .and_then(app::tags::tags_list_handler));
+
+    let api_routes = api_books
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_api_auth(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(api::books::list_books_handler)
+        .or(api_books
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(with_api_auth(db.clone()))
+            .and(with_json_body())
+            .and(with_db(db.clone()))
+            .and_then(api::books::create_book_handler))
+        .or(api_books
+            .and(warp::get())
+            .and(with_api_auth(db.clone()))
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(with_db(db.clone()))
+            .and_then(api::books::get_book_handler))
+        .or(api_books
+            .and(warp::patch())
+            .and(with_api_auth(db.clone()))
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(with_json_body())
+            .and(with_db(db.clone()))
+            .and_then(api::books::update_book_handler))
+        .or(api_books
+            .and(warp::delete())
+            .and(with_api_auth(db.clone()))
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(with_db(db.clone()))
+            .and_then(api::books::delete_book_handler));
+
+    let routes = welcome_route
         .or(auth_routes)
+        .or(account_routes)
         .or(metrics_route)
         .or(health_route)
         .or(books_routes)
-        .with(warp::cors().allow_any_origin())
-        .recover(error::handle_rejection)
+        .or(restaurants_routes)
+        .or(recipes_routes)
+        .or(todos_routes)
+        .or(tags_routes)
+        .or(api_routes)
+        .with(warp::cors().allow_any_origin());
+
+    // rejections are turned into replies along with the request headers, since the error reply
+    // depends on the Accept header
+    warp::header::headers_cloned()
+        .and(
+            routes
+                .map(Ok::<_, Rejection>)
+                .or_else(|err| async move { Ok::<_, Infallible>((Err(err),)) }),
+        )
+        .map(reply_or_error)
+}
+
+fn reply_or_error<R: warp::Reply + 'static>(
+    headers: HeaderMap,
+    outcome: Result<R, Rejection>,
+) -> Box<dyn warp::Reply> {
+    match outcome {
+        Ok(reply) => Box::new(reply),
+        Err(err) => error::handle_rejection(err, &headers),
+    }
 }
 
 fn with_db(db: DB) -> impl Filter<Extract = (DB,), Error = Infallible> + Clone {
@@ -97,10 +670,18 @@ fn with_db(db: DB) -> impl Filter<Extract = (DB,), Error = Infallible> + Clone {
 async fn do_stuff(inp: (String, DB)) -> WebResult<Session> {
     let cookie = inp.0;
     let db = inp.1;
-    log::info!("cookie: {}", cookie);
-    let session = find_session(&cookie, &db)
+    let session_id =
+        cookie::verify(&cookie).ok_or_else(|| reject::custom(error::Error::NoSessionFoundError))?;
+    let session = db
+        .find_session(&session_id)
         .await
         .map_err(|_| reject::custom(error::Error::NoSessionFoundError))?;
+    // only slide the expiry every now and then, not on every single request
+    if Utc::now() - session.last_seen_at > Duration::seconds(SESSION_TOUCH_INTERVAL_SECS) {
+        if let Err(e) = db.touch_session(&session).await {
+            log::error!("could not renew session: {}", e);
+        }
+    }
     Ok(session)
 }
 
@@ -109,3 +690,643 @@ fn with_valid_session(db: DB) -> impl Filter<Extract = (Session,), Error = Rejec
         .map(move |cookie: String| (cookie, db.clone()))
         .and_then(do_stuff)
 }
+
+/// Authenticates API requests using an `Authorization: Bearer` token, or the session cookie
+/// as a fallback, rejecting with 401 instead of redirecting to the login page. With the cookie,
+/// requests other than GET and HEAD need the session's CSRF token in an `X-CSRF-Token` header.
+fn with_api_auth(db: DB) -> impl Filter<Extract = (ApiUser,), Error = Rejection> + Clone {
+    warp::header::optional::<String>(AUTHORIZATION)
+        .and(warp::cookie::optional(COOKIE_NAME))
+        .and(warp::method())
+        .and(warp::header::optional::<String>(CSRF_HEADER))
+        .and(with_db(db))
+        .and_then(authenticate_api)
+}
+
+async fn authenticate_api(
+    header: Option<String>,
+    cookie: Option<String>,
+    method: Method,
+    csrf_token: Option<String>,
+    db: DB,
+) -> WebResult<ApiUser> {
+    match (header, cookie) {
+        (Some(header), _) => authenticate_token(&header, &db).await,
+        (None, Some(cookie)) => {
+            let session = do_stuff((cookie, db))
+                .await
+                .map_err(|_| reject::custom(error::Error::UnauthorizedError))?;
+            // browsers send the cookie along with cross-site requests, so changes need the CSRF
+            // token of the session as well
+            let safe = method == Method::GET || method == Method::HEAD;
+            let verified = csrf_token
+                .is_some_and(|token| csrf::verify_session_token(&session.session_id, &token));
+            if !safe && !verified {
+                return Err(reject::custom(error::Error::InvalidCsrfTokenError));
+            }
+            Ok(ApiUser {
+                user_id: session.user_id,
+            })
+        }
+        (None, None) => Err(reject::custom(error::Error::UnauthorizedError)),
+    }
+}
+
+async fn authenticate_token(header: &str, db: &DB) -> WebResult<ApiUser> {
+    let token = header
+        .strip_prefix(BEARER_PREFIX)
+        .map(str::trim)
+        .ok_or_else(|| reject::custom(error::Error::UnauthorizedError))?;
+    let api_token = db
+        .find_token(&app::tokens::hash_token(token))
+        .await
+        .map_err(|_| reject::custom(error::Error::UnauthorizedError))?;
+    if let Err(e) = db.touch_token(&api_token.id).await {
+        log::error!("could not update token usage: {}", e);
+    }
+    Ok(ApiUser {
+        user_id: api_token.user_id,
+    })
+}
+
+fn with_json_body<T: DeserializeOwned + Send>(
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    warp::body::content_length_limit(MAX_JSON_BODY_SIZE).and(warp::body::json())
+}
+
+/// Deserializes a form body, after checking its CSRF token against the session cookie
+fn with_csrf_form<T: DeserializeOwned + Send + 'static>(
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    with_limited_csrf_form(MAX_FORM_BODY_SIZE)
+}
+
+/// Deserializes a form body carrying the contents of an uploaded file, like `with_csrf_form`
+fn with_csrf_upload_form<T: DeserializeOwned + Send + 'static>(
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    with_limited_csrf_form(MAX_UPLOAD_SIZE)
+}
+
+fn with_limited_csrf_form<T: DeserializeOwned + Send + 'static>(
+    limit: u64,
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    warp::cookie(COOKIE_NAME)
+        .and(warp::body::content_length_limit(limit))
+        .and(warp::body::bytes())
+        .and_then(verify_csrf_form::<T>)
+}
+
+/// Checks the CSRF token of a form body, which carries no further data
+fn with_csrf() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    with_csrf_form::<CsrfToken>().map(|_| ()).untuple_one()
+}
+
+/// Deserializes a form body sent without a session, after checking its CSRF token against the
+/// CSRF cookie
+fn with_login_csrf_form<T: DeserializeOwned + Send + 'static>(
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    warp::cookie::optional(CSRF_COOKIE_NAME)
+        .and(warp::body::content_length_limit(MAX_FORM_BODY_SIZE))
+        .and(warp::body::bytes())
+        .and_then(verify_login_csrf_form::<T>)
+}
+
+async fn verify_csrf_form<T: DeserializeOwned>(cookie: String, body: Bytes) -> WebResult<T> {
+    let session_id =
+        cookie::verify(&cookie).ok_or_else(|| reject::custom(error::Error::NoSessionFoundError))?;
+    let token = parse_csrf_token(&body)?;
+    if !csrf::verify_session_token(&session_id, &token.csrf_token) {
+        return Err(reject::custom(error::Error::InvalidCsrfTokenError));
+    }
+    parse_form(&body)
+}
+
+/// Collects the fields of a multipart form body, after checking its CSRF token against the
+/// session cookie
+fn with_csrf_multipart(
+) -> impl Filter<Extract = (HashMap<String, Vec<u8>>,), Error = Rejection> + Clone {
+    warp::cookie(COOKIE_NAME)
+        .and(warp::multipart::form().max_length(MAX_UPLOAD_SIZE))
+        .and_then(verify_csrf_multipart)
+}
+
+async fn verify_csrf_multipart(
+    cookie: String,
+    mut form: FormData,
+) -> WebResult<HashMap<String, Vec<u8>>> {
+    let session_id =
+        cookie::verify(&cookie).ok_or_else(|| reject::custom(error::Error::NoSessionFoundError))?;
+    let mut fields = HashMap::new();
+    while let Some(part) = form.next().await {
+        let mut part =
+            part.map_err(|e| reject::custom(error::Error::InvalidInputError(e.to_string())))?;
+        let mut data = Vec::new();
+        while let Some(chunk) = part.data().await {
+            let chunk = chunk
+                .map_err(|e| reject::custom(error::Error::InvalidInputError(e.to_string())))?;
+            data.extend_from_slice(chunk.bytes());
+        }
+        fields.insert(part.name().to_owned(), data);
+    }
+    let token = fields
+        .get("csrf_token")
+        .and_then(|t| std::str::from_utf8(t).ok())
+        .ok_or_else(|| reject::custom(error::Error::InvalidCsrfTokenError))?;
+    if !csrf::verify_session_token(&session_id, token) {
+        return Err(reject::custom(error::Error::InvalidCsrfTokenError));
+    }
+    Ok(fields)
+}
+
+async fn verify_login_csrf_form<T: DeserializeOwned>(
+    cookie: Option<String>,
+    body: Bytes,
+) -> WebResult<T> {
+    let cookie = cookie.ok_or_else(|| reject::custom(error::Error::InvalidCsrfTokenError))?;
+    let token = parse_csrf_token(&body)?;
+    if !csrf::verify_login_token(&cookie, &token.csrf_token) {
+        return Err(reject::custom(error::Error::InvalidCsrfTokenError));
+    }
+    parse_form(&body)
+}
+
+fn parse_csrf_token(body: &Bytes) -> WebResult<CsrfToken> {
+    serde_urlencoded::from_bytes(body)
+        .map_err(|_| reject::custom(error::Error::InvalidCsrfTokenError))
+}
+
+fn parse_form<T: DeserializeOwned>(body: &Bytes) -> WebResult<T> {
+    serde_urlencoded::from_bytes(body)
+        .map_err(|e| reject::custom(error::Error::InvalidInputError(e.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::books::{BookQuery, NewBook};
+    use crate::app::tags::TagEntry;
+    use crate::db::memory::MemoryStore;
+    use std::sync::Arc;
+    use warp::http::StatusCode;
+    use warp::test::request;
+
+    const TOKEN: &str = "tdl_test";
+
+    async fn store_with_user() -> (DB, Session) {
+        let db: DB = Arc::new(MemoryStore::new());
+        let user_id = db
+            .create_user("reader@example.com", "hash")
+            .await
+            .expect("user is created");
+        let session = db
+            .create_session(&user_id)
+            .await
+            .expect("session is created");
+        (db, session)
+    }
+
+    fn session_cookie(session: &Session) -> String {
+        format!("{}={}", COOKIE_NAME, cookie::sign(&session.session_id))
+    }
+
+    fn book_form(csrf_token: &str, name: &str, pages: &str) -> String {
+        serde_urlencoded::to_string([
+            ("csrf_token", csrf_token),
+            ("name", name),
+            ("author", "Frank Herbert"),
+            ("language", "English"),
+            ("pages", pages),
+            ("tags", ""),
+        ])
+        .expect("form can be encoded")
+    }
+
+    async fn book_count(db: &DB, session: &Session) -> usize {
+        db.fetch_books(&session.user_id, &BookQuery::all())
+            .await
+            .expect("books can be fetched")
+            .len()
+    }
+
+    #[tokio::test]
+    async fn health_is_ok() {
+        let (db, _) = store_with_user().await;
+        let res = request().path("/health").reply(&router(db)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), "OK");
+    }
+
+    #[tokio::test]
+    async fn pages_redirect_to_login_without_session() {
+        let (db, _) = store_with_user().await;
+        let res = request()
+            .path("/books/list")
+            .header("accept", "text/html")
+            .reply(&router(db))
+            .await;
+        assert!(res.status().is_redirection());
+        assert_eq!(res.headers()["location"], "/login");
+    }
+
+    #[tokio::test]
+    async fn book_form_creates_book() {
+        let (db, session) = store_with_user().await;
+        let body = book_form(&csrf::session_token(&session), "Dune", "412");
+        let res = request()
+            .method("POST")
+            .path("/books/new")
+            .header("cookie", session_cookie(&session))
+            .header("accept", "text/html")
+            .body(body)
+            .reply(&router(db.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let books = db
+            .fetch_books(&session.user_id, &BookQuery::all())
+            .await
+            .expect("books can be fetched");
+        assert_eq!(books.len(), 1);
+        assert_eq!(books[0].name, "Dune");
+        assert_eq!(books[0].num_pages, 412);
+    }
+
+    #[tokio::test]
+    async fn invalid_book_form_is_shown_again() {
+        let (db, session) = store_with_user().await;
+        let body = book_form(&csrf::session_token(&session), "Dune", "many");
+        let res = request()
+            .method("POST")
+            .path("/books/new")
+            .header("cookie", session_cookie(&session))
+            .header("accept", "text/html")
+            .body(body)
+            .reply(&router(db.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert!(String::from_utf8_lossy(res.body()).contains("must be a whole number"));
+        assert_eq!(book_count(&db, &session).await, 0);
+    }
+
+    #[tokio::test]
+    async fn forms_with_invalid_csrf_token_are_rejected() {
+        let (db, session) = store_with_user().await;
+        let res = request()
+            .method("POST")
+            .path("/books/new")
+            .header("cookie", session_cookie(&session))
+            .body(book_form("forged", "Dune", "412"))
+            .reply(&router(db.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(book_count(&db, &session).await, 0);
+    }
+
+    #[tokio::test]
+    async fn large_forms_are_rejected() {
+        let (db, session) = store_with_user().await;
+        let name = "a".repeat(MAX_FORM_BODY_SIZE as usize);
+        let body = book_form(&csrf::session_token(&session), &name, "412");
+        let res = request()
+            .method("POST")
+            .path("/books/new")
+            .header("cookie", session_cookie(&session))
+            .body(body)
+            .reply(&router(db.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(book_count(&db, &session).await, 0);
+    }
+
+    #[tokio::test]
+    async fn api_requires_token() {
+        let (db, _) = store_with_user().await;
+        let res = request()
+            .path("/api/v1/books")
+            .header(AUTHORIZATION, "Bearer tdl_unknown")
+            .reply(&router(db))
+            .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            res.body(),
+            r#"{"message":"Unauthorized","detail":"You need to log in to see this page."}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn api_creates_and_lists_books() {
+        let (db, session) = store_with_user().await;
+        db.create_token(&session.user_id, "test", &app::tokens::hash_token(TOKEN))
+            .await
+            .expect("token is created");
+        let auth = format!("{}{}", BEARER_PREFIX, TOKEN);
+
+        let res = request()
+            .method("POST")
+            .path("/api/v1/books")
+            .header(AUTHORIZATION, &auth)
+            .json(&serde_json::json!({
+                "name": "Dune",
+                "author": "Frank Herbert",
+                "language": "English",
+                "pages": 412,
+            }))
+            .reply(&router(db.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let res = request()
+            .path("/api/v1/books")
+            .header(AUTHORIZATION, &auth)
+            .reply(&router(db.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let books: serde_json::Value =
+            serde_json::from_slice(res.body()).expect("response is JSON");
+        assert_eq!(books[0]["name"], "Dune");
+        assert_eq!(books.as_array().map(Vec::len), Some(1));
+    }
+
+    #[tokio::test]
+    async fn api_hides_books_of_other_users() {
+        let (db, session) = store_with_user().await;
+        let other_id = db
+            .create_user("other@example.com", "hash")
+            .await
+            .expect("user is created");
+        db.create_token(&other_id, "test", &app::tokens::hash_token(TOKEN))
+            .await
+            .expect("token is created");
+        let book = NewBook {
+            name: "Dune".to_owned(),
+            author: "Frank Herbert".to_owned(),
+            language: "English".to_owned(),
+            pages: 412,
+        };
+        let id = db
+            .create_book(&book, &session.user_id)
+            .await
+            .expect("book is created");
+
+        let res = request()
+            .path(&format!("/api/v1/books/{}", id))
+            .header(AUTHORIZATION, format!("{}{}", BEARER_PREFIX, TOKEN))
+            .reply(&router(db))
+            .await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn api_accepts_session_cookie_for_reading() {
+        let (db, session) = store_with_user().await;
+        let res = request()
+            .path("/api/v1/books")
+            .header("cookie", session_cookie(&session))
+            .reply(&router(db))
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), "[]");
+    }
+
+    #[tokio::test]
+    async fn api_changes_with_session_cookie_need_csrf_token() {
+        let (db, session) = store_with_user().await;
+        let book = serde_json::json!({
+            "name": "Dune",
+            "author": "Frank Herbert",
+            "language": "English",
+            "pages": 412,
+        });
+
+        let res = request()
+            .method("POST")
+            .path("/api/v1/books")
+            .header("cookie", session_cookie(&session))
+            .body(book.to_string())
+            .reply(&router(db.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(book_count(&db, &session).await, 0);
+
+        let res = request()
+            .method("POST")
+            .path("/api/v1/books")
+            .header("cookie", session_cookie(&session))
+            .header(CSRF_HEADER, csrf::session_token(&session))
+            .json(&book)
+            .reply(&router(db.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(book_count(&db, &session).await, 1);
+    }
+
+    #[tokio::test]
+    async fn password_change_revokes_api_tokens() {
+        let (db, session) = store_with_user().await;
+        let password_hash = bcrypt::hash("old password", 4).expect("password is hashed");
+        db.update_password(&session.user_id, &password_hash)
+            .await
+            .expect("password is updated");
+        db.create_token(&session.user_id, "test", &app::tokens::hash_token(TOKEN))
+            .await
+            .expect("token is created");
+        let body = serde_urlencoded::to_string([
+            ("csrf_token", csrf::session_token(&session).as_str()),
+            ("old_password", "old password"),
+            ("new_password", "new password"),
+            ("new_password_confirmation", "new password"),
+        ])
+        .expect("form can be encoded");
+
+        let res = request()
+            .method("POST")
+            .path("/account/password")
+            .header("cookie", session_cookie(&session))
+            .header("accept", "text/html")
+            .body(body)
+            .reply(&router(db.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let tokens = db
+            .fetch_tokens(&session.user_id)
+            .await
+            .expect("tokens can be fetched");
+        assert!(tokens.is_empty());
+    }
+
+    #[tokio::test]
+    async fn book_list_ignores_invalid_parameters() {
+        let (db, session) = store_with_user().await;
+        let res = request()
+            .path("/books/list?sort=foo&dir=up&page=abc&status=")
+            .header("cookie", session_cookie(&session))
+            .header("accept", "text/html")
+            .reply(&router(db))
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn search_links_to_book_details() {
+        let (db, session) = store_with_user().await;
+        let book = NewBook {
+            name: "Dune".to_owned(),
+            author: "Frank Herbert".to_owned(),
+            language: "English".to_owned(),
+            pages: 412,
+        };
+        let id = db
+            .create_book(&book, &session.user_id)
+            .await
+            .expect("book is created");
+
+        let res = request()
+            .path("/books/search?q=dun")
+            .header("cookie", session_cookie(&session))
+            .header("accept", "text/html")
+            .reply(&router(db))
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = String::from_utf8_lossy(res.body());
+        assert!(body.contains(&format!(r#"books&#x2f;{}"><mark>Dun</mark>e</a>"#, id)));
+    }
+
+    #[tokio::test]
+    async fn invalid_tags_leave_edited_book_unchanged() {
+        let (db, session) = store_with_user().await;
+        let book = NewBook {
+            name: "Dune".to_owned(),
+            author: "Frank Herbert".to_owned(),
+            language: "English".to_owned(),
+            pages: 412,
+        };
+        let id = db
+            .create_book(&book, &session.user_id)
+            .await
+            .expect("book is created");
+        let body = serde_urlencoded::to_string([
+            ("csrf_token", csrf::session_token(&session).as_str()),
+            ("name", "Dune Messiah"),
+            ("author", "Frank Herbert"),
+            ("language", "English"),
+            ("pages", "256"),
+            ("tags", &format!("scifi, {}", "x".repeat(41))),
+        ])
+        .expect("form can be encoded");
+
+        let res = request()
+            .method("POST")
+            .path(&format!("/books/edit/{}", id))
+            .header("cookie", session_cookie(&session))
+            .header("accept", "text/html")
+            .body(body)
+            .reply(&router(db.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let book = db
+            .fetch_book(&id, &session.user_id)
+            .await
+            .expect("book is fetched");
+        assert_eq!(book.name, "Dune");
+        let tags = db
+            .fetch_tags(&session.user_id)
+            .await
+            .expect("tags are fetched");
+        assert!(tags.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tag_names_are_unique_ignoring_case() {
+        let (db, session) = store_with_user().await;
+        let tag = |name: &str| TagEntry {
+            name: name.to_owned(),
+            color: "#888888".to_owned(),
+        };
+        db.create_tag(&tag("SciFi"), &session.user_id)
+            .await
+            .expect("tag is created");
+        let id = db
+            .create_tag(&tag("Fantasy"), &session.user_id)
+            .await
+            .expect("tag is created");
+        let form = |name: &str| {
+            serde_urlencoded::to_string([
+                ("csrf_token", csrf::session_token(&session).as_str()),
+                ("name", name),
+                ("color", "#888888"),
+            ])
+            .expect("form can be encoded")
+        };
+
+        for (path, name) in [
+            ("/tags/new".to_owned(), "scifi"),
+            (format!("/tags/edit/{}", id), "SCIFI"),
+        ] {
+            let res = request()
+                .method("POST")
+                .path(&path)
+                .header("cookie", session_cookie(&session))
+                .header("accept", "text/html")
+                .body(form(name))
+                .reply(&router(db.clone()))
+                .await;
+            assert_eq!(res.status(), StatusCode::CONFLICT);
+        }
+        let names: Vec<String> = db
+            .fetch_tags(&session.user_id)
+            .await
+            .expect("tags are fetched")
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(names, vec!["Fantasy", "SciFi"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_query_is_a_bad_request() {
+        let (db, session) = store_with_user().await;
+        let res = request()
+            .path("/todos/list?filter=bogus")
+            .header("cookie", session_cookie(&session))
+            .reply(&router(db))
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn api_rejects_unsupported_content_type() {
+        let (db, session) = store_with_user().await;
+        db.create_token(&session.user_id, "test", &app::tokens::hash_token(TOKEN))
+            .await
+            .expect("token is created");
+        let res = request()
+            .method("POST")
+            .path("/api/v1/books")
+            .header(AUTHORIZATION, format!("{}{}", BEARER_PREFIX, TOKEN))
+            .header("content-type", "text/plain")
+            .body("Dune")
+            .reply(&router(db))
+            .await;
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn api_errors_include_detail() {
+        let (db, session) = store_with_user().await;
+        db.create_token(&session.user_id, "test", &app::tokens::hash_token(TOKEN))
+            .await
+            .expect("token is created");
+        let res = request()
+            .method("POST")
+            .path("/api/v1/books")
+            .header(AUTHORIZATION, format!("{}{}", BEARER_PREFIX, TOKEN))
+            .json(&serde_json::json!({ "name": "Dune" }))
+            .reply(&router(db))
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let error: serde_json::Value =
+            serde_json::from_slice(res.body()).expect("response is JSON");
+        assert_eq!(error["message"], "Invalid Body");
+        assert!(error["detail"]
+            .as_str()
+            .is_some_and(|d| d.contains("missing field")));
+    }
+}
@@ -4,8 +4,20 @@ use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
 use std::env;
 
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Mongo,
+    Sqlite,
+    Memory,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Database {
+    /// storage backend, `mongo`, `sqlite` or `memory`
+    pub backend: Backend,
+    /// database file, used by the `sqlite` backend
+    pub sqlite_path: String,
     pub host: String,
     pub user: String,
     pub pw: String,
@@ -20,12 +32,38 @@ pub struct Log {
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Server {
-    pub port: usize,
+    pub port: u16,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Session {
+    /// seconds of inactivity after which a session expires
+    pub lifetime_secs: i64,
+    /// seconds after login after which a session expires, regardless of activity
+    pub max_lifetime_secs: i64,
+    pub cleanup_interval_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Cookie {
+    /// secret used for signing session cookies, there is none by default
+    #[serde(default)]
+    pub secret: String,
+    /// secrets, which were used for signing before and are still accepted
+    #[serde(default)]
+    pub previous_secrets: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct App {
     pub init_db: bool,
+    /// apply pending data migrations on startup
+    #[serde(default)]
+    pub run_migrations: bool,
+    pub books_owner: Option<String>,
+    /// user, which is created on init_db, if it doesn't exist yet
+    pub admin_email: Option<String>,
+    pub admin_password: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -33,6 +71,8 @@ pub struct Settings {
     pub server: Server,
     pub db: Database,
     pub log: Log,
+    pub session: Session,
+    pub cookie: Cookie,
     pub app: App,
 }
 
@@ -50,6 +90,38 @@ impl Settings {
 
         s.merge(Environment::with_prefix("tood").separator("__"))?;
 
-        s.try_into()
+        let settings: Settings = s.try_into()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Rejects values, which would make sessions expire immediately or the cleanup task panic,
+    /// and a missing cookie secret, which would make session cookies forgeable
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.cookie.secret.is_empty() {
+            return Err(ConfigError::Message(
+                "cookie.secret must be set, e.g. using TOOD_COOKIE__SECRET".to_owned(),
+            ));
+        }
+        let positive = [
+            ("session.lifetime_secs", self.session.lifetime_secs > 0),
+            (
+                "session.max_lifetime_secs",
+                self.session.max_lifetime_secs > 0,
+            ),
+            (
+                "session.cleanup_interval_secs",
+                self.session.cleanup_interval_secs > 0,
+            ),
+        ];
+        for (key, valid) in positive.iter() {
+            if !valid {
+                return Err(ConfigError::Message(format!(
+                    "{} must be greater than 0",
+                    key
+                )));
+            }
+        }
+        Ok(())
     }
 }
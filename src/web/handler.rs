@@ -1,17 +1,13 @@
 //! Module holding internal handlers for health checks etc.
 
-use crate::{error::Error::*, WebResult, DB};
+use crate::{WebResult, DB};
 use log::error;
 use warp::{reject, Reply};
 
 const HEALTH: &str = "OK";
 
 pub async fn health_handler(db: DB) -> WebResult<impl Reply> {
-    // TODO: better health check for mongo
-    let _ = db
-        .list_collection_names(None)
-        .await
-        .map_err(|e| reject::custom(MongoError(e)))?;
+    db.ping().await.map_err(reject::custom)?;
     Ok(HEALTH)
 }
 